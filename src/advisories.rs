@@ -0,0 +1,159 @@
+//! Finding installed formulae that depend on deprecated or disabled ones.
+use crate::{all_installed, Package, Result};
+use std::collections::{BTreeMap, VecDeque};
+
+/// How far a formula is along the way to removal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Deprecation {
+    /// The formula still installs, but will be disabled.
+    Deprecated,
+    /// The formula can no longer be installed.
+    Disabled,
+}
+
+/// An installed formula that depends on a deprecated or disabled one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Advisory {
+    pub formula: String,
+    /// The deprecated or disabled dependency.
+    pub dependency: String,
+    /// The dependencies leading from `formula` to `dependency`, ending with
+    /// `dependency`. This has one entry for direct dependencies.
+    pub chain: Vec<String>,
+    pub deprecation: Deprecation,
+    /// Why the dependency is deprecated or disabled, such as `unmaintained`.
+    pub reason: Option<String>,
+}
+
+impl Package {
+    /// Whether the formula is deprecated or disabled.
+    pub fn deprecation(&self) -> Option<Deprecation> {
+        if self.disabled {
+            Some(Deprecation::Disabled)
+        } else if self.deprecated {
+            Some(Deprecation::Deprecated)
+        } else {
+            None
+        }
+    }
+}
+
+/// Reports the installed formulae that depend, directly or through other
+/// dependencies, on a deprecated or disabled formula.
+///
+/// There is an advisory for each pair of formula and deprecated dependency,
+/// through the shortest chain of dependencies. Deprecated formulae that
+/// nothing depends on are not reported; check `Package::deprecation` for
+/// those.
+pub fn advisories() -> Result<Vec<Advisory>> {
+    let installed = all_installed()?;
+    let graph: BTreeMap<String, Vec<String>> = installed
+        .values()
+        .map(|p| (p.name.clone(), dependencies(p)))
+        .collect();
+    let flagged: BTreeMap<String, (Deprecation, Option<String>)> = installed
+        .values()
+        .filter_map(|p| {
+            let deprecation = p.deprecation()?;
+            let reason = match deprecation {
+                Deprecation::Disabled => p.disable_reason.clone(),
+                Deprecation::Deprecated => p.deprecation_reason.clone(),
+            };
+            Some((p.name.clone(), (deprecation, reason)))
+        })
+        .collect();
+    Ok(trace(&graph, &flagged))
+}
+
+/// For internal use, the names of the installed dependencies of `package`,
+/// preferring the runtime dependencies recorded when it was installed.
+pub(crate) fn dependencies(package: &Package) -> Vec<String> {
+    let runtime: Vec<String> = package
+        .installed
+        .last()
+        .map(|i| i.runtime_dependencies.iter())
+        .into_iter()
+        .flatten()
+        // Dependencies from other taps are named `user/tap/name`.
+        .map(|d| {
+            d.full_name
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .to_owned()
+        })
+        .collect();
+    if runtime.is_empty() {
+        package.dependencies.clone()
+    } else {
+        runtime
+    }
+}
+
+/// Finds the shortest chain from each formula in `graph` to each `flagged`
+/// formula.
+fn trace(
+    graph: &BTreeMap<String, Vec<String>>,
+    flagged: &BTreeMap<String, (Deprecation, Option<String>)>,
+) -> Vec<Advisory> {
+    let mut advisories = Vec::new();
+    for formula in graph.keys() {
+        // Breadth first, so the first chain found to a dependency is the
+        // shortest.
+        let mut chains: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        let mut queue: VecDeque<&str> = VecDeque::from([formula.as_str()]);
+        while let Some(current) = queue.pop_front() {
+            let chain = chains.get(current).cloned().unwrap_or_default();
+            for dep in graph.get(current).into_iter().flatten() {
+                if dep == formula || chains.contains_key(dep.as_str()) {
+                    continue;
+                }
+                let mut next = chain.clone();
+                next.push(dep.clone());
+                if let Some((deprecation, reason)) = flagged.get(dep) {
+                    advisories.push(Advisory {
+                        formula: formula.clone(),
+                        dependency: dep.clone(),
+                        chain: next.clone(),
+                        deprecation: *deprecation,
+                        reason: reason.clone(),
+                    });
+                }
+                chains.insert(dep, next);
+                queue.push_back(dep);
+            }
+        }
+    }
+    advisories
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traces_deprecated_dependencies() {
+        let graph: BTreeMap<String, Vec<String>> = [
+            ("curl", vec!["openssl@1.1"]),
+            ("wget", vec!["libidn2", "curl"]),
+            ("libidn2", vec![]),
+            ("openssl@1.1", vec![]),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.iter().map(|d| d.to_string()).collect()))
+        .collect();
+        let flagged = vec![(
+            "openssl@1.1".to_owned(),
+            (Deprecation::Disabled, Some("unmaintained".to_owned())),
+        )]
+        .into_iter()
+        .collect();
+        let advisories = trace(&graph, &flagged);
+        assert_eq!(advisories.len(), 2);
+        assert_eq!(advisories[0].formula, "curl");
+        assert_eq!(advisories[0].chain, vec!["openssl@1.1"]);
+        assert_eq!(advisories[1].formula, "wget");
+        assert_eq!(advisories[1].chain, vec!["curl", "openssl@1.1"]);
+        assert_eq!(advisories[1].reason.as_deref(), Some("unmaintained"));
+    }
+}
@@ -0,0 +1,185 @@
+//! Homebrew's own usage analytics, via `brew analytics`, and the install
+//! counts it publishes for each formula.
+use crate::command::brew;
+use crate::{brew_error, json_flag, parse_packages, Analytics, Error, Failure, Package, Result};
+
+/// A period brew reports install counts over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AnalyticsWindow {
+    Days30,
+    Days90,
+    Days365,
+}
+
+impl AnalyticsWindow {
+    pub const ALL: [AnalyticsWindow; 3] = [
+        AnalyticsWindow::Days30,
+        AnalyticsWindow::Days90,
+        AnalyticsWindow::Days365,
+    ];
+
+    /// How brew names the window, such as `30d`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AnalyticsWindow::Days30 => "30d",
+            AnalyticsWindow::Days90 => "90d",
+            AnalyticsWindow::Days365 => "365d",
+        }
+    }
+}
+
+/// The install count of one variant of a formula.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VariantCount {
+    /// The formula, such as `jq`.
+    pub name: String,
+    /// The options it was installed with, such as `--HEAD`.
+    pub options: Vec<String>,
+    pub count: usize,
+}
+
+impl VariantCount {
+    /// The count of `variant`, as brew names it, such as `jq --HEAD`.
+    pub(crate) fn new(variant: &str, count: usize) -> VariantCount {
+        let mut parts = variant.split_whitespace().map(str::to_owned);
+        VariantCount {
+            name: parts.next().unwrap_or_default(),
+            options: parts.collect(),
+            count,
+        }
+    }
+
+    /// Checks if the variant was installed from HEAD.
+    pub fn is_head(&self) -> bool {
+        self.options.iter().any(|o| o == "--HEAD")
+    }
+}
+
+impl Package {
+    /// Fetches the formula's install counts, which brew's info often leaves
+    /// out, such as for formulae read from a cloned tap.
+    ///
+    /// This runs `brew info --analytics`, and with the `download` feature,
+    /// falls back to the JSON API for homebrew/core formulae. Returns `None`
+    /// if there are no counts for the formula, as for third-party taps.
+    pub fn fetch_analytics(&self) -> Result<Option<Analytics>> {
+        let output = brew(&["info", json_flag()?, "--analytics", &self.full_name])?;
+        if !output.success() {
+            return Err(brew_error(&output, Error::UnknownError));
+        }
+        let analytics = parse_packages(output.stdout())?
+            .into_iter()
+            .next()
+            .and_then(|p| p.analytics);
+        #[cfg(feature = "download")]
+        {
+            let core = !matches!(self.tap.as_deref(), Some(t) if t != "homebrew/core");
+            if analytics.is_none() && core {
+                return api_analytics(&self.name);
+            }
+        }
+        Ok(analytics)
+    }
+}
+
+/// Reads the install counts of the homebrew/core formula `name` from the
+/// JSON API.
+#[cfg(feature = "download")]
+fn api_analytics(name: &str) -> Result<Option<Analytics>> {
+    #[derive(serde::Deserialize)]
+    struct Formula {
+        analytics: Option<Analytics>,
+    }
+    let url = format!("{}/formula/{}.json", crate::api_domain(), name);
+    match crate::command::http_get(&url)?.call() {
+        Ok(response) => Ok(serde_json::from_str::<Formula>(&response.into_string()?)?.analytics),
+        Err(ureq::Error::Status(404, _)) => Ok(None),
+        Err(e) => Err(std::io::Error::other(e.to_string()).into()),
+    }
+}
+
+/// Whether brew sends anonymous usage analytics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnalyticsState {
+    Enabled,
+    Disabled,
+}
+
+/// Checks if analytics are enabled, as reported by `brew analytics state`.
+///
+/// This accounts for `HOMEBREW_NO_ANALYTICS` as seen by brew.
+pub fn state() -> Result<AnalyticsState> {
+    let output = brew(&["analytics", "state"])?;
+    if !output.success() {
+        return Err(brew_error(&output, Error::UnknownError));
+    }
+    parse_state(output.stdout()).ok_or_else(|| {
+        Error::UnknownError(Failure {
+            message: format!("Unexpected analytics state: {}", output.stdout().trim()),
+            ..Failure::default()
+        })
+    })
+}
+
+/// Stops brew sending analytics, with `brew analytics off`.
+pub fn turn_off() -> Result<()> {
+    set("off")
+}
+
+/// Lets brew send analytics, with `brew analytics on`.
+pub fn turn_on() -> Result<()> {
+    set("on")
+}
+
+fn set(state: &str) -> Result<()> {
+    let output = brew(&["analytics", state])?;
+    if output.success() {
+        Ok(())
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+fn parse_state(stdout: &str) -> Option<AnalyticsState> {
+    let stdout = stdout.to_lowercase();
+    if stdout.contains("disabled") {
+        Some(AnalyticsState::Disabled)
+    } else if stdout.contains("enabled") {
+        Some(AnalyticsState::Enabled)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_state() {
+        assert_eq!(
+            parse_state("InfluxDB analytics are enabled.\n"),
+            Some(AnalyticsState::Enabled)
+        );
+        assert_eq!(
+            parse_state("Analytics are disabled.\n"),
+            Some(AnalyticsState::Disabled)
+        );
+        assert_eq!(parse_state(""), None);
+    }
+
+    #[test]
+    fn reads_windows() {
+        let analytic: crate::Analytic = serde_json::from_value(serde_json::json!({
+            "30d": { "jq": 10, "jq --HEAD": 2 },
+            "90d": { "jq": 30 },
+            "365d": { "jq": 100, "jq --HEAD": 7 },
+        }))
+        .unwrap();
+        assert_eq!(analytic.total(AnalyticsWindow::Days365), 107);
+        let counts = analytic.variant_counts(AnalyticsWindow::Days30);
+        assert_eq!(counts[0].count, 10);
+        assert!(counts[1].is_head());
+        assert_eq!(counts[1].name, "jq");
+    }
+}
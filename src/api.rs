@@ -0,0 +1,318 @@
+//! Offline access to the formula metadata Homebrew caches from its JSON API.
+//!
+//! Since Homebrew 4, `brew update` downloads every formula's metadata into
+//! `formula.jws.json` under the Homebrew cache, and every cask's into
+//! `cask.jws.json`. Reading them directly is much faster than `brew info
+//! --all`, and works without spawning brew.
+//!
+//! brew checks the files' signatures against Homebrew's public key when it
+//! downloads them. This module does not check them again, so the metadata is
+//! only as trustworthy as the cache directory it is read from.
+use crate::cask::Cask;
+use crate::receipt::Receipt;
+use crate::{Dependency, Error, Installed, Package, Result, Version};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The Homebrew cache directory.
+///
+/// This is the one set with `Brew::cache_dir` for the current client, or
+/// else `HOMEBREW_CACHE`, unless the client is isolated, or else the
+/// platform default that brew would pick. It does not check that the
+/// directory exists.
+pub fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = crate::command::cache_dir() {
+        return Some(dir);
+    }
+    if let Some(dir) = crate::command::inherited_var("HOMEBREW_CACHE") {
+        return Some(dir.into());
+    }
+    let home = PathBuf::from(std::env::var_os("HOME")?);
+    if cfg!(target_os = "macos") {
+        Some(home.join("Library/Caches/Homebrew"))
+    } else {
+        Some(
+            std::env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(".cache"))
+                .join("Homebrew"),
+        )
+    }
+}
+
+/// The path of the cached formula metadata, if the cache directory is known.
+pub fn formula_cache_path() -> Option<PathBuf> {
+    cache_dir().map(|dir| dir.join("api/formula.jws.json"))
+}
+
+/// The path of the cached cask metadata, if the cache directory is known.
+pub fn cask_cache_path() -> Option<PathBuf> {
+    cache_dir().map(|dir| dir.join("api/cask.jws.json"))
+}
+
+/// A JSON Web Signature file, as served by the Homebrew API.
+#[derive(Deserialize)]
+struct Jws {
+    payload: String,
+    signatures: Vec<Signature>,
+}
+
+#[derive(Deserialize)]
+struct Signature {
+    protected: String,
+    signature: String,
+}
+
+/// Reads the payload of a JWS file from the API cache.
+///
+/// The file must be a well formed JWS with at least one signature, but the
+/// signatures are not verified.
+fn read_payload(path: &Path) -> Result<String> {
+    let jws: Jws = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    if jws.signatures.is_empty()
+        || jws
+            .signatures
+            .iter()
+            .any(|s| s.protected.is_empty() || s.signature.is_empty())
+    {
+        return Err(Error::InvalidApiCache(path.to_owned()));
+    }
+    Ok(jws.payload)
+}
+
+/// Reads every formula from a `formula.jws.json` file.
+pub fn read_formulae<P: AsRef<Path>>(path: P) -> Result<HashMap<String, Package>> {
+    let packages: Vec<Package> = serde_json::from_str(&read_payload(path.as_ref())?)?;
+    Ok(packages.into_iter().map(|p| (p.name.clone(), p)).collect())
+}
+
+/// Reads every cask from a `cask.jws.json` file, by token.
+pub fn read_casks<P: AsRef<Path>>(path: P) -> Result<HashMap<String, Cask>> {
+    let casks: Vec<Cask> = serde_json::from_str(&read_payload(path.as_ref())?)?;
+    Ok(casks.into_iter().map(|c| (c.token.clone(), c)).collect())
+}
+
+/// Reads every formula from Homebrew's API cache.
+///
+/// Returns `Ok(None)` if there is no cache, which happens when brew has never
+/// updated or `HOMEBREW_NO_INSTALL_FROM_API` is set.
+pub fn cached_formulae() -> Result<Option<HashMap<String, Package>>> {
+    match formula_cache_path() {
+        Some(path) if path.is_file() => read_formulae(path).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Reads every cask from Homebrew's API cache.
+///
+/// Returns `Ok(None)` if there is no cache, as for `cached_formulae`.
+pub fn cached_casks() -> Result<Option<HashMap<String, Cask>>> {
+    match cask_cache_path() {
+        Some(path) if path.is_file() => read_casks(path).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Returns a map of all packages, preferring Homebrew's API cache.
+///
+/// The cache knows nothing of what is installed, so `installed`,
+/// `linked_keg`, `pinned` and `outdated` are filled in from the kegs in the
+/// Cellar and their receipts. Falls back to `crate::all_packages()` only if
+/// there is no cache; a cache that cannot be read is an error, such as
+/// `Error::InvalidApiCache`.
+pub fn all_packages() -> Result<HashMap<String, Package>> {
+    match cached_formulae()? {
+        Some(mut packages) => {
+            let cellar = crate::cellar()?;
+            let var = crate::prefix()?.join("var/homebrew");
+            overlay_installed(&mut packages, &cellar, &var)?;
+            Ok(packages)
+        }
+        None => crate::all_packages(),
+    }
+}
+
+/// Fills in the install state of `packages` from the kegs in `cellar`, and
+/// the pins and links brew keeps in `var`, its `var/homebrew`.
+fn overlay_installed(
+    packages: &mut HashMap<String, Package>,
+    cellar: &Path,
+    var: &Path,
+) -> Result<()> {
+    if !cellar.is_dir() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(cellar)? {
+        let rack = entry?.path();
+        let name = rack
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let package = match packages.get_mut(&name) {
+            Some(package) => package,
+            None => continue,
+        };
+        let mut kegs = Vec::new();
+        for keg in std::fs::read_dir(&rack)? {
+            let keg = keg?.path();
+            if let Ok(receipt) = Receipt::read(&keg) {
+                let version = keg
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                kegs.push((version, receipt));
+            }
+        }
+        kegs.sort_by_key(|(_, receipt)| receipt.time);
+        package.installed = kegs
+            .into_iter()
+            .map(|(version, receipt)| installed(version, receipt))
+            .collect();
+        package.linked_keg = std::fs::read_link(var.join("linked").join(&name))
+            .ok()
+            .and_then(|keg| Some(keg.file_name()?.to_string_lossy().into_owned()));
+        package.pinned = var.join("pinned").join(&name).exists();
+        package.outdated = outdated(package);
+    }
+    Ok(())
+}
+
+/// What `brew info` reports for a keg of `version` with `receipt`.
+fn installed(version: String, receipt: Receipt) -> Installed {
+    Installed {
+        version: Version { original: version },
+        used_options: receipt.used_options,
+        built_as_bottle: receipt.built_as_bottle,
+        poured_from_bottle: receipt.poured_from_bottle,
+        runtime_dependencies: receipt
+            .runtime_dependencies
+            .unwrap_or_default()
+            .into_iter()
+            .map(|d| Dependency {
+                full_name: d.full_name,
+                version: Version {
+                    original: d.version.unwrap_or_default(),
+                },
+                #[cfg(feature = "preserve-unknown")]
+                extra: Default::default(),
+            })
+            .collect(),
+        installed_as_dependency: receipt.installed_as_dependency,
+        installed_on_request: receipt.installed_on_request,
+        #[cfg(feature = "preserve-unknown")]
+        extra: Default::default(),
+    }
+}
+
+/// Whether no installed keg of `package` is its current stable version.
+///
+/// As with `brew outdated`, HEAD installs are never outdated.
+fn outdated(package: &Package) -> bool {
+    let stable = match &package.versions.stable {
+        Some(stable) => stable.original(),
+        None => return false,
+    };
+    let current = match package.revision {
+        0 => stable.to_owned(),
+        revision => format!("{}_{}", stable, revision),
+    };
+    !package.installed.is_empty()
+        && !package.installed.iter().any(|i| {
+            let version = i.version.original();
+            version == current || version.starts_with("HEAD")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_unsigned() {
+        let path = std::env::temp_dir().join("brew-rs-unsigned.jws.json");
+        std::fs::write(&path, r#"{"payload": "[]", "signatures": []}"#).unwrap();
+        assert!(matches!(
+            read_formulae(&path),
+            Err(Error::InvalidApiCache(_))
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reads_casks() {
+        let path =
+            std::env::temp_dir().join(format!("brew-rs-cask-{}.jws.json", std::process::id()));
+        let payload = serde_json::json!([{
+            "token": "firefox", "full_token": "firefox", "tap": "homebrew/cask",
+            "name": ["Mozilla Firefox"], "desc": "Web browser", "version": "121.0",
+            "installed": null, "artifacts": [{"app": ["Firefox.app"]}],
+        }]);
+        let jws = serde_json::json!({
+            "payload": payload.to_string(),
+            "signatures": [{"protected": "eyJhbGciOiJQUzUxMiJ9", "signature": "c2ln"}],
+        });
+        std::fs::write(&path, jws.to_string()).unwrap();
+        let casks = read_casks(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(casks["firefox"].name, vec!["Mozilla Firefox"]);
+    }
+
+    #[test]
+    fn overlays_install_state() {
+        let root = std::env::temp_dir().join(format!("brew-rs-overlay-{}", std::process::id()));
+        let cellar = root.join("Cellar");
+        let var = root.join("var/homebrew");
+        let receipt = |time: u64| {
+            serde_json::json!({
+                "installed_on_request": true, "poured_from_bottle": true, "time": time,
+                "runtime_dependencies": [{"full_name": "oniguruma", "version": "6.9.9"}],
+            })
+        };
+        for (keg, time) in &[("jq/1.6", 1), ("jq/1.7", 2), ("wget/1.21", 1)] {
+            std::fs::create_dir_all(cellar.join(keg)).unwrap();
+            std::fs::write(
+                cellar.join(keg).join("INSTALL_RECEIPT.json"),
+                receipt(*time).to_string(),
+            )
+            .unwrap();
+        }
+        std::fs::create_dir_all(var.join("pinned")).unwrap();
+        std::fs::write(var.join("pinned/wget"), "").unwrap();
+
+        let mut packages: HashMap<_, _> = [
+            (
+                "jq",
+                serde_json::json!({"versions": {"stable": "1.7", "bottle": true}}),
+            ),
+            (
+                "wget",
+                serde_json::json!({"versions": {"stable": "1.24", "bottle": true}}),
+            ),
+            ("ripgrep", serde_json::json!({})),
+        ]
+        .iter()
+        .map(|(name, fields)| {
+            (
+                name.to_string(),
+                crate::tests::formula(name, fields.clone()),
+            )
+        })
+        .collect();
+        let result = overlay_installed(&mut packages, &cellar, &var);
+        std::fs::remove_dir_all(&root).unwrap();
+        result.unwrap();
+
+        let jq = &packages["jq"];
+        let versions: Vec<_> = jq.installed.iter().map(|i| i.version.original()).collect();
+        assert_eq!(versions, ["1.6", "1.7"]);
+        assert_eq!(
+            jq.installed[1].runtime_dependencies[0].full_name,
+            "oniguruma"
+        );
+        assert!(jq.installed[1].installed_on_request);
+        assert!(!jq.outdated && !jq.pinned);
+        assert!(packages["wget"].outdated && packages["wget"].pinned);
+        assert!(packages["ripgrep"].installed.is_empty());
+    }
+}
@@ -0,0 +1,407 @@
+//! Locating bottle downloads, and verifying where they were built.
+use crate::command::{bottle_domain, github_token, Output, DEFAULT_BOTTLE_DOMAIN};
+use crate::platform::Platform;
+use crate::{Bottle, Error, Package, Result};
+use std::path::Path;
+#[cfg(feature = "download")]
+use std::path::PathBuf;
+
+/// The anonymous bearer token GitHub Packages accepts for public images.
+pub const GHCR_ANONYMOUS_TOKEN: &str = "QQ==";
+
+/// Everything needed to download a bottle file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BottleDownload {
+    pub url: String,
+    pub sha256: String,
+    /// Headers to send with the request.
+    pub headers: Vec<(String, String)>,
+    /// For bottles on GitHub Packages, the endpoint that issues a scoped
+    /// pull token, for registries that reject the anonymous token.
+    pub token_url: Option<String>,
+}
+
+impl Bottle {
+    /// How to download the bottle for `platform`.
+    ///
+    /// Bottles hosted on GitHub Packages (ghcr.io) can't be fetched by
+    /// joining `root_url` and a file name: they are OCI blobs addressed by
+    /// checksum, and need a bearer token even when public.
+    ///
+    /// homebrew/core bottles are downloaded from the mirror set by
+    /// `set_bottle_domain` or `Brew::bottle_domain`, if any.
+    pub fn download_url(&self, platform: &Platform) -> Option<BottleDownload> {
+        let file = self.file_for(platform)?;
+        let mut download = BottleDownload {
+            url: mirrored(&file.url, bottle_domain().as_deref()),
+            sha256: file.sha256.clone(),
+            headers: Vec::new(),
+            token_url: None,
+        };
+        if let Some(repository) = ghcr_repository(&download.url) {
+            download.headers.push((
+                "Authorization".to_owned(),
+                format!("Bearer {}", GHCR_ANONYMOUS_TOKEN),
+            ));
+            download.token_url = Some(format!(
+                "https://ghcr.io/token?service=ghcr.io&scope=repository:{}:pull",
+                repository
+            ));
+        }
+        Some(download)
+    }
+}
+
+impl Bottle {
+    /// The GitHub repository whose workflows build and attest the bottle,
+    /// such as `Homebrew/homebrew-core`, for bottles on GitHub Packages.
+    pub fn attestation_repository(&self) -> Option<String> {
+        let path = self.root_url.strip_prefix("https://ghcr.io/v2/")?;
+        let (user, tap) = path.trim_end_matches('/').split_once('/')?;
+        let user = if user == "homebrew" { "Homebrew" } else { user };
+        Some(format!("{}/homebrew-{}", user, tap))
+    }
+
+    /// Verifies the build provenance attestation of `file`, a download of
+    /// this bottle, with `gh attestation verify`.
+    ///
+    /// This needs the GitHub CLI, which is passed the token from
+    /// `github_token`. Fails with `Error::AttestationFailed` if the
+    /// attestation could not be verified, and with `Error::UnsupportedBottle`
+    /// if the bottle is not on GitHub Packages, where attestations are kept.
+    pub fn verify_attestation<P: AsRef<Path>>(&self, file: P) -> Result<()> {
+        let repository = self.attestation_repository().ok_or_else(|| {
+            Error::UnsupportedBottle(format!("{} has no attestations", self.root_url))
+        })?;
+        let mut command = std::process::Command::new("gh");
+        command
+            .args(["attestation", "verify"])
+            .arg(file.as_ref())
+            .args(["--repo", &repository])
+            .stdin(std::process::Stdio::null());
+        if let Some(token) = github_token() {
+            command.env("GH_TOKEN", token);
+        }
+        let output = command.output()?;
+        let output = Output::new(output.stdout, output.stderr, output.status.into()).ran(&command);
+        if output.success() {
+            Ok(())
+        } else {
+            Err(Error::AttestationFailed(output.failure()))
+        }
+    }
+}
+
+#[cfg(feature = "download")]
+impl Bottle {
+    /// Downloads the bottle for `platform` into `dir`, returning the path of
+    /// the downloaded file, or `None` if there is no bottle for `platform`.
+    ///
+    /// The file is named after its checksum. A partial download left in
+    /// `dir` by an earlier call is resumed, and the file is only moved into
+    /// place once its checksum has been verified.
+    pub fn download_to<P: AsRef<Path>>(
+        &self,
+        platform: &Platform,
+        dir: P,
+    ) -> Result<Option<PathBuf>> {
+        match self.download_url(platform) {
+            Some(download) => {
+                let name = format!("{}.bottle.tar.gz", download.sha256);
+                download.download_to(dir.as_ref().join(name)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl Package {
+    /// How to download the stable bottle for `platform`.
+    pub fn bottle_download(&self, platform: &Platform) -> Option<BottleDownload> {
+        self.bottle.get("stable")?.download_url(platform)
+    }
+
+    /// Downloads the stable bottle for `platform` into `dir`, named as brew
+    /// names it in its cache, such as `jq--1.7.1.arm64_sonoma.bottle.tar.gz`.
+    #[cfg(feature = "download")]
+    pub fn download_bottle_to<P: AsRef<Path>>(
+        &self,
+        platform: &Platform,
+        dir: P,
+    ) -> Result<Option<PathBuf>> {
+        let (bottle, version) = match (self.bottle.get("stable"), &self.versions.stable) {
+            (Some(bottle), Some(version)) => (bottle, version),
+            _ => return Ok(None),
+        };
+        let download = match bottle.download_url(platform) {
+            Some(download) => download,
+            None => return Ok(None),
+        };
+        // The tag of the bottle used, which may be for an older release.
+        let tag = bottle
+            .files
+            .iter()
+            .find(|(_, file)| file.url == download.url)
+            .map_or("all", |(tag, _)| tag.as_str());
+        let rebuild = match bottle.rebuild {
+            0 => String::new(),
+            n => format!(".{}", n),
+        };
+        let name = format!(
+            "{}--{}.{}.bottle{}.tar.gz",
+            self.name,
+            version.original(),
+            tag,
+            rebuild
+        );
+        download.download_to(dir.as_ref().join(name)).map(Some)
+    }
+}
+
+#[cfg(feature = "download")]
+impl BottleDownload {
+    /// Downloads the bottle to `path`, verifying its checksum.
+    ///
+    /// The download is written next to `path` with an `.incomplete` suffix,
+    /// and resumed from there if that file already exists.
+    pub fn download_to<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.download_to_with_progress(path, |_, _| {})
+    }
+
+    /// Like `download_to`, calling `f` with the bytes downloaded so far, and
+    /// the size of the bottle if the server reported it, as they arrive.
+    pub fn download_to_with_progress<P, F>(&self, path: P, mut f: F) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+        F: FnMut(u64, Option<u64>),
+    {
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".incomplete");
+        let partial = PathBuf::from(partial);
+
+        let offset = std::fs::metadata(&partial).map(|m| m.len()).unwrap_or(0);
+        let start = std::time::Instant::now();
+        let mut headers = self.headers.clone();
+        let mut result = self.request(&headers, offset)?.call();
+        if let (Err(ureq::Error::Status(401 | 403, _)), Some(token_url)) =
+            (&result, &self.token_url)
+        {
+            headers.retain(|(name, _)| name != "Authorization");
+            headers.push((
+                "Authorization".to_owned(),
+                format!("Bearer {}", pull_token(token_url)?),
+            ));
+            result = self.request(&headers, offset)?.call();
+        }
+        let spawn = start.elapsed();
+        let downloaded = match result {
+            Ok(response) => {
+                let resumed = response.status() == 206;
+                let start = if resumed { offset } else { 0 };
+                let length = response
+                    .header("Content-Length")
+                    .and_then(|l| l.parse::<u64>().ok());
+                let mut file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .append(resumed)
+                    .truncate(!resumed)
+                    .open(&partial)?;
+                f(start, length.map(|l| start + l));
+                let mut reader = Counting {
+                    inner: response.into_reader(),
+                    read: start,
+                    total: length.map(|l| start + l),
+                    f: &mut f,
+                };
+                std::io::copy(&mut reader, &mut file)
+            }
+            // The partial download is already complete.
+            Err(ureq::Error::Status(416, _)) if offset > 0 => {
+                f(offset, Some(offset));
+                Ok(0)
+            }
+            Err(e) => Err(std::io::Error::other(e.to_string())),
+        };
+        if let Some(metrics) = crate::command::metrics() {
+            metrics.download_finished(&crate::DownloadMetrics {
+                url: self.url.clone(),
+                success: downloaded.is_ok(),
+                timing: crate::Timing {
+                    spawn,
+                    wall: start.elapsed(),
+                    bytes_downloaded: downloaded.as_ref().ok().copied(),
+                },
+            });
+        }
+        downloaded?;
+
+        if sha256_file(&partial)? != self.sha256 {
+            std::fs::remove_file(&partial)?;
+            return Err(Error::ChecksumMismatch(path.to_owned()));
+        }
+        std::fs::rename(&partial, path)?;
+        Ok(path.to_owned())
+    }
+
+    /// The request for the download, with `headers`, starting `offset` bytes
+    /// in.
+    fn request(&self, headers: &[(String, String)], offset: u64) -> Result<ureq::Request> {
+        let mut request = crate::command::http_get(&self.url)?;
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+        if offset > 0 {
+            request = request.set("Range", &format!("bytes={}-", offset));
+        }
+        Ok(request)
+    }
+}
+
+/// Reads `inner`, calling `f` with the bytes read so far, counted from
+/// `read`, and `total`.
+#[cfg(feature = "download")]
+struct Counting<'a, R> {
+    inner: R,
+    read: u64,
+    total: Option<u64>,
+    f: &'a mut dyn FnMut(u64, Option<u64>),
+}
+
+#[cfg(feature = "download")]
+impl<R: std::io::Read> std::io::Read for Counting<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.read += n as u64;
+            (self.f)(self.read, self.total);
+        }
+        Ok(n)
+    }
+}
+
+/// Gets a pull token from the GitHub Packages token endpoint `token_url`,
+/// signing in with `github_token()` if there is one, so that bottles in
+/// private taps can be downloaded.
+#[cfg(feature = "download")]
+fn pull_token(token_url: &str) -> Result<String> {
+    #[derive(serde::Deserialize)]
+    struct Token {
+        token: String,
+    }
+
+    let mut request = crate::command::http_get(token_url)?;
+    if let Some(token) = crate::github_token() {
+        let credentials = base64(format!("brew-rs:{}", token).as_bytes());
+        request = request.set("Authorization", &format!("Basic {}", credentials));
+    }
+    let response = request
+        .call()
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(serde_json::from_str::<Token>(&response.into_string()?)?.token)
+}
+
+/// Encodes `bytes` as padded, standard base64.
+#[cfg(any(feature = "download", test))]
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// The hex encoded SHA-256 checksum of the file at `path`.
+#[cfg(any(feature = "download", feature = "pour", feature = "verify"))]
+pub(crate) fn sha256_file(path: &Path) -> std::io::Result<String> {
+    use sha2::{Digest, Sha256};
+    use std::io::Read;
+
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// `url` on the bottle mirror `domain`, if it is a homebrew/core bottle.
+fn mirrored(url: &str, domain: Option<&str>) -> String {
+    match (domain, url.strip_prefix(DEFAULT_BOTTLE_DOMAIN)) {
+        (Some(domain), Some(path)) => format!("{}{}", domain.trim_end_matches('/'), path),
+        _ => url.to_owned(),
+    }
+}
+
+/// The repository of a ghcr.io blob URL, such as `homebrew/core/jq`.
+fn ghcr_repository(url: &str) -> Option<&str> {
+    let path = url.strip_prefix("https://ghcr.io/v2/")?;
+    Some(&path[..path.find("/blobs/")?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ghcr_urls() {
+        assert_eq!(
+            ghcr_repository("https://ghcr.io/v2/homebrew/core/openssl/3/blobs/sha256:abc"),
+            Some("homebrew/core/openssl/3")
+        );
+        assert_eq!(
+            ghcr_repository("https://homebrew.bintray.com/bottles/jq-1.6.big_sur.bottle.tar.gz"),
+            None
+        );
+    }
+
+    #[test]
+    fn uses_bottle_mirrors() {
+        let url = "https://ghcr.io/v2/homebrew/core/jq/blobs/sha256:abc";
+        assert_eq!(
+            mirrored(url, Some("https://artifactory.example.com/ghcr/")),
+            "https://artifactory.example.com/ghcr/jq/blobs/sha256:abc"
+        );
+        assert_eq!(mirrored(url, None), url);
+        let tap = "https://ghcr.io/v2/user/tap/tool/blobs/sha256:abc";
+        assert_eq!(mirrored(tap, Some("https://mirror.example.com")), tap);
+    }
+
+    #[test]
+    fn finds_attestation_repositories() {
+        let bottle: Bottle = serde_json::from_value(serde_json::json!({
+            "rebuild": 0,
+            "root_url": "https://ghcr.io/v2/homebrew/core",
+            "files": {},
+        }))
+        .unwrap();
+        assert_eq!(
+            bottle.attestation_repository().as_deref(),
+            Some("Homebrew/homebrew-core")
+        );
+    }
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!(base64(b"A"), GHCR_ANONYMOUS_TOKEN);
+        assert_eq!(base64(b"brew"), "YnJldw==");
+        assert_eq!(base64(b"rs:"), "cnM6");
+    }
+}
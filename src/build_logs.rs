@@ -0,0 +1,102 @@
+//! Reading the logs brew writes when building formulae from source.
+use crate::api::cache_dir;
+use crate::{tail_lines, Package, Result};
+use std::path::{Path, PathBuf};
+
+/// The logs of a formula's last build from source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuildLogs {
+    /// The formula's log directory, such as
+    /// `~/Library/Logs/Homebrew/jq`.
+    pub dir: PathBuf,
+    /// Every log file in `dir`, in the order the build steps ran.
+    pub paths: Vec<PathBuf>,
+    /// The log of the last build step, which is the one that failed if the
+    /// build did.
+    pub main: Option<PathBuf>,
+    /// The last lines of `main`.
+    pub tail: Vec<String>,
+}
+
+/// The directory brew writes build logs to.
+///
+/// This respects `HOMEBREW_LOGS`, unless the client is isolated, and
+/// otherwise uses the platform default that brew would pick.
+pub fn logs_dir() -> Option<PathBuf> {
+    if let Some(dir) = crate::command::inherited_var("HOMEBREW_LOGS") {
+        return Some(dir.into());
+    }
+    if cfg!(target_os = "macos") {
+        let home = PathBuf::from(std::env::var_os("HOME")?);
+        Some(home.join("Library/Logs/Homebrew"))
+    } else {
+        cache_dir().map(|dir| dir.join("Logs"))
+    }
+}
+
+impl Package {
+    /// The logs of the package's last build from source, with the last
+    /// `lines` lines of the main log, or `None` if it has no logs.
+    ///
+    /// brew replaces the logs each time it builds the package, so after a
+    /// failed install these hold the compiler's error.
+    pub fn last_build_logs(&self, lines: usize) -> Result<Option<BuildLogs>> {
+        let dir = match logs_dir() {
+            Some(dir) => dir.join(&self.name),
+            None => return Ok(None),
+        };
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_file())
+            .collect();
+        paths.sort();
+        let main = main_log(&paths).map(Path::to_owned);
+        let tail = match &main {
+            Some(main) => tail_lines(main, lines)?,
+            None => Vec::new(),
+        };
+        Ok(Some(BuildLogs {
+            dir,
+            paths,
+            main,
+            tail,
+        }))
+    }
+}
+
+/// The log of the last build step, from logs named like `02.make`.
+///
+/// `00.options.out` only records the options the build used, so is never
+/// the main log.
+fn main_log(paths: &[PathBuf]) -> Option<&Path> {
+    paths
+        .iter()
+        .filter(|p| {
+            let name = p.file_name().unwrap_or_default().to_string_lossy();
+            let step = name.split('.').next().unwrap_or_default();
+            !step.is_empty() && step.bytes().all(|b| b.is_ascii_digit()) && step != "00"
+        })
+        .max_by_key(|p| p.file_name())
+        .map(PathBuf::as_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_main_log() {
+        let paths: Vec<PathBuf> = ["00.options.out", "01.configure", "02.make", "config.log"]
+            .iter()
+            .map(|p| Path::new("/logs/jq").join(p))
+            .collect();
+        assert_eq!(main_log(&paths), Some(Path::new("/logs/jq/02.make")));
+        assert_eq!(main_log(&paths[..1]), None);
+    }
+}
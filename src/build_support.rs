@@ -0,0 +1,191 @@
+//! Building against installed formulae.
+//!
+//! Keg-only formulae, such as openssl@3, are not linked into the prefix, so
+//! compilers and pkg-config will not find them without help.
+//!
+//! `probe` is meant for build scripts of -sys crates:
+//!
+//! ``` no_run
+//! // In build.rs:
+//! let openssl = brew::build_support::probe("openssl@3").unwrap();
+//! // Pass `openssl.include_paths` to the C compiler, such as with
+//! // `cc::Build::includes`.
+//! ```
+use crate::{prefix, Error, Failure, Package, Result};
+use std::path::{Path, PathBuf};
+
+/// The flags needed to build against an installed formula, as suggested in
+/// the caveats of keg-only formulae.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BuildFlags {
+    /// Entries for `LDFLAGS`, such as `-L/opt/homebrew/opt/openssl@3/lib`.
+    pub ldflags: Vec<String>,
+    /// Entries for `CPPFLAGS`, such as
+    /// `-I/opt/homebrew/opt/openssl@3/include`.
+    pub cppflags: Vec<String>,
+    /// Entries for `PKG_CONFIG_PATH`.
+    pub pkg_config_path: Vec<PathBuf>,
+    /// Entries for `PATH`.
+    pub path: Vec<PathBuf>,
+}
+
+impl BuildFlags {
+    /// The flags for the keg or `opt` directory at `prefix`, for the
+    /// directories in it that exist.
+    pub fn for_prefix<P: AsRef<Path>>(prefix: P) -> BuildFlags {
+        let prefix = prefix.as_ref();
+        let mut flags = BuildFlags::default();
+        if prefix.join("lib").is_dir() {
+            flags
+                .ldflags
+                .push(format!("-L{}", prefix.join("lib").display()));
+        }
+        if prefix.join("include").is_dir() {
+            flags
+                .cppflags
+                .push(format!("-I{}", prefix.join("include").display()));
+        }
+        for dir in &["lib/pkgconfig", "share/pkgconfig"] {
+            if prefix.join(dir).is_dir() {
+                flags.pkg_config_path.push(prefix.join(dir));
+            }
+        }
+        for dir in &["bin", "sbin"] {
+            if prefix.join(dir).is_dir() {
+                flags.path.push(prefix.join(dir));
+            }
+        }
+        flags
+    }
+
+    /// Adds the flags from `other`.
+    pub fn extend(&mut self, other: BuildFlags) {
+        self.ldflags.extend(other.ldflags);
+        self.cppflags.extend(other.cppflags);
+        self.pkg_config_path.extend(other.pkg_config_path);
+        self.path.extend(other.path);
+    }
+
+    /// The flags as environment variables, leaving out empty ones.
+    ///
+    /// `PKG_CONFIG_PATH` and `PATH` only hold the new entries, so should be
+    /// prepended to any existing value.
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let join_paths = |paths: &[PathBuf]| {
+            std::env::join_paths(paths)
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        let vars = vec![
+            ("LDFLAGS", self.ldflags.join(" ")),
+            ("CPPFLAGS", self.cppflags.join(" ")),
+            ("PKG_CONFIG_PATH", join_paths(&self.pkg_config_path)),
+            ("PATH", join_paths(&self.path)),
+        ];
+        vars.into_iter().filter(|(_, v)| !v.is_empty()).collect()
+    }
+}
+
+impl Package {
+    /// The flags needed to build against the package.
+    ///
+    /// These point into the package's `opt` directory, so stay valid across
+    /// upgrades. They are only needed for keg-only packages, but are
+    /// returned for any installed package.
+    pub fn build_env(&self) -> Result<BuildFlags> {
+        let opt = self.opt_prefix()?;
+        if !opt.is_dir() {
+            return Err(Error::NotInstalled(Failure::default()));
+        }
+        Ok(BuildFlags::for_prefix(opt))
+    }
+}
+
+/// Where to find the libraries and headers of an installed formula.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Library {
+    pub formula: String,
+    /// The formula's `opt` directory.
+    pub prefix: PathBuf,
+    pub link_paths: Vec<PathBuf>,
+    pub include_paths: Vec<PathBuf>,
+}
+
+/// Finds the libraries and headers of `formula`, failing with
+/// `Error::MissingFormula` if it is not installed.
+///
+/// This does not look up the formula with brew, so is fast enough to call
+/// from build scripts.
+pub fn find(formula: &str) -> Result<Library> {
+    let opt = homebrew_prefix()?.join("opt").join(formula);
+    if !opt.is_dir() {
+        return Err(Error::MissingFormula(formula.to_owned()));
+    }
+    let existing = |dir: &str| Some(opt.join(dir)).filter(|p| p.is_dir());
+    Ok(Library {
+        formula: formula.to_owned(),
+        link_paths: existing("lib").into_iter().collect(),
+        include_paths: existing("include").into_iter().collect(),
+        prefix: opt,
+    })
+}
+
+/// Finds `formula` as `find` does, and prints the `cargo:` instructions to
+/// link against it from a build script.
+///
+/// If the formula is not installed, this prints a warning saying how to
+/// install it before returning the error.
+pub fn probe(formula: &str) -> Result<Library> {
+    let library = match find(formula) {
+        Ok(library) => library,
+        Err(e) => {
+            if let Error::MissingFormula(_) = e {
+                println!(
+                    "cargo:warning=the Homebrew formula {} is not installed, install it with `brew install {}`",
+                    formula, formula
+                );
+            }
+            return Err(e);
+        }
+    };
+    // The opt link changes when the formula is upgraded.
+    println!("cargo:rerun-if-changed={}", library.prefix.display());
+    for path in &library.link_paths {
+        println!("cargo:rustc-link-search=native={}", path.display());
+    }
+    Ok(library)
+}
+
+/// The Homebrew prefix, from `HOMEBREW_PREFIX` if set, since build scripts
+/// are often run from a shell with brew's environment.
+fn homebrew_prefix() -> Result<PathBuf> {
+    match std::env::var_os("HOMEBREW_PREFIX") {
+        Some(prefix) if !prefix.is_empty() => Ok(PathBuf::from(prefix)),
+        _ => prefix(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_build_flags() {
+        let opt = std::env::temp_dir().join("brew-rs-build-flags");
+        std::fs::create_dir_all(opt.join("lib/pkgconfig")).unwrap();
+        std::fs::create_dir_all(opt.join("include")).unwrap();
+        let flags = BuildFlags::for_prefix(&opt);
+        assert_eq!(
+            flags.ldflags,
+            vec![format!("-L{}", opt.join("lib").display())]
+        );
+        assert_eq!(flags.pkg_config_path, vec![opt.join("lib/pkgconfig")]);
+        assert!(flags.path.is_empty());
+        let vars = flags.env_vars();
+        assert_eq!(
+            vars.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec!["LDFLAGS", "CPPFLAGS", "PKG_CONFIG_PATH"]
+        );
+        std::fs::remove_dir_all(opt).unwrap();
+    }
+}
@@ -0,0 +1,135 @@
+//! Opening pull requests to update formulae, via `brew bump-formula-pr`.
+use crate::command::brew;
+use crate::{brew_error, Error, Result};
+
+/// Represents command line options with which to bump a formula.
+#[derive(Clone, Debug, Default)]
+pub struct BumpOptions {
+    url: Option<String>,
+    sha256: Option<String>,
+    version: Option<String>,
+    tag: Option<String>,
+    revision: Option<String>,
+    message: Option<String>,
+    dry_run: bool,
+    no_browse: bool,
+}
+
+impl BumpOptions {
+    /// Represents no options added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the `--url=url` option, the new source URL.
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_owned());
+        self
+    }
+
+    /// Adds the `--sha256=sha256` option, the checksum of the new source.
+    pub fn sha256(mut self, sha256: &str) -> Self {
+        self.sha256 = Some(sha256.to_owned());
+        self
+    }
+
+    /// Adds the `--version=version` option.
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_owned());
+        self
+    }
+
+    /// Adds the `--tag=tag` option, for formulae built from a git tag.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_owned());
+        self
+    }
+
+    /// Adds the `--revision=revision` option, the commit of `tag`.
+    pub fn revision(mut self, revision: &str) -> Self {
+        self.revision = Some(revision.to_owned());
+        self
+    }
+
+    /// Adds the `--message=message` option, added to the pull request
+    /// description.
+    pub fn message(mut self, message: &str) -> Self {
+        self.message = Some(message.to_owned());
+        self
+    }
+
+    /// Adds the `--dry-run` flag.
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
+    /// Adds the `--no-browse` flag.
+    pub fn no_browse(mut self) -> Self {
+        self.no_browse = true;
+        self
+    }
+
+    fn brew_options(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        let values = [
+            ("url", &self.url),
+            ("sha256", &self.sha256),
+            ("version", &self.version),
+            ("tag", &self.tag),
+            ("revision", &self.revision),
+            ("message", &self.message),
+        ];
+        for (name, value) in values.iter() {
+            if let Some(value) = value {
+                out.push(format!("--{}={}", name, value));
+            }
+        }
+        if self.dry_run {
+            out.push("--dry-run".to_owned());
+        }
+        if self.no_browse {
+            out.push("--no-browse".to_owned());
+        }
+        out
+    }
+}
+
+/// Opens a pull request updating `formula`, returning its URL.
+///
+/// Returns `None` for a dry run, or if brew did not print the URL.
+pub fn bump_formula_pr(formula: &str, options: &BumpOptions) -> Result<Option<String>> {
+    let mut args = vec!["bump-formula-pr".to_owned()];
+    args.extend(options.brew_options());
+    args.push(formula.to_owned());
+    let output = brew(&args)?;
+    if output.success() {
+        Ok(pull_request_url(output.stdout()).or_else(|| pull_request_url(output.stderr())))
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+/// Finds the URL of a GitHub pull request in `text`.
+fn pull_request_url(text: &str) -> Option<String> {
+    text.split_whitespace()
+        .find(|w| w.starts_with("https://github.com/") && w.contains("/pull/"))
+        .map(|w| w.trim_end_matches(|c: char| !c.is_ascii_digit()).to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_pull_request_url() {
+        assert_eq!(
+            pull_request_url(
+                "==> Creating pull request\nhttps://github.com/Homebrew/homebrew-core/pull/123456\n"
+            )
+            .as_deref(),
+            Some("https://github.com/Homebrew/homebrew-core/pull/123456")
+        );
+        assert_eq!(pull_request_url("==> replace \"1.0\" with \"1.1\""), None);
+    }
+}
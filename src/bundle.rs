@@ -0,0 +1,600 @@
+//! Reading and writing Brewfiles, as used by `brew bundle`.
+//!
+//! Brewfiles are Ruby, so only the common subset is understood: one
+//! directive per entry (`tap`, `brew`, `cask`, `mas` or `vscode`) with a
+//! quoted name followed by literal arguments. Anything else, such as
+//! comments, blank lines and `cask_args`, is kept verbatim so that
+//! round-tripping a Brewfile does not drop lines.
+//!
+//! ```
+//! use brew::bundle::{Brewfile, Entry, Value};
+//!
+//! let brewfile: Brewfile = r#"
+//! tap "homebrew/cask"
+//! cask "firefox", args: { appdir: "~/Applications" }
+//! mas "Xcode", id: 497799835
+//! "#
+//! .parse()
+//! .unwrap();
+//! let firefox = brewfile.entries().nth(1).unwrap();
+//! assert_eq!(firefox.name, "firefox");
+//! assert_eq!(
+//!     firefox.option("args").unwrap().get("appdir"),
+//!     Some(&Value::String("~/Applications".to_owned()))
+//! );
+//! assert_eq!(brewfile.entries().nth(2).unwrap().mas_id(), Some(497799835));
+//! ```
+use std::fmt;
+use std::str::FromStr;
+
+/// The kinds of Brewfile directive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EntryKind {
+    Tap,
+    Brew,
+    Cask,
+    Mas,
+    Vscode,
+}
+
+impl EntryKind {
+    /// The directive as written in a Brewfile.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntryKind::Tap => "tap",
+            EntryKind::Brew => "brew",
+            EntryKind::Cask => "cask",
+            EntryKind::Mas => "mas",
+            EntryKind::Vscode => "vscode",
+        }
+    }
+
+    fn from_directive(s: &str) -> Option<Self> {
+        Some(match s {
+            "tap" => EntryKind::Tap,
+            "brew" => EntryKind::Brew,
+            "cask" => EntryKind::Cask,
+            "mas" => EntryKind::Mas,
+            "vscode" => EntryKind::Vscode,
+            _ => return None,
+        })
+    }
+}
+
+/// A Ruby literal used as an argument in a Brewfile.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Symbol(String),
+    Integer(i64),
+    Bool(bool),
+    Nil,
+    Array(Vec<Value>),
+    /// A hash, with its keys in the order they were written.
+    Hash(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Looks up `key` if this is a hash.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Hash(pairs) => pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// The string, if this is a string or symbol.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) | Value::Symbol(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::String(s) => {
+                write!(f, "\"")?;
+                let mut chars = s.chars().peekable();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' | '\\' => write!(f, "\\{}", c)?,
+                        // Keep `#{` from being read back as interpolation.
+                        '#' if chars.peek() == Some(&'{') => write!(f, "\\#")?,
+                        '\n' => write!(f, "\\n")?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Value::Symbol(s) => write!(f, ":{}", s),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Nil => write!(f, "nil"),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, "]")
+            }
+            Value::Hash(pairs) => {
+                write!(f, "{{ ")?;
+                write_pairs(f, pairs)?;
+                write!(f, " }}")
+            }
+        }
+    }
+}
+
+fn write_pairs(f: &mut fmt::Formatter, pairs: &[(String, Value)]) -> fmt::Result {
+    for (i, (k, v)) in pairs.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}: {}", k, v)?;
+    }
+    Ok(())
+}
+
+/// A single `tap`, `brew`, `cask`, `mas` or `vscode` directive.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub kind: EntryKind,
+    pub name: String,
+    /// Positional arguments after the name, such as a tap's clone URL.
+    pub args: Vec<Value>,
+    /// Keyword arguments, such as `args:`, `link:` or `id:`.
+    pub options: Vec<(String, Value)>,
+    /// A comment at the end of the line, without the `#`.
+    pub comment: Option<String>,
+}
+
+impl Entry {
+    /// An entry with no arguments.
+    pub fn new(kind: EntryKind, name: &str) -> Self {
+        Entry {
+            kind,
+            name: name.to_owned(),
+            args: Vec::new(),
+            options: Vec::new(),
+            comment: None,
+        }
+    }
+
+    /// A `tap` entry, cloned from `url` if given.
+    pub fn tap(name: &str, url: Option<&str>) -> Self {
+        let mut entry = Self::new(EntryKind::Tap, name);
+        entry.args.extend(url.map(|u| Value::String(u.to_owned())));
+        entry
+    }
+
+    /// A `brew` entry.
+    pub fn brew(name: &str) -> Self {
+        Self::new(EntryKind::Brew, name)
+    }
+
+    /// A `cask` entry.
+    pub fn cask(name: &str) -> Self {
+        Self::new(EntryKind::Cask, name)
+    }
+
+    /// A `mas` entry for the Mac App Store app with `id`.
+    pub fn mas(name: &str, id: u64) -> Self {
+        Self::new(EntryKind::Mas, name).with_option("id", Value::Integer(id as i64))
+    }
+
+    /// A `vscode` extension entry.
+    pub fn vscode(name: &str) -> Self {
+        Self::new(EntryKind::Vscode, name)
+    }
+
+    /// Sets the keyword argument `key`, replacing any existing value.
+    pub fn with_option(mut self, key: &str, value: Value) -> Self {
+        match self.options.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value,
+            None => self.options.push((key.to_owned(), value)),
+        }
+        self
+    }
+
+    /// The keyword argument `key`.
+    pub fn option(&self, key: &str) -> Option<&Value> {
+        self.options.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// The clone URL of a `tap` entry.
+    pub fn tap_url(&self) -> Option<&str> {
+        match self.kind {
+            EntryKind::Tap => self.args.first().and_then(Value::as_str),
+            _ => None,
+        }
+    }
+
+    /// The App Store id of a `mas` entry.
+    pub fn mas_id(&self) -> Option<u64> {
+        match (self.kind, self.option("id")) {
+            (EntryKind::Mas, Some(Value::Integer(id))) => Some(*id as u64),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.kind.as_str(),
+            Value::String(self.name.clone())
+        )?;
+        for arg in &self.args {
+            write!(f, ", {}", arg)?;
+        }
+        if !self.options.is_empty() {
+            write!(f, ", ")?;
+            write_pairs(f, &self.options)?;
+        }
+        if let Some(comment) = &self.comment {
+            write!(f, " #{}", comment)?;
+        }
+        Ok(())
+    }
+}
+
+/// A line of a Brewfile.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Line {
+    Entry(Entry),
+    /// A line that is not a recognized entry, kept verbatim.
+    Other(String),
+}
+
+/// A parsed Brewfile.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Brewfile {
+    pub lines: Vec<Line>,
+}
+
+impl Brewfile {
+    /// An empty Brewfile.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads and parses the Brewfile at `path`.
+    pub fn read<P: AsRef<std::path::Path>>(path: P) -> crate::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(text.parse().unwrap_or_else(|e| match e {}))
+    }
+
+    /// Appends `entry`.
+    pub fn push(&mut self, entry: Entry) {
+        self.lines.push(Line::Entry(entry));
+    }
+
+    /// The recognized entries, in order.
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.lines.iter().filter_map(|l| match l {
+            Line::Entry(e) => Some(e),
+            Line::Other(_) => None,
+        })
+    }
+
+    /// The recognized entries of `kind`, in order.
+    pub fn entries_of(&self, kind: EntryKind) -> impl Iterator<Item = &Entry> {
+        self.entries().filter(move |e| e.kind == kind)
+    }
+}
+
+impl FromStr for Brewfile {
+    type Err = std::convert::Infallible;
+
+    /// Parses a Brewfile. Lines that are not understood are kept as
+    /// `Line::Other`, so this never fails.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = Vec::new();
+        let mut pending = String::new();
+        for line in s.lines() {
+            if !pending.is_empty() {
+                pending.push('\n');
+            }
+            pending.push_str(line);
+            // Entries may span lines while brackets are open.
+            if open_brackets(&pending) > 0 {
+                continue;
+            }
+            lines.extend(parse_lines(&pending));
+            pending.clear();
+        }
+        if !pending.is_empty() {
+            lines.extend(parse_lines(&pending));
+        }
+        Ok(Brewfile { lines })
+    }
+}
+
+fn parse_lines(text: &str) -> Vec<Line> {
+    match parse_entry(text) {
+        Some(entry) => vec![Line::Entry(entry)],
+        None => text
+            .split('\n')
+            .map(|l| Line::Other(l.to_owned()))
+            .collect(),
+    }
+}
+
+impl fmt::Display for Brewfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Entry(e) => writeln!(f, "{}", e)?,
+                Line::Other(s) => writeln!(f, "{}", s)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Counts unclosed brackets outside of strings and comments.
+fn open_brackets(text: &str) -> i32 {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '#') => {
+                // Skip to the end of the line.
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            (None, '[') | (None, '{') | (None, '(') => depth += 1,
+            (None, ']') | (None, '}') | (None, ')') => depth -= 1,
+            _ => {}
+        }
+    }
+    depth
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        match self.rest.strip_prefix(s) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn identifier(&mut self) -> Option<&'a str> {
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest.len());
+        if end == 0 {
+            return None;
+        }
+        let (ident, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Some(ident)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let quote = self
+            .rest
+            .chars()
+            .next()
+            .filter(|&c| c == '"' || c == '\'')?;
+        let mut out = String::new();
+        let mut chars = self.rest[1..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next()?.1 {
+                    'n' if quote == '"' => out.push('\n'),
+                    't' if quote == '"' => out.push('\t'),
+                    c => out.push(c),
+                },
+                c if c == quote => {
+                    self.rest = &self.rest[i + 2..];
+                    return Some(out);
+                }
+                // Interpolation can't be evaluated.
+                '#' if quote == '"' && self.rest[i + 2..].starts_with('{') => return None,
+                c => out.push(c),
+            }
+        }
+        None
+    }
+
+    /// Parses `key:`, `"key" =>` or `:key =>`, restoring the position if
+    /// there is no key.
+    fn key(&mut self) -> Option<String> {
+        let start = self.rest;
+        if let Some(ident) = self.identifier() {
+            if !self.rest.starts_with("::") && self.eat(":") {
+                return Some(ident.to_owned());
+            }
+        } else if let Some(key) = self.string().or_else(|| {
+            if self.eat(":") {
+                self.identifier().map(str::to_owned)
+            } else {
+                None
+            }
+        }) {
+            self.skip_whitespace();
+            if self.eat("=>") {
+                return Some(key);
+            }
+        }
+        self.rest = start;
+        None
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        self.skip_whitespace();
+        let c = self.rest.chars().next()?;
+        match c {
+            '"' | '\'' => self.string().map(Value::String),
+            ':' => {
+                self.eat(":");
+                self.identifier().map(|s| Value::Symbol(s.to_owned()))
+            }
+            '[' => {
+                self.eat("[");
+                let mut values = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.eat("]") {
+                        return Some(Value::Array(values));
+                    }
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    if !self.eat(",") && !self.rest.starts_with(']') {
+                        return None;
+                    }
+                }
+            }
+            '{' => {
+                self.eat("{");
+                let mut pairs = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.eat("}") {
+                        return Some(Value::Hash(pairs));
+                    }
+                    let key = self.key()?;
+                    pairs.push((key, self.value()?));
+                    self.skip_whitespace();
+                    if !self.eat(",") && !self.rest.starts_with('}') {
+                        return None;
+                    }
+                }
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let end = self.rest[1..]
+                    .find(|c: char| !(c.is_ascii_digit() || c == '_'))
+                    .map_or(self.rest.len(), |i| i + 1);
+                let value = self.rest[..end].replace('_', "").parse().ok()?;
+                self.rest = &self.rest[end..];
+                Some(Value::Integer(value))
+            }
+            _ => match self.identifier()? {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                "nil" => Some(Value::Nil),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Parses a single entry, or returns `None` if `text` is not one.
+fn parse_entry(text: &str) -> Option<Entry> {
+    let mut parser = Parser {
+        rest: text.trim_start(),
+    };
+    let kind = EntryKind::from_directive(parser.identifier()?)?;
+    if !parser.rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    parser.skip_whitespace();
+    let name = parser.string()?;
+    let mut entry = Entry::new(kind, &name);
+    loop {
+        parser.skip_whitespace();
+        if parser.rest.is_empty() {
+            return Some(entry);
+        }
+        if let Some(comment) = parser.rest.strip_prefix('#') {
+            entry.comment = Some(comment.to_owned());
+            return Some(entry);
+        }
+        if !parser.eat(",") {
+            return None;
+        }
+        parser.skip_whitespace();
+        match parser.key() {
+            Some(key) => {
+                let value = parser.value()?;
+                entry.options.push((key, value));
+            }
+            None if entry.options.is_empty() => entry.args.push(parser.value()?),
+            None => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let text = r#"# Taps
+tap "homebrew/cask"
+tap "user/tap", "https://gitlab.com/user/homebrew-tap.git"
+cask_args appdir: "/Applications"
+
+brew "mysql@5.6", restart_service: true, link: true, conflicts_with: ["mysql"]
+brew "denji/nginx/nginx-full", args: ["with-rmtp"] # needs rtmp
+cask "firefox", args: { appdir: "~/my-apps/Applications" }
+mas "1Password", id: 443987910
+vscode "GitHub.codespaces"
+"#;
+        let brewfile: Brewfile = text.parse().unwrap();
+        assert_eq!(brewfile.to_string(), text);
+        assert_eq!(brewfile.entries().count(), 7);
+        assert_eq!(
+            brewfile
+                .entries_of(EntryKind::Tap)
+                .nth(1)
+                .unwrap()
+                .tap_url(),
+            Some("https://gitlab.com/user/homebrew-tap.git")
+        );
+        assert_eq!(
+            brewfile.lines[3],
+            Line::Other("cask_args appdir: \"/Applications\"".to_owned())
+        );
+    }
+
+    #[test]
+    fn multi_line_entries() {
+        let brewfile: Brewfile = "cask \"firefox\", args: {\n  appdir: \"~/Apps\",\n}\n"
+            .parse()
+            .unwrap();
+        let firefox = brewfile.entries().next().unwrap();
+        assert_eq!(
+            firefox
+                .option("args")
+                .unwrap()
+                .get("appdir")
+                .unwrap()
+                .as_str(),
+            Some("~/Apps")
+        );
+    }
+}
@@ -0,0 +1,663 @@
+//! Homebrew casks, and treating them alongside formulae.
+//!
+//! ``` no_run
+//! # fn main() -> brew::Result<()> {
+//! use brew::bundle::Brewfile;
+//! use brew::cask::AnyPackage;
+//!
+//! for entry in Brewfile::read("Brewfile")?.entries() {
+//!     if let Some(package) = AnyPackage::from_entry(entry)? {
+//!         if !package.is_installed() {
+//!             package.install(&brew::Options::new())?;
+//!         }
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::bundle::{Entry, EntryKind};
+use crate::command::brew;
+use crate::outdated::{parse_outdated, OutdatedPackage};
+use crate::{brew_error, parse_packages, shell_words, Error, Failure, Options, Package, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Represents a Homebrew cask, which may or may not be installed.
+///
+/// Casks have many more fields than are kept here, so the `strict-schema`
+/// feature does not apply to them.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Cask {
+    pub token: String,
+    /// The token, with the tap for casks outside homebrew/cask.
+    pub full_token: String,
+    pub tap: Option<String>,
+    /// The names of the app, such as `Firefox`.
+    #[serde(default)]
+    pub name: Vec<String>,
+    pub desc: Option<String>,
+    pub homepage: Option<String>,
+    pub url: Option<String>,
+    pub version: String,
+    /// The installed version, if the cask is installed.
+    pub installed: Option<String>,
+    #[serde(default)]
+    pub outdated: bool,
+    /// Whether the app updates itself.
+    pub auto_updates: Option<bool>,
+    #[serde(default)]
+    pub deprecated: bool,
+    #[serde(default)]
+    pub disabled: bool,
+    /// What the cask installs and removes, such as `{"app": ["Firefox.app"]}`,
+    /// as brew reports it.
+    #[serde(default)]
+    pub artifacts: Vec<serde_json::Value>,
+    /// The formulae and casks the cask needs installed.
+    #[serde(default)]
+    pub depends_on: CaskDependencies,
+    #[cfg(feature = "preserve-unknown")]
+    /// Unknown fields, as for `Package::extra`.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// The formulae and casks a cask depends on. Its other requirements, such
+/// as the macOS version, are not kept.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CaskDependencies {
+    #[serde(default)]
+    pub formula: Vec<String>,
+    #[serde(default)]
+    pub cask: Vec<String>,
+}
+
+impl Cask {
+    /// Creates a cask, filling out the struct from `brew info`.
+    pub fn new(token: &str) -> Result<Cask> {
+        let output = brew(&["info", "--json=v2", "--cask", token])?;
+        if output.success() {
+            parse_casks(output.stdout())?
+                .into_iter()
+                .next()
+                .ok_or_else(|| Error::PackageNotFound(Failure::default()))
+        } else {
+            Err(brew_error(&output, Error::PackageNotFound))
+        }
+    }
+
+    /// Fetches the cask's info again, updating it in place.
+    pub fn refresh(&mut self) -> Result<()> {
+        *self = Self::new(&self.full_token)?;
+        Ok(())
+    }
+
+    /// Check if the cask is installed.
+    pub fn is_installed(&self) -> bool {
+        self.installed.is_some()
+    }
+
+    /// Installs the cask, doing nothing if it is already installed.
+    pub fn install(&self, options: &CaskOptions) -> Result<Cask> {
+        if self.is_installed() {
+            return Self::new(&self.full_token);
+        }
+        let mut args = vec!["install".to_owned(), "--cask".to_owned()];
+        args.extend(options.brew_options());
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        self.run(&args, Error::InstallFailed)
+    }
+
+    /// Uninstalls the cask.
+    pub fn uninstall(&self) -> Result<Cask> {
+        self.run(&["uninstall", "--cask"], Error::UnknownError)
+    }
+
+    /// Whether brew only upgrades the cask when asked to be greedy, because
+    /// the app updates itself or has no version (`version :latest`).
+    pub fn needs_greedy(&self) -> bool {
+        self.auto_updates == Some(true) || self.version == "latest"
+    }
+
+    /// Upgrades the cask. Unless `greedy`, casks for which `needs_greedy`
+    /// is true are left as they are.
+    pub fn upgrade(&self, greedy: bool) -> Result<Cask> {
+        self.upgrade_with(greedy, &CaskOptions::new())
+    }
+
+    /// Upgrades the cask as `upgrade` does, with `options`.
+    pub fn upgrade_with(&self, greedy: bool, options: &CaskOptions) -> Result<Cask> {
+        if !self.is_installed() {
+            return Err(Error::NotInstalled(Failure::default()));
+        }
+        if !greedy && self.needs_greedy() {
+            return Ok(self.clone());
+        }
+        let mut args = vec!["upgrade".to_owned(), "--cask".to_owned()];
+        if greedy {
+            args.push("--greedy".to_owned());
+        }
+        args.extend(options.brew_options());
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        self.run(&args, Error::UnknownError)
+    }
+
+    /// Runs brew with `args` and the cask's token, returning the cask as it
+    /// is afterwards.
+    fn run(&self, args: &[&str], error: fn(crate::Failure) -> Error) -> Result<Cask> {
+        let mut args = args.to_vec();
+        args.push(&self.full_token);
+        let output = brew(&args)?;
+        if output.success() {
+            Self::new(&self.full_token)
+        } else {
+            Err(brew_error(&output, error))
+        }
+    }
+}
+
+/// Represents command line options with which to install a cask.
+#[derive(Clone, Debug, Default)]
+pub struct CaskOptions {
+    appdir: Option<PathBuf>,
+    languages: Vec<String>,
+    require_sha: bool,
+    skip_cask_deps: bool,
+    no_quarantine: bool,
+}
+
+impl CaskOptions {
+    /// Represents no options added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the `--appdir=dir` option, installing apps into `dir` instead of
+    /// `/Applications`.
+    pub fn appdir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.appdir = Some(dir.into());
+        self
+    }
+
+    /// Adds a language to the `--language` option, such as `de`, for casks
+    /// with localized downloads. The first language given is preferred.
+    pub fn language(mut self, language: &str) -> Self {
+        self.languages.push(language.to_owned());
+        self
+    }
+
+    /// Adds the `--require-sha` flag, refusing casks without a checksum.
+    pub fn require_sha(mut self) -> Self {
+        self.require_sha = true;
+        self
+    }
+
+    /// Adds the `--skip-cask-deps` flag.
+    pub fn skip_cask_deps(mut self) -> Self {
+        self.skip_cask_deps = true;
+        self
+    }
+
+    /// Adds the `--no-quarantine` flag, so macOS does not ask before the app
+    /// is first opened. Only use this for apps that have been vetted some
+    /// other way, as it skips Gatekeeper's check.
+    pub fn no_quarantine(mut self) -> Self {
+        self.no_quarantine = true;
+        self
+    }
+
+    /// The options as a value for `HOMEBREW_CASK_OPTS`, for commands that
+    /// install casks without taking these flags, such as `brew bundle`.
+    ///
+    /// brew splits the value as a shell would, so options with spaces or
+    /// quotes in them, such as an appdir of `~/My Apps`, are quoted.
+    pub fn cask_opts(&self) -> String {
+        shell_words(&self.brew_options())
+    }
+
+    fn brew_options(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        if let Some(dir) = &self.appdir {
+            out.push(format!("--appdir={}", dir.display()));
+        }
+        if !self.languages.is_empty() {
+            out.push(format!("--language={}", self.languages.join(",")));
+        }
+        if self.require_sha {
+            out.push("--require-sha".to_owned());
+        }
+        if self.skip_cask_deps {
+            out.push("--skip-cask-deps".to_owned());
+        }
+        if self.no_quarantine {
+            out.push("--no-quarantine".to_owned());
+        }
+        out
+    }
+}
+
+/// Upgrades every outdated cask, returning those upgraded. Unless `greedy`,
+/// casks that update themselves or have no version are left out, as by
+/// `brew upgrade --cask`.
+pub fn upgrade_all_casks(greedy: bool) -> Result<Vec<OutdatedPackage>> {
+    upgrade_all_casks_with(greedy, &CaskOptions::new())
+}
+
+/// Upgrades every outdated cask as `upgrade_all_casks` does, with `options`.
+pub fn upgrade_all_casks_with(greedy: bool, options: &CaskOptions) -> Result<Vec<OutdatedPackage>> {
+    let flags: &[&str] = if greedy {
+        &["--cask", "--greedy"]
+    } else {
+        &["--cask"]
+    };
+    let output = brew(&[&["outdated", "--json=v2"], flags].concat())?;
+    if !output.success() {
+        return Err(brew_error(&output, Error::UnknownError));
+    }
+    let outdated = parse_outdated(output.stdout())?;
+    if outdated.is_empty() {
+        return Ok(outdated);
+    }
+    let options = options.brew_options();
+    let mut args = [&["upgrade"], flags].concat();
+    args.extend(options.iter().map(String::as_str));
+    args.extend(outdated.iter().map(|c| c.name.as_str()));
+    let output = brew(&args)?;
+    if output.success() {
+        Ok(outdated)
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+/// What `brew uninstall --zap` removes for a cask, from its `zap` stanza.
+///
+/// Paths may start with `~` and use `*` wildcards.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Zap {
+    /// Paths moved to the trash.
+    pub trash: Vec<String>,
+    /// Paths deleted outright.
+    pub delete: Vec<String>,
+    /// Directories removed if they are empty.
+    pub rmdir: Vec<String>,
+    /// Package receipt ids forgotten with `pkgutil --forget`.
+    pub pkgutil: Vec<String>,
+    /// launchd job labels removed.
+    pub launchctl: Vec<String>,
+    /// Bundle ids of apps quit first.
+    pub quit: Vec<String>,
+    /// Login items removed.
+    pub login_item: Vec<String>,
+    /// Kernel extension ids unloaded.
+    pub kext: Vec<String>,
+}
+
+impl Zap {
+    /// The paths trashed, deleted, or removed if empty.
+    pub fn paths(&self) -> impl Iterator<Item = &String> {
+        self.trash.iter().chain(&self.delete).chain(&self.rmdir)
+    }
+}
+
+impl Cask {
+    /// The cask's `zap` stanza, or `None` if it has none.
+    pub fn zap(&self) -> Option<Zap> {
+        let mut zap: Option<Zap> = None;
+        let directives = self.artifacts.iter().filter_map(|a| a.get("zap"));
+        for directive in directives.flat_map(|d| d.as_array().cloned().unwrap_or_default()) {
+            let zap = zap.get_or_insert_with(Zap::default);
+            zap.trash.extend(strings(directive.get("trash")));
+            zap.delete.extend(strings(directive.get("delete")));
+            zap.rmdir.extend(strings(directive.get("rmdir")));
+            zap.pkgutil.extend(strings(directive.get("pkgutil")));
+            zap.launchctl.extend(strings(directive.get("launchctl")));
+            zap.quit.extend(strings(directive.get("quit")));
+            zap.login_item.extend(strings(directive.get("login_item")));
+            zap.kext.extend(strings(directive.get("kext")));
+        }
+        zap
+    }
+}
+
+/// For internal use, the strings in `value`, which brew writes as a string
+/// or an array whose strings come first, followed by options such as
+/// `{"target": ...}`.
+pub(crate) fn strings(value: Option<&serde_json::Value>) -> Vec<String> {
+    match value {
+        Some(serde_json::Value::String(s)) => vec![s.clone()],
+        Some(serde_json::Value::Array(values)) => values
+            .iter()
+            .filter_map(|v| v.as_str().map(str::to_owned))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// For internal use, gets the info of each of `tokens` at once.
+pub(crate) fn casks(tokens: &[&str]) -> Result<Vec<Cask>> {
+    let mut args = vec!["info", "--json=v2", "--cask"];
+    args.extend(tokens);
+    let output = brew(&args)?;
+    if output.success() {
+        parse_casks(output.stdout())
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+/// Parses the casks from `brew info --json=v2 --cask` output.
+fn parse_casks(json: &str) -> Result<Vec<Cask>> {
+    #[derive(Deserialize)]
+    struct V2 {
+        casks: Vec<Cask>,
+    }
+    Ok(serde_json::from_str::<V2>(json)?.casks)
+}
+
+/// Looks up `name`, which may be a formula or a cask, with `brew info
+/// --json=v2`.
+///
+/// brew refuses `--formula` and `--cask` together, so it is asked for each
+/// in turn. A name that is both, such as `docker`, fails with
+/// `Error::AmbiguousName`; use `Package::new` or `Cask::new` for the one
+/// meant.
+pub fn info(name: &str) -> Result<AnyPackage> {
+    let formula = lookup(&["info", "--json=v2", "--formula", name])?;
+    let cask = lookup(&["info", "--json=v2", "--cask", name])?;
+    match (formula, cask) {
+        (Ok(_), Ok(_)) => Err(Error::AmbiguousName(name.to_owned())),
+        (Ok(json), Err(_)) | (Err(_), Ok(json)) => parse_any(&json),
+        (Err(failure), Err(_)) => Err(Error::PackageNotFound(failure)),
+    }
+}
+
+/// Runs `brew info` with `args`, returning what it printed, or how it failed
+/// if brew did not find the package.
+fn lookup(args: &[&str]) -> Result<std::result::Result<String, Failure>> {
+    let output = brew(args)?;
+    if output.success() {
+        return Ok(Ok(output.stdout().to_owned()));
+    }
+    match brew_error(&output, Error::UnknownError) {
+        Error::PackageNotFound(failure) => Ok(Err(failure)),
+        e => Err(e),
+    }
+}
+
+/// Parses the formula or cask from `brew info --json=v2` output, preferring
+/// the formula.
+fn parse_any(json: &str) -> Result<AnyPackage> {
+    if let Some(package) = parse_packages(json)?.into_iter().next() {
+        return Ok(package.into());
+    }
+    parse_casks(json)?
+        .into_iter()
+        .next()
+        .map(AnyPackage::from)
+        .ok_or_else(|| Error::PackageNotFound(Failure::default()))
+}
+
+/// A formula or a cask.
+#[derive(Clone, Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum AnyPackage {
+    Formula(Package),
+    Cask(Cask),
+}
+
+impl AnyPackage {
+    /// Looks up the formula or cask of a Brewfile entry. Entries that are
+    /// not `brew` or `cask` have no package.
+    pub fn from_entry(entry: &Entry) -> Result<Option<AnyPackage>> {
+        Ok(match entry.kind {
+            EntryKind::Brew => Some(Package::new(&entry.name)?.into()),
+            EntryKind::Cask => Some(Cask::new(&entry.name)?.into()),
+            _ => None,
+        })
+    }
+
+    /// The name of a formula, or the token of a cask.
+    pub fn name(&self) -> &str {
+        match self {
+            AnyPackage::Formula(package) => &package.name,
+            AnyPackage::Cask(cask) => &cask.token,
+        }
+    }
+
+    pub fn is_installed(&self) -> bool {
+        match self {
+            AnyPackage::Formula(package) => package.is_installed(),
+            AnyPackage::Cask(cask) => cask.is_installed(),
+        }
+    }
+
+    /// Installs the package, as `Package::install` or `Cask::install`.
+    /// `options` only apply to formulae.
+    pub fn install(&self, options: &Options) -> Result<AnyPackage> {
+        Ok(match self {
+            AnyPackage::Formula(package) => package.install(options)?.into(),
+            AnyPackage::Cask(cask) => cask.install(&CaskOptions::new())?.into(),
+        })
+    }
+
+    /// Uninstalls the package, leaving formulae that depend on it alone.
+    pub fn uninstall(&self) -> Result<AnyPackage> {
+        Ok(match self {
+            AnyPackage::Formula(package) => package.uninstall(false, false)?.into(),
+            AnyPackage::Cask(cask) => cask.uninstall()?.into(),
+        })
+    }
+}
+
+impl From<Package> for AnyPackage {
+    fn from(package: Package) -> Self {
+        AnyPackage::Formula(package)
+    }
+}
+
+impl From<Cask> for AnyPackage {
+    fn from(cask: Cask) -> Self {
+        AnyPackage::Cask(cask)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_casks() {
+        let casks = parse_casks(
+            r#"{"formulae": [], "casks": [{
+                "token": "firefox", "full_token": "firefox", "tap": "homebrew/cask",
+                "name": ["Mozilla Firefox"], "desc": "Web browser",
+                "homepage": "https://www.mozilla.org/firefox/",
+                "url": "https://download-installer.cdn.mozilla.net/firefox.dmg",
+                "version": "121.0", "installed": null, "outdated": false,
+                "auto_updates": true, "artifacts": [{"app": ["Firefox.app"]}]
+            }]}"#,
+        )
+        .unwrap();
+        let firefox = AnyPackage::from(casks[0].clone());
+        assert_eq!(firefox.name(), "firefox");
+        assert!(!firefox.is_installed());
+    }
+
+    #[test]
+    fn parses_any() {
+        let jq = crate::tests::formula_json("jq", serde_json::json!({}));
+        let firefox = serde_json::json!({
+            "token": "firefox", "full_token": "firefox", "version": "121.0", "installed": null,
+        });
+        assert!(matches!(
+            parse_any(&serde_json::json!({"formulae": [], "casks": [firefox]}).to_string()),
+            Ok(AnyPackage::Cask(cask)) if cask.token == "firefox"
+        ));
+        assert!(matches!(
+            parse_any(&serde_json::json!({"formulae": [jq], "casks": []}).to_string()),
+            Ok(AnyPackage::Formula(package)) if package.name == "jq"
+        ));
+        assert!(matches!(
+            parse_any(r#"{"formulae": [], "casks": []}"#),
+            Err(Error::PackageNotFound(_))
+        ));
+    }
+
+    #[cfg(feature = "fixtures")]
+    #[test]
+    fn looks_up_formulae_and_casks() {
+        use crate::tests::recording;
+        use serde_json::json;
+
+        let not_found = |args: &[&str], stderr: &str| crate::fixtures::Recording {
+            stderr: stderr.to_owned(),
+            status: crate::ExitStatus {
+                code: Some(1),
+                signal: None,
+            },
+            ..recording(args, "")
+        };
+        let docker = crate::tests::formula_json("docker", json!({}));
+        let cask = |token: &str| {
+            json!({"formulae": [], "casks": [{
+                "token": token, "full_token": token, "version": "1.0", "installed": null,
+            }]})
+            .to_string()
+        };
+        let _guard = crate::fixtures::replay_recordings(vec![
+            recording(&["--version"], "Homebrew 4.2.0"),
+            recording(
+                &["info", "--json=v2", "--formula", "docker"],
+                &json!({"formulae": [docker], "casks": []}).to_string(),
+            ),
+            recording(&["info", "--json=v2", "--cask", "docker"], &cask("docker")),
+            not_found(
+                &["info", "--json=v2", "--formula", "firefox"],
+                "Error: No available formula with the name \"firefox\".",
+            ),
+            recording(
+                &["info", "--json=v2", "--cask", "firefox"],
+                &cask("firefox"),
+            ),
+            not_found(
+                &["info", "--json=v2", "--formula", "jqq"],
+                "Error: No available formula with the name \"jqq\".",
+            ),
+            not_found(
+                &["info", "--json=v2", "--cask", "jqq"],
+                "Error: Cask 'jqq' is unavailable: No Cask with this name exists.",
+            ),
+        ]);
+        // A new client, so the version is not cached from other tests.
+        crate::Brew::new().enter(|| {
+            assert!(matches!(info("docker"), Err(Error::AmbiguousName(name)) if name == "docker"));
+            assert!(
+                matches!(info("firefox"), Ok(AnyPackage::Cask(cask)) if cask.token == "firefox")
+            );
+            assert!(matches!(info("jqq"), Err(Error::PackageNotFound(_))));
+        });
+    }
+
+    #[cfg(feature = "fixtures")]
+    #[test]
+    fn new_reports_brew_errors() {
+        let _guard = crate::fixtures::replay_recordings(vec![
+            crate::tests::recording(&["--version"], "Homebrew 4.2.0"),
+            crate::fixtures::Recording {
+                stderr: "Error: Another active Homebrew process is already in progress.".to_owned(),
+                status: crate::ExitStatus {
+                    code: Some(1),
+                    signal: None,
+                },
+                ..crate::tests::recording(&["info", "--json=v2", "--cask", "firefox"], "")
+            },
+        ]);
+        crate::Brew::new().enter(|| {
+            assert!(matches!(Cask::new("firefox"), Err(Error::Locked(_))));
+        });
+    }
+
+    #[test]
+    fn quotes_cask_opts() {
+        assert_eq!(
+            CaskOptions::new().require_sha().no_quarantine().cask_opts(),
+            "--require-sha --no-quarantine"
+        );
+        assert_eq!(
+            CaskOptions::new()
+                .appdir("/Users/me/My Apps")
+                .no_quarantine()
+                .cask_opts(),
+            "'--appdir=/Users/me/My Apps' --no-quarantine"
+        );
+        assert_eq!(
+            CaskOptions::new().appdir("/Users/me/Bob's").cask_opts(),
+            r"'--appdir=/Users/me/Bob'\''s'"
+        );
+    }
+
+    #[test]
+    fn builds_cask_options() {
+        assert_eq!(
+            CaskOptions::new()
+                .appdir("/Applications/Vetted")
+                .language("de")
+                .language("en")
+                .require_sha()
+                .skip_cask_deps()
+                .brew_options(),
+            vec![
+                "--appdir=/Applications/Vetted",
+                "--language=de,en",
+                "--require-sha",
+                "--skip-cask-deps"
+            ]
+        );
+        assert!(CaskOptions::new().brew_options().is_empty());
+    }
+
+    #[test]
+    fn leaves_self_updating_casks() {
+        let mut cask: Cask = serde_json::from_value(serde_json::json!({
+            "token": "firefox", "full_token": "firefox", "version": "121.0",
+            "installed": "120.0", "outdated": true, "auto_updates": true,
+        }))
+        .unwrap();
+        assert!(cask.needs_greedy());
+        let upgraded = cask.upgrade(false).unwrap();
+        assert_eq!(upgraded.installed.as_deref(), Some("120.0"));
+        cask.auto_updates = None;
+        assert!(!cask.needs_greedy());
+        cask.version = "latest".to_owned();
+        assert!(cask.needs_greedy());
+    }
+
+    #[test]
+    fn parses_zap_stanzas() {
+        let cask: Cask = serde_json::from_value(serde_json::json!({
+            "token": "docker",
+            "full_token": "docker",
+            "version": "4.26.1",
+            "artifacts": [
+                {"zap": [
+                    {"trash": ["~/Library/Containers/com.docker.docker", "~/.docker"]},
+                    {"launchctl": "com.docker.helper", "pkgutil": ["com.docker.pkg"],
+                     "rmdir": "~/Library/Caches/com.docker.docker"},
+                ]},
+            ],
+        }))
+        .unwrap();
+        let zap = cask.zap().unwrap();
+        assert_eq!(zap.launchctl, vec!["com.docker.helper"]);
+        assert_eq!(zap.pkgutil, vec!["com.docker.pkg"]);
+        assert_eq!(zap.paths().count(), 3);
+
+        let cask: Cask = serde_json::from_value(serde_json::json!({
+            "token": "jq-ui", "full_token": "jq-ui", "version": "1.0",
+            "artifacts": [{"app": ["jq UI.app"]}],
+        }))
+        .unwrap();
+        assert_eq!(cask.zap(), None);
+    }
+}
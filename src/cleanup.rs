@@ -0,0 +1,277 @@
+//! Removing old versions and stale downloads, via `brew cleanup`.
+use crate::command::brew;
+use crate::receipt::Receipt;
+use crate::{brew_error, cellar, prefix, Brew, Error, Result, Timing};
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Represents command line options with which to clean up.
+#[derive(Clone, Debug, Default)]
+pub struct CleanupOptions {
+    prune: Option<u32>,
+    scrub: bool,
+    dry_run: bool,
+    keep_latest: Option<usize>,
+}
+
+impl CleanupOptions {
+    /// Represents no options added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the `--prune=days` option, removing cached files older than
+    /// `days`.
+    pub fn prune(mut self, days: u32) -> Self {
+        self.prune = Some(days);
+        self
+    }
+
+    /// Adds the `-s` flag, scrubbing the cache of downloads for the latest
+    /// versions too.
+    pub fn scrub(mut self) -> Self {
+        self.scrub = true;
+        self
+    }
+
+    /// Adds the `--dry-run` flag, reporting what would be removed without
+    /// removing it.
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
+    /// Keeps the `n` most recently installed versions of each formula,
+    /// instead of only the latest.
+    ///
+    /// brew cleanup always removes every version but the latest, so the old
+    /// versions of formulae with more than one installed are removed directly
+    /// from the Cellar, and brew is told to leave those formulae alone with
+    /// `HOMEBREW_NO_CLEANUP_FORMULAE`. brew still cleans up everything else,
+    /// such as the cache. The linked version is never removed.
+    pub fn keep_latest(mut self, n: usize) -> Self {
+        self.keep_latest = Some(n.max(1));
+        self
+    }
+
+    fn brew_options(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        if let Some(days) = self.prune {
+            out.push(format!("--prune={}", days));
+        }
+        if self.scrub {
+            out.push("-s".to_owned());
+        }
+        if self.dry_run {
+            out.push("--dry-run".to_owned());
+        }
+        out
+    }
+}
+
+/// What a cleanup removed, or would remove for a dry run.
+#[derive(Clone, Debug, Default)]
+pub struct CleanupReport {
+    /// The files and directories removed.
+    pub removed: Vec<PathBuf>,
+    /// The disk space freed, as reported by brew (e.g. "1.2GB").
+    pub freed: Option<String>,
+    /// How long `brew cleanup` took, if it was run.
+    pub timing: Option<Timing>,
+}
+
+/// Cleans up every installed formula and the download cache.
+pub fn cleanup(options: &CleanupOptions) -> Result<CleanupReport> {
+    cleanup_formulae(&[], options)
+}
+
+/// Cleans up a single formula.
+pub fn cleanup_formula(name: &str, options: &CleanupOptions) -> Result<CleanupReport> {
+    cleanup_formulae(&[name], options)
+}
+
+/// Cleans up `names`, or everything if `names` is empty.
+fn cleanup_formulae(names: &[&str], options: &CleanupOptions) -> Result<CleanupReport> {
+    let mut report = CleanupReport::default();
+    let mut kept = Vec::new();
+    if let Some(keep) = options.keep_latest.filter(|&n| n > 1) {
+        let cellar = cellar()?;
+        let opt = prefix()?.join("opt");
+        let mut racks: Vec<String> = names
+            .iter()
+            .map(|n| n.rsplit('/').next().unwrap_or(n).to_owned())
+            .collect();
+        if racks.is_empty() {
+            racks = std::fs::read_dir(&cellar)?
+                .filter_map(|e| e.ok())
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .collect();
+            racks.sort();
+        }
+        for name in racks {
+            let kegs = kegs_by_age(&cellar.join(&name))?;
+            if kegs.len() <= 1 {
+                continue;
+            }
+            let linked = std::fs::canonicalize(opt.join(&name)).ok();
+            for keg in kegs.into_iter().skip(keep) {
+                if linked.as_deref() == std::fs::canonicalize(&keg).ok().as_deref() {
+                    continue;
+                }
+                if !options.dry_run {
+                    std::fs::remove_dir_all(&keg)?;
+                }
+                report.removed.push(keg);
+            }
+            kept.push(name);
+        }
+    }
+    let mut args = vec!["cleanup".to_owned()];
+    args.extend(options.brew_options());
+    args.extend(names.iter().map(|n| n.to_string()));
+    let mut client = Brew::current();
+    if !kept.is_empty() {
+        let skipped = crate::command::inherited_var("HOMEBREW_NO_CLEANUP_FORMULAE")
+            .and_then(|v| v.into_string().ok());
+        client = client.env(
+            "HOMEBREW_NO_CLEANUP_FORMULAE",
+            &no_cleanup_formulae(&kept, skipped.as_deref()),
+        );
+    }
+    let output = client.enter(|| brew(&args))?;
+    if output.success() {
+        let brew_report = parse_report(output.stdout());
+        report.removed.extend(brew_report.removed);
+        report.freed = brew_report.freed;
+        report.timing = Some(output.timing);
+        Ok(report)
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+/// The value of `HOMEBREW_NO_CLEANUP_FORMULAE` that keeps brew away from
+/// `kept`, as well as the formulae in `skipped`, the caller's value.
+fn no_cleanup_formulae(kept: &[String], skipped: Option<&str>) -> String {
+    let mut formulae: Vec<&str> = skipped
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .collect();
+    formulae.extend(kept.iter().map(String::as_str));
+    formulae.join(",")
+}
+
+/// The installed versions of a formula, newest first.
+///
+/// Kegs are ordered by the install time in their receipts, and then by
+/// version. Their modification times say little, as brew touches kegs when
+/// relinking them, and copying a Cellar resets them.
+fn kegs_by_age(rack: &Path) -> Result<Vec<PathBuf>> {
+    if !rack.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut kegs = std::fs::read_dir(rack)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.is_dir())
+        .map(|path| {
+            let time = Receipt::read(&path).ok().and_then(|r| r.time);
+            let version = path
+                .file_name()
+                .and_then(|n| version_rs::Version::from_str(&n.to_string_lossy()).ok());
+            (time, version, path)
+        })
+        .collect::<Vec<_>>();
+    kegs.sort_by(|a, b| {
+        b.0.cmp(&a.0)
+            .then_with(|| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal))
+    });
+    Ok(kegs.into_iter().map(|(_, _, path)| path).collect())
+}
+
+/// Parses the output of `brew cleanup`.
+fn parse_report(stdout: &str) -> CleanupReport {
+    let mut report = CleanupReport::default();
+    for line in stdout.lines() {
+        let line = line.trim_start_matches("==> ");
+        if let Some(rest) = line
+            .strip_prefix("Removing: ")
+            .or_else(|| line.strip_prefix("Would remove: "))
+        {
+            let path = match rest.rfind(" (") {
+                Some(i) => &rest[..i],
+                None => rest,
+            };
+            report
+                .removed
+                .push(PathBuf::from(path.trim_end_matches("...")));
+        } else if let Some(rest) = line
+            .strip_prefix("This operation has freed approximately ")
+            .or_else(|| line.strip_prefix("This operation would free approximately "))
+        {
+            report.freed = rest.split_whitespace().next().map(str::to_owned);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_report() {
+        let report = parse_report(
+            "Removing: /usr/local/Cellar/jq/1.6... (18 files, 1.1MB)\n\
+             Removing: /Users/me/Library/Caches/Homebrew/jq--1.6.tar.gz... (1.2MB)\n\
+             ==> This operation has freed approximately 2.3MB of disk space.\n",
+        );
+        assert_eq!(
+            report.removed,
+            vec![
+                PathBuf::from("/usr/local/Cellar/jq/1.6"),
+                PathBuf::from("/Users/me/Library/Caches/Homebrew/jq--1.6.tar.gz"),
+            ]
+        );
+        assert_eq!(report.freed.as_deref(), Some("2.3MB"));
+    }
+
+    #[test]
+    fn orders_kegs_by_install_time() {
+        let rack = std::env::temp_dir().join(format!("brew-rs-kegs-{}/jq", std::process::id()));
+        // 1.7 was installed last, though 1.6 was copied into place after it.
+        // Kegs without an install time come last, newest version first.
+        for (version, time) in &[
+            ("1.7", Some(200)),
+            ("1.6", Some(100)),
+            ("1.10", None),
+            ("1.5", None),
+        ] {
+            let keg = rack.join(version);
+            std::fs::create_dir_all(&keg).unwrap();
+            let receipt = serde_json::json!({ "time": time });
+            std::fs::write(keg.join("INSTALL_RECEIPT.json"), receipt.to_string()).unwrap();
+        }
+        let kegs = kegs_by_age(&rack);
+        std::fs::remove_dir_all(rack.parent().unwrap()).unwrap();
+        let names: Vec<_> = kegs
+            .unwrap()
+            .iter()
+            .map(|k| k.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["1.7", "1.6", "1.10", "1.5"]);
+    }
+
+    #[test]
+    fn skips_kept_formulae() {
+        let kept = vec!["node".to_owned(), "python@3.12".to_owned()];
+        assert_eq!(no_cleanup_formulae(&kept, None), "node,python@3.12");
+        assert_eq!(
+            no_cleanup_formulae(&kept, Some("go, ")),
+            "go,node,python@3.12"
+        );
+    }
+}
@@ -0,0 +1,1862 @@
+use crate::{Failure, Invocation, Result};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::ffi::{OsStr, OsString};
+#[cfg(not(feature = "pty"))]
+use std::io::BufReader;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+#[cfg(not(feature = "pty"))]
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How a brew process exited.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExitStatus {
+    /// The exit code, if the process exited normally.
+    pub code: Option<i32>,
+    /// The signal that terminated the process, on Unix.
+    pub signal: Option<i32>,
+}
+
+impl ExitStatus {
+    /// Checks if the process exited with code 0.
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+impl From<std::process::ExitStatus> for ExitStatus {
+    fn from(status: std::process::ExitStatus) -> Self {
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal = None;
+        ExitStatus {
+            code: status.code(),
+            signal,
+        }
+    }
+}
+
+/// The captured result of running a brew command.
+#[derive(Clone, Debug)]
+pub(crate) struct Output {
+    /// stdout, lossily decoded and with ANSI escape sequences removed.
+    pub(crate) stdout: String,
+    /// stderr, lossily decoded and with ANSI escape sequences removed.
+    pub(crate) stderr: String,
+    pub(crate) raw_stdout: Vec<u8>,
+    pub(crate) raw_stderr: Vec<u8>,
+    pub(crate) status: ExitStatus,
+    pub(crate) timing: Timing,
+    /// The command that was run, if recorded with `ran`.
+    pub(crate) command: Option<Invocation>,
+}
+
+impl Output {
+    pub(crate) fn new(raw_stdout: Vec<u8>, raw_stderr: Vec<u8>, status: ExitStatus) -> Output {
+        Output {
+            stdout: clean(&raw_stdout),
+            stderr: clean(&raw_stderr),
+            raw_stdout,
+            raw_stderr,
+            status,
+            timing: Timing::default(),
+            command: None,
+        }
+    }
+
+    /// Records that this is the output of `command`, for errors to report.
+    pub(crate) fn ran(mut self, command: &std::process::Command) -> Output {
+        self.command = Some(Invocation {
+            argv: std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|a| a.to_string_lossy().into_owned())
+                .collect(),
+            dir: command
+                .get_current_dir()
+                .map(Path::to_owned)
+                .or_else(|| std::env::current_dir().ok()),
+        });
+        self
+    }
+
+    /// The failure to report for the command, with its stderr as the
+    /// message.
+    pub(crate) fn failure(&self) -> Failure {
+        Failure {
+            message: self.stderr.clone(),
+            status: Some(self.status),
+            stdout: self.raw_stdout.clone(),
+            stderr: self.raw_stderr.clone(),
+            command: self.command.clone().map(Box::new),
+        }
+    }
+
+    /// Records that the command started at `start`, and took `spawn` to
+    /// spawn.
+    fn timed(mut self, start: Instant, spawn: Duration) -> Output {
+        self.timing.spawn = spawn;
+        self.timing.wall = start.elapsed();
+        self
+    }
+
+    pub(crate) fn success(&self) -> bool {
+        self.status.success()
+    }
+
+    pub(crate) fn stdout(&self) -> &str {
+        &self.stdout
+    }
+
+    pub(crate) fn stderr(&self) -> &str {
+        &self.stderr
+    }
+}
+
+/// How long an operation took.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timing {
+    /// How long it took to start brew, or for a download, to get a
+    /// response.
+    pub spawn: Duration,
+    /// How long the operation took in all, including `spawn`.
+    pub wall: Duration,
+    /// How much was downloaded, when known. brew does not report this, so
+    /// it is only known for downloads this crate makes itself.
+    pub bytes_downloaded: Option<u64>,
+}
+
+/// A brew command that finished, as reported to `Metrics`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandMetrics {
+    /// The arguments brew was run with, starting with the command, such as
+    /// `install`.
+    pub args: Vec<String>,
+    pub status: ExitStatus,
+    pub timing: Timing,
+}
+
+/// A download this crate made itself, as reported to `Metrics`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DownloadMetrics {
+    pub url: String,
+    pub success: bool,
+    pub timing: Timing,
+}
+
+/// Receives timings of brew operations, such as to export them as
+/// Prometheus counters. Set with `set_metrics` or `Brew::metrics`.
+///
+/// The methods are called on the thread that ran the operation, so should
+/// return quickly.
+pub trait Metrics: Send + Sync {
+    /// Called after each brew command finishes, whether or not it
+    /// succeeded.
+    fn command_finished(&self, _command: &CommandMetrics) {}
+
+    /// Called after each download this crate makes itself, such as of a
+    /// bottle, finishes.
+    fn download_finished(&self, _download: &DownloadMetrics) {}
+}
+
+/// Adds up the timings of the commands and downloads of an operation,
+/// passing each on to the metrics it stands in for.
+struct Accumulated {
+    timing: Mutex<Timing>,
+    inner: Option<Arc<dyn Metrics>>,
+}
+
+impl Accumulated {
+    fn add(&self, timing: &Timing) {
+        let mut total = self.timing.lock().unwrap_or_else(|e| e.into_inner());
+        total.spawn += timing.spawn;
+        if let Some(bytes) = timing.bytes_downloaded {
+            *total.bytes_downloaded.get_or_insert(0) += bytes;
+        }
+    }
+}
+
+impl Metrics for Accumulated {
+    fn command_finished(&self, command: &CommandMetrics) {
+        self.add(&command.timing);
+        if let Some(inner) = &self.inner {
+            inner.command_finished(command);
+        }
+    }
+
+    fn download_finished(&self, download: &DownloadMetrics) {
+        self.add(&download.timing);
+        if let Some(inner) = &self.inner {
+            inner.download_finished(download);
+        }
+    }
+}
+
+/// For internal use, runs `f` as a single operation, returning how long it
+/// took. The spawn time and bytes downloaded are the totals of the commands
+/// and downloads `f` ran on this thread.
+pub(crate) fn timed<T, F: FnOnce() -> T>(f: F) -> (T, Timing) {
+    let client = Brew::current();
+    let accumulated = Arc::new(Accumulated {
+        timing: Mutex::new(Timing::default()),
+        inner: client.metrics.clone(),
+    });
+    let start = Instant::now();
+    let result = client.metrics(Some(accumulated.clone())).enter(f);
+    let mut timing = *accumulated.timing.lock().unwrap_or_else(|e| e.into_inner());
+    timing.wall = start.elapsed();
+    (result, timing)
+}
+
+/// The brew executable to run when a client does not set one, as described
+/// in `Brew::executable`.
+fn brew_executable() -> &'static Path {
+    static EXECUTABLE: OnceLock<PathBuf> = OnceLock::new();
+    EXECUTABLE.get_or_init(|| {
+        find_brew(
+            std::env::var_os("PATH").as_deref(),
+            std::env::var_os("HOMEBREW_PREFIX").as_deref(),
+            std::env::var_os("HOME").as_deref(),
+        )
+    })
+}
+
+fn find_brew(
+    path: Option<&OsStr>,
+    homebrew_prefix: Option<&OsStr>,
+    home: Option<&OsStr>,
+) -> PathBuf {
+    if let Some(path) = path {
+        if std::env::split_paths(path).any(|dir| dir.join("brew").is_file()) {
+            return PathBuf::from("brew");
+        }
+    }
+    let mut candidates = Vec::new();
+    candidates.extend(homebrew_prefix.map(|p| Path::new(p).join("bin/brew")));
+    candidates.push(PathBuf::from("/opt/homebrew/bin/brew"));
+    candidates.push(PathBuf::from("/usr/local/bin/brew"));
+    candidates.push(PathBuf::from("/home/linuxbrew/.linuxbrew/bin/brew"));
+    candidates.extend(home.map(|h| Path::new(h).join(".linuxbrew/bin/brew")));
+    candidates
+        .into_iter()
+        .find(|c| c.is_file())
+        .unwrap_or_else(|| PathBuf::from("brew"))
+}
+
+/// When brew updates itself and its taps before running a command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum AutoUpdate {
+    /// Never auto-update, by setting `HOMEBREW_NO_AUTO_UPDATE`. Call
+    /// `update()` to update.
+    #[default]
+    Never,
+    /// Let brew auto-update as it normally does, before commands such as
+    /// `install` and `upgrade`.
+    Always,
+    /// Run `brew update` before commands that would auto-update, at most
+    /// once per interval for this process.
+    OncePerInterval(Duration),
+}
+
+/// The commands brew auto-updates before.
+const AUTO_UPDATE_COMMANDS: [&str; 6] = [
+    "install",
+    "reinstall",
+    "upgrade",
+    "outdated",
+    "tap",
+    "bundle",
+];
+
+/// The proxies to use for brew commands and downloads.
+///
+/// Unset proxies are left to the environment.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Proxy {
+    http: Option<String>,
+    https: Option<String>,
+    all: Option<String>,
+    no_proxy: Vec<String>,
+}
+
+impl Proxy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the proxies from `http_proxy`, `https_proxy`, `all_proxy` and
+    /// `no_proxy`, or their upper case versions.
+    pub fn from_env() -> Self {
+        let var = |name: &str| {
+            std::env::var(name)
+                .or_else(|_| std::env::var(name.to_uppercase()))
+                .ok()
+                .filter(|v| !v.is_empty())
+        };
+        Proxy {
+            http: var("http_proxy"),
+            https: var("https_proxy"),
+            all: var("all_proxy"),
+            no_proxy: var("no_proxy")
+                .map(|v| v.split(',').map(|h| h.trim().to_owned()).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Sets the proxy for `http` URLs, as `http_proxy` does.
+    pub fn http(mut self, url: &str) -> Self {
+        self.http = Some(url.to_owned());
+        self
+    }
+
+    /// Sets the proxy for `https` URLs, as `HTTPS_PROXY` does.
+    pub fn https(mut self, url: &str) -> Self {
+        self.https = Some(url.to_owned());
+        self
+    }
+
+    /// Sets the proxy for URLs without a more specific one, as `ALL_PROXY`
+    /// does.
+    pub fn all(mut self, url: &str) -> Self {
+        self.all = Some(url.to_owned());
+        self
+    }
+
+    /// Connects to `host`, and its subdomains, directly, as `NO_PROXY` does.
+    /// `*` bypasses the proxy for every host.
+    pub fn no_proxy(mut self, host: &str) -> Self {
+        self.no_proxy.push(host.to_owned());
+        self
+    }
+
+    /// The proxy to connect to `url` through, if any.
+    pub fn for_url(&self, url: &str) -> Option<&str> {
+        let (scheme, rest) = url.split_once("://")?;
+        let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+        let host = authority.rsplit('@').next().unwrap_or_default();
+        // Remove the port, keeping IPv6 addresses such as `[::1]` whole.
+        let host = match host.strip_prefix('[') {
+            Some(v6) => v6.split(']').next().unwrap_or_default(),
+            None => host.split(':').next().unwrap_or_default(),
+        };
+        let bypassed = self.no_proxy.iter().any(|pattern| {
+            let pattern = pattern.trim_start_matches('.');
+            pattern == "*"
+                || host.eq_ignore_ascii_case(pattern)
+                || host
+                    .to_ascii_lowercase()
+                    .ends_with(&format!(".{}", pattern.to_ascii_lowercase()))
+        });
+        if bypassed {
+            return None;
+        }
+        let specific = match scheme {
+            "http" => self.http.as_deref(),
+            "https" => self.https.as_deref(),
+            _ => None,
+        };
+        specific.or(self.all.as_deref())
+    }
+
+    /// The environment variables brew and the tools it runs read the proxies
+    /// from.
+    fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = Vec::new();
+        if let Some(http) = &self.http {
+            vars.push(("http_proxy", http.clone()));
+        }
+        if let Some(https) = &self.https {
+            vars.push(("https_proxy", https.clone()));
+            vars.push(("HTTPS_PROXY", https.clone()));
+        }
+        if let Some(all) = &self.all {
+            vars.push(("all_proxy", all.clone()));
+            vars.push(("ALL_PROXY", all.clone()));
+        }
+        if !self.no_proxy.is_empty() {
+            vars.push(("no_proxy", self.no_proxy.join(",")));
+            vars.push(("NO_PROXY", self.no_proxy.join(",")));
+        }
+        vars
+    }
+}
+
+/// How much brew prints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Verbosity {
+    /// Passes `--quiet`, so brew only prints warnings and errors.
+    Quiet,
+    #[default]
+    Normal,
+    /// Passes `--verbose`.
+    Verbose,
+    /// Passes `--debug`, so brew also prints the commands it runs and
+    /// backtraces.
+    Debug,
+}
+
+impl Verbosity {
+    /// The flag brew takes for the level, if any.
+    pub fn flag(self) -> Option<&'static str> {
+        match self {
+            Verbosity::Quiet => Some("--quiet"),
+            Verbosity::Normal => None,
+            Verbosity::Verbose => Some("--verbose"),
+            Verbosity::Debug => Some("--debug"),
+        }
+    }
+}
+
+/// What happens to the output of brew commands that change the system.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ChildOutput {
+    /// Captured silently.
+    #[default]
+    Captured,
+    /// Captured, and copied to this process's stdout and stderr as brew
+    /// writes it.
+    Streamed,
+    /// Written straight to this process's stdout and stderr. Nothing is
+    /// captured, so errors only have brew's exit status to go on.
+    Inherited,
+}
+
+/// The commands `Verbosity` and `ChildOutput` apply to. Commands whose
+/// output this crate parses, such as `info`, always run at the normal
+/// verbosity, captured.
+const ACTION_COMMANDS: [&str; 13] = [
+    "install",
+    "reinstall",
+    "upgrade",
+    "uninstall",
+    "fetch",
+    "link",
+    "unlink",
+    "pin",
+    "unpin",
+    "autoremove",
+    "postinstall",
+    "tap",
+    "untap",
+];
+
+/// Represents a brew installation, and how to run it: the executable, extra
+/// environment variables, the cache directory, and the policies for
+/// auto-updating, proxies, tokens and output.
+///
+/// Everything this crate does runs brew through the current client, which is
+/// the default one unless another has been entered with `Brew::enter`. The
+/// `set_*` functions, such as `set_auto_update`, configure the default one.
+///
+/// ``` no_run
+/// use brew::{AutoUpdate, Brew, Package};
+///
+/// let brew = Brew::new()
+///     .executable("/opt/homebrew/bin/brew")
+///     .auto_update(AutoUpdate::Always)
+///     .env("HOMEBREW_NO_ANALYTICS", "1");
+/// let jq = brew.enter(|| Package::new("jq"));
+/// ```
+#[derive(Clone, Default)]
+pub struct Brew {
+    executable: Option<PathBuf>,
+    /// Variables to set, or with `None` to remove, for each brew process.
+    env: Vec<(String, Option<String>)>,
+    cache_dir: Option<PathBuf>,
+    auto_update: AutoUpdate,
+    proxy: Option<Proxy>,
+    github_token: Option<String>,
+    bottle_domain: Option<String>,
+    api_domain: Option<String>,
+    verbosity: Verbosity,
+    child_output: ChildOutput,
+    #[cfg(feature = "pty")]
+    pty: bool,
+    isolated: bool,
+    locale: Locale,
+    metrics: Option<Arc<dyn Metrics>>,
+    /// When `AutoUpdate::OncePerInterval` last updated, shared by clones.
+    last_update: Arc<Mutex<Option<Instant>>>,
+    /// What `brew --version` printed, once it has run successfully. Shared
+    /// by clones.
+    version: Arc<Mutex<Option<String>>>,
+}
+
+impl std::fmt::Debug for Brew {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("Brew");
+        debug
+            .field("executable", &self.program())
+            .field("env", &self.env)
+            .field("cache_dir", &self.cache_dir)
+            .field("auto_update", &self.auto_update)
+            .field("proxy", &self.proxy)
+            .field("github_token", &self.github_token.as_ref().map(|_| "..."))
+            .field("bottle_domain", &self.bottle_domain)
+            .field("api_domain", &self.api_domain)
+            .field("verbosity", &self.verbosity)
+            .field("child_output", &self.child_output);
+        #[cfg(feature = "pty")]
+        debug.field("pty", &self.pty);
+        debug
+            .field("isolated", &self.isolated)
+            .field("locale", &self.locale.0)
+            .field("metrics", &self.metrics.is_some())
+            .finish()
+    }
+}
+
+/// The locale brew runs in, or `None` to inherit this process's.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Locale(Option<String>);
+
+impl Default for Locale {
+    /// `C.UTF-8`, which keeps brew's messages in English without mangling
+    /// non-ASCII names and paths as `C` would. macOS has no `C.UTF-8`, so
+    /// `en_US.UTF-8` is used there instead.
+    fn default() -> Self {
+        if cfg!(target_os = "macos") {
+            Locale(Some("en_US.UTF-8".to_owned()))
+        } else {
+            Locale(Some("C.UTF-8".to_owned()))
+        }
+    }
+}
+
+thread_local! {
+    /// The client entered on this thread, if any.
+    static CURRENT: RefCell<Option<Brew>> = const { RefCell::new(None) };
+}
+
+/// The client used when none has been entered.
+fn default_client() -> MutexGuard<'static, Brew> {
+    static DEFAULT: OnceLock<Mutex<Brew>> = OnceLock::new();
+    DEFAULT
+        .get_or_init(|| Mutex::new(Brew::new()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Restores the client entered before `Brew::enter`, even if it panics.
+struct Entered(Option<Brew>);
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        CURRENT.with(|c| *c.borrow_mut() = previous);
+    }
+}
+
+impl Brew {
+    /// Represents the brew found as described in `Brew::executable`, with
+    /// the default policies.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The client brew commands currently run through on this thread.
+    ///
+    /// This is the client entered with `Brew::enter`, or else a copy of the
+    /// default client.
+    pub fn current() -> Brew {
+        CURRENT
+            .with(|c| c.borrow().clone())
+            .unwrap_or_else(|| default_client().clone())
+    }
+
+    /// Runs `f` with this client as the current one on this thread, so that
+    /// the brew commands it runs use this client's configuration.
+    ///
+    /// Threads this crate starts itself, such as for `InstallExecutor`, use
+    /// the client that was current when they were started. Other threads
+    /// start on the default client, unless started with `Brew::spawn`.
+    pub fn enter<T, F: FnOnce() -> T>(&self, f: F) -> T {
+        let previous = CURRENT.with(|c| c.borrow_mut().replace(self.clone()));
+        let _entered = Entered(previous);
+        f()
+    }
+
+    /// Runs `f` on a new thread, with this client as the current one there,
+    /// as `std::thread::spawn` does.
+    pub fn spawn<T, F>(&self, f: F) -> JoinHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let client = self.clone();
+        std::thread::spawn(move || client.enter(f))
+    }
+
+    /// Runs the brew executable at `path`.
+    ///
+    /// By default this is `brew` if it is on the `PATH`, and otherwise the
+    /// first standard install location that exists, since Linux installs are
+    /// often missing from the `PATH` of non-interactive shells.
+    pub fn executable<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.executable = Some(path.into());
+        self
+    }
+
+    /// Sets the environment variable `key` for each brew command, after the
+    /// variables the other settings set, so it takes precedence over them.
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.env.push((key.to_owned(), Some(value.to_owned())));
+        self
+    }
+
+    /// Removes the environment variable `key` for each brew command.
+    pub fn env_remove(mut self, key: &str) -> Self {
+        self.env.push((key.to_owned(), None));
+        self
+    }
+
+    /// Uses `dir` as the Homebrew cache, as `HOMEBREW_CACHE` does, both for
+    /// brew commands and for this crate's own reads of the cache, such as in
+    /// `api::cache_dir`.
+    pub fn cache_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Sets when brew auto-updates. The default is `AutoUpdate::Never`.
+    pub fn auto_update(mut self, policy: AutoUpdate) -> Self {
+        self.auto_update = policy;
+        self
+    }
+
+    /// Sets the proxies for brew commands and downloads, as `set_proxy` does
+    /// for the default client.
+    pub fn proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Sets the GitHub token, as `set_github_token` does for the default
+    /// client.
+    pub fn github_token(mut self, token: Option<&str>) -> Self {
+        self.github_token = token.map(str::to_owned);
+        self
+    }
+
+    /// Downloads bottles from the mirror at `url` rather than GitHub
+    /// Packages, as `set_bottle_domain` does for the default client.
+    pub fn bottle_domain(mut self, url: &str) -> Self {
+        self.bottle_domain = Some(url.to_owned());
+        self
+    }
+
+    /// Reads the JSON API from the mirror at `url`, as `set_api_domain` does
+    /// for the default client.
+    pub fn api_domain(mut self, url: &str) -> Self {
+        self.api_domain = Some(url.to_owned());
+        self
+    }
+
+    /// Sets how much brew prints, as `set_verbosity` does for the default
+    /// client.
+    pub fn verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Sets what happens to the output of commands that change the system,
+    /// as `set_child_output` does for the default client.
+    pub fn child_output(mut self, mode: ChildOutput) -> Self {
+        self.child_output = mode;
+        self
+    }
+
+    /// Runs commands that change the system in a pseudo-terminal, as
+    /// `set_pty` does for the default client.
+    #[cfg(feature = "pty")]
+    pub fn pty(mut self, enabled: bool) -> Self {
+        self.pty = enabled;
+        self
+    }
+
+    /// Runs brew without the `HOMEBREW_*`, Ruby and proxy variables of this
+    /// process's environment, and with a standard `PATH`, as `set_isolated`
+    /// does for the default client.
+    pub fn isolated(mut self, enabled: bool) -> Self {
+        self.isolated = enabled;
+        self
+    }
+
+    /// Sets the locale brew runs in, as `set_locale` does for the default
+    /// client.
+    pub fn locale(mut self, locale: Option<&str>) -> Self {
+        self.locale = Locale(locale.map(str::to_owned));
+        self
+    }
+
+    /// Sets where to report the timings of brew operations, as `set_metrics`
+    /// does for the default client.
+    pub fn metrics(mut self, metrics: Option<Arc<dyn Metrics>>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Forgets what this client, and its clones, cached about the brew
+    /// installation, such as its version, so that it is checked again. Call
+    /// this after installing or upgrading brew itself.
+    pub fn invalidate(&self) {
+        *self.version.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// What `brew --version` printed, or `None` if brew could not be run.
+    ///
+    /// This is only run until it succeeds once, and again after
+    /// `invalidate` is called, so brew installed in the meantime is found.
+    fn version_output(&self) -> Option<String> {
+        let mut version = self.version.lock().unwrap_or_else(|e| e.into_inner());
+        if version.is_none() {
+            *version = self
+                .brew(&["--version"])
+                .ok()
+                .filter(Output::success)
+                .map(|o| o.stdout);
+        }
+        version.clone()
+    }
+
+    /// The brew executable this client runs.
+    fn program(&self) -> &Path {
+        match &self.executable {
+            Some(path) => path,
+            None => brew_executable(),
+        }
+    }
+
+    /// The GitHub token set for this client, or else the one in
+    /// `HOMEBREW_GITHUB_API_TOKEN` or `GITHUB_TOKEN`.
+    fn token(&self) -> Option<String> {
+        self.github_token
+            .clone()
+            .or_else(|| {
+                inherited_var("HOMEBREW_GITHUB_API_TOKEN")?
+                    .into_string()
+                    .ok()
+            })
+            .or_else(|| std::env::var("GITHUB_TOKEN").ok())
+            .filter(|t| !t.is_empty())
+    }
+}
+
+/// Sets when brew auto-updates, for every brew command run through the
+/// default client.
+///
+/// The default is `AutoUpdate::Never`.
+pub fn set_auto_update(policy: AutoUpdate) {
+    default_client().auto_update = policy;
+}
+
+/// When brew auto-updates, for the current client.
+pub fn auto_update() -> AutoUpdate {
+    Brew::current().auto_update
+}
+
+/// Sets the proxies for every brew command run through the default client,
+/// and every download it makes.
+///
+/// `None`, the default, leaves brew to read them from the environment, and
+/// has downloads use `Proxy::from_env()`.
+pub fn set_proxy(proxy: Option<Proxy>) {
+    default_client().proxy = proxy;
+}
+
+/// The proxies of the current client.
+pub fn proxy() -> Option<Proxy> {
+    Brew::current().proxy
+}
+
+/// Sets the GitHub token brew uses for the GitHub API, as
+/// `HOMEBREW_GITHUB_API_TOKEN`, and this crate uses for GitHub and GitHub
+/// Packages requests, for the default client.
+///
+/// Authenticated requests have a much higher rate limit, so automation that
+/// runs many commands should set one.
+pub fn set_github_token(token: Option<&str>) {
+    default_client().github_token = token.map(str::to_owned);
+}
+
+/// The GitHub token of the current client, or else the one in
+/// `HOMEBREW_GITHUB_API_TOKEN` or `GITHUB_TOKEN`.
+pub fn github_token() -> Option<String> {
+    Brew::current().token()
+}
+
+/// The domain bottles are downloaded from by default.
+pub const DEFAULT_BOTTLE_DOMAIN: &str = "https://ghcr.io/v2/homebrew/core";
+
+/// The domain the JSON API is read from by default.
+pub const DEFAULT_API_DOMAIN: &str = "https://formulae.brew.sh/api";
+
+/// Sets a mirror, such as an Artifactory repository, to download
+/// homebrew/core bottles from rather than `DEFAULT_BOTTLE_DOMAIN`, for brew
+/// commands run through the default client and this crate's own downloads,
+/// as `HOMEBREW_BOTTLE_DOMAIN` does.
+///
+/// `None`, the default, leaves it to `HOMEBREW_BOTTLE_DOMAIN`.
+pub fn set_bottle_domain(url: Option<&str>) {
+    default_client().bottle_domain = url.map(str::to_owned);
+}
+
+/// The bottle mirror of the current client, or else the one in
+/// `HOMEBREW_BOTTLE_DOMAIN`, if any.
+pub fn bottle_domain() -> Option<String> {
+    Brew::current()
+        .bottle_domain
+        .or_else(|| inherited_var("HOMEBREW_BOTTLE_DOMAIN")?.into_string().ok())
+        .filter(|d| !d.is_empty())
+}
+
+/// Sets a mirror to read the JSON API from rather than
+/// `DEFAULT_API_DOMAIN`, for brew commands run through the default client,
+/// as `HOMEBREW_API_DOMAIN` does.
+///
+/// `None`, the default, leaves it to `HOMEBREW_API_DOMAIN`.
+pub fn set_api_domain(url: Option<&str>) {
+    default_client().api_domain = url.map(str::to_owned);
+}
+
+/// The JSON API mirror of the current client, or else the one in
+/// `HOMEBREW_API_DOMAIN`, or else `DEFAULT_API_DOMAIN`.
+pub fn api_domain() -> String {
+    Brew::current()
+        .api_domain
+        .or_else(|| inherited_var("HOMEBREW_API_DOMAIN")?.into_string().ok())
+        .filter(|d| !d.is_empty())
+        .unwrap_or_else(|| DEFAULT_API_DOMAIN.to_owned())
+}
+
+/// Sets how much brew prints, for commands that change the system such as
+/// `install`, run through the default client.
+///
+/// The default is `Verbosity::Normal`. This only matters if the output is
+/// shown with `set_child_output`, or read from errors.
+pub fn set_verbosity(verbosity: Verbosity) {
+    default_client().verbosity = verbosity;
+}
+
+/// How much brew prints, for the current client.
+pub fn verbosity() -> Verbosity {
+    Brew::current().verbosity
+}
+
+/// Sets what happens to the output of commands that change the system, such
+/// as `install`, run through the default client.
+///
+/// The default is `ChildOutput::Captured`.
+pub fn set_child_output(mode: ChildOutput) {
+    default_client().child_output = mode;
+}
+
+/// What happens to the output of commands, for the current client.
+pub fn child_output() -> ChildOutput {
+    Brew::current().child_output
+}
+
+/// Runs commands that change the system, such as `install`, in a
+/// pseudo-terminal, so brew and the installers it runs behave as they do
+/// in a terminal: progress bars are drawn, and installers can prompt.
+///
+/// A pseudo-terminal merges stdout and stderr, so the captured output has
+/// everything brew wrote in both. With `ChildOutput::Inherited` this
+/// process's stdin is forwarded, so prompts can be answered.
+#[cfg(feature = "pty")]
+pub fn set_pty(enabled: bool) {
+    default_client().pty = enabled;
+}
+
+/// Whether commands run in a pseudo-terminal, for the current client.
+#[cfg(feature = "pty")]
+pub fn pty() -> bool {
+    Brew::current().pty
+}
+
+/// Sets whether brew commands run through the default client ignore this
+/// process's environment, so they behave the same whatever shell the program
+/// was started from.
+///
+/// Inherited `HOMEBREW_*` variables, Ruby variables such as `RUBYOPT`, and
+/// proxy variables are removed, and `PATH` is only brew's directory and the
+/// system directories, with a bare `brew` executable looked up in this
+/// process's `PATH` first. Variables set through the client, such as with
+/// `set_proxy` or `Brew::env`, still apply. This crate's own reads of those
+/// variables, such as for `api::cache_dir` or `bottle_domain`, ignore them
+/// too. The default is `false`.
+pub fn set_isolated(enabled: bool) {
+    default_client().isolated = enabled;
+}
+
+/// Whether brew commands ignore this process's environment, for the current
+/// client.
+pub fn isolated() -> bool {
+    Brew::current().isolated
+}
+
+/// Sets the locale brew commands run through the default client run in, as
+/// `LC_ALL` and `LANG`, or `None` to leave this process's.
+///
+/// The default is `C.UTF-8`, or `en_US.UTF-8` on macOS, so that output this
+/// crate parses, such as from `update`, `cleanup` and `doctor`, is in
+/// English whatever the user's locale.
+pub fn set_locale(locale: Option<&str>) {
+    default_client().locale = Locale(locale.map(str::to_owned));
+}
+
+/// The locale brew commands run in, for the current client, or `None` if
+/// they run in this process's.
+pub fn locale() -> Option<String> {
+    Brew::current().locale.0
+}
+
+/// The variables of `vars` an isolated client removes.
+fn isolated_vars<I: Iterator<Item = OsString>>(vars: I) -> Vec<OsString> {
+    vars.filter(|key| is_isolated_var(&key.to_string_lossy()))
+        .collect()
+}
+
+fn is_isolated_var(key: &str) -> bool {
+    const RUBY: &[&str] = &[
+        "RUBYOPT",
+        "RUBYLIB",
+        "GEM_HOME",
+        "GEM_PATH",
+        "BUNDLE_GEMFILE",
+    ];
+    key.starts_with("HOMEBREW_")
+        || RUBY.contains(&key)
+        || key.to_ascii_lowercase().ends_with("_proxy")
+}
+
+/// This process's `key` variable, unless the current client is isolated and
+/// removes it, so this crate reads the same environment brew runs with.
+pub(crate) fn inherited_var(key: &str) -> Option<OsString> {
+    if is_isolated_var(key) && Brew::current().isolated {
+        None
+    } else {
+        std::env::var_os(key)
+    }
+}
+
+/// `program` as a path that does not depend on `PATH`: a bare name is looked
+/// up in `path`, as the shell would, and anything else is kept.
+fn absolute_program(program: &Path, path: Option<&OsStr>) -> PathBuf {
+    if program.components().count() == 1 && !program.is_absolute() {
+        let found = path
+            .into_iter()
+            .flat_map(std::env::split_paths)
+            .map(|dir| dir.join(program))
+            .find(|candidate| candidate.is_file() && candidate.is_absolute());
+        if let Some(found) = found {
+            return found;
+        }
+    }
+    program.to_owned()
+}
+
+/// The `PATH` of an isolated client: the directory of `brew`, then the
+/// system directories.
+fn isolated_path(brew: &Path) -> OsString {
+    let mut path = OsString::new();
+    if let Some(dir) = brew.parent().filter(|d| !d.as_os_str().is_empty()) {
+        path.push(dir);
+        path.push(":");
+    }
+    path.push("/usr/bin:/bin:/usr/sbin:/sbin");
+    path
+}
+
+/// Sets where to report the timings of brew operations run through the
+/// default client, or `None` to stop reporting them.
+pub fn set_metrics(metrics: Option<Arc<dyn Metrics>>) {
+    default_client().metrics = metrics;
+}
+
+/// The metrics of the current client.
+#[cfg(feature = "download")]
+pub(crate) fn metrics() -> Option<Arc<dyn Metrics>> {
+    Brew::current().metrics
+}
+
+/// What `brew --version` printed for the current client, or `None` if brew
+/// could not be run.
+pub(crate) fn version_output() -> Option<String> {
+    Brew::current().version_output()
+}
+
+/// The cache directory set for the current client, if any.
+pub(crate) fn cache_dir() -> Option<PathBuf> {
+    Brew::current().cache_dir
+}
+
+/// Checks if `args` is a command `Verbosity` and `ChildOutput` apply to.
+fn is_action(args: &[&str]) -> bool {
+    args.first().is_some_and(|c| ACTION_COMMANDS.contains(c))
+}
+
+/// Starts a GET request for `url`, through the proxy for it.
+#[cfg(any(feature = "download", feature = "github"))]
+pub(crate) fn http_get(url: &str) -> Result<ureq::Request> {
+    let proxy = proxy().unwrap_or_else(Proxy::from_env);
+    let mut agent = ureq::AgentBuilder::new();
+    if let Some(proxy) = proxy.for_url(url) {
+        let proxy = ureq::Proxy::new(proxy)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+        agent = agent.proxy(proxy);
+    }
+    Ok(agent
+        .build()
+        .get(url)
+        .set("User-Agent", concat!("brew-rs/", env!("CARGO_PKG_VERSION"))))
+}
+
+impl Brew {
+    /// Runs `brew update` first if `args` is a command brew would auto-update
+    /// before, and the `OncePerInterval` policy says it is time.
+    ///
+    /// Failing to update is not an error, as with brew's own auto-update.
+    fn update_if_stale(&self, args: &[&str]) {
+        let interval = match self.auto_update {
+            AutoUpdate::OncePerInterval(interval) => interval,
+            _ => return,
+        };
+        if !args
+            .first()
+            .is_some_and(|c| AUTO_UPDATE_COMMANDS.contains(c))
+        {
+            return;
+        }
+        {
+            let mut last = self.last_update.lock().unwrap_or_else(|e| e.into_inner());
+            if last.is_some_and(|t| t.elapsed() < interval) {
+                return;
+            }
+            *last = Some(Instant::now());
+        }
+        let _ = self.brew(&["update"]);
+    }
+
+    /// The brew process to spawn for `args`.
+    fn command(&self, args: &[&str]) -> std::process::Command {
+        let mut command = if self.isolated {
+            // The isolated `PATH` is also the one a bare `brew` is looked up
+            // in, so find it in the caller's first.
+            let program = absolute_program(self.program(), std::env::var_os("PATH").as_deref());
+            let mut command = std::process::Command::new(&program);
+            for key in isolated_vars(std::env::vars_os().map(|(k, _)| k)) {
+                command.env_remove(key);
+            }
+            command.env("PATH", isolated_path(&program));
+            command
+        } else {
+            std::process::Command::new(self.program())
+        };
+        command.args(args.first()).stdin(Stdio::null());
+        if let Some(locale) = &self.locale.0 {
+            command.env("LC_ALL", locale).env("LANG", locale);
+        }
+        if is_action(args) {
+            command.args(self.verbosity.flag());
+        }
+        command.args(args.iter().skip(1));
+        if self.auto_update != AutoUpdate::Always {
+            command.env("HOMEBREW_NO_AUTO_UPDATE", "1");
+        }
+        if let Some(proxy) = &self.proxy {
+            command.envs(proxy.env_vars());
+        }
+        if let Some(token) = &self.github_token {
+            command.env("HOMEBREW_GITHUB_API_TOKEN", token);
+        }
+        if let Some(dir) = &self.cache_dir {
+            command.env("HOMEBREW_CACHE", dir);
+        }
+        if let Some(domain) = &self.bottle_domain {
+            command.env("HOMEBREW_BOTTLE_DOMAIN", domain);
+        }
+        if let Some(domain) = &self.api_domain {
+            command.env("HOMEBREW_API_DOMAIN", domain);
+        }
+        for (key, value) in &self.env {
+            match value {
+                Some(value) => command.env(key, value),
+                None => command.env_remove(key),
+            };
+        }
+        command
+    }
+
+    /// Runs `brew` with `args`, auto-updating according to the policy.
+    ///
+    /// Every brew invocation in the crate goes through here, so that it can be
+    /// recorded or replayed when the `fixtures` feature is enabled.
+    fn brew(&self, args: &[&str]) -> Result<Output> {
+        #[cfg(feature = "fixtures")]
+        {
+            if let Some(output) = crate::fixtures::replayed(args)? {
+                return Ok(output);
+            }
+        }
+        self.update_if_stale(args);
+        #[cfg(feature = "log")]
+        log::debug!("running brew {}", args.join(" "));
+        let output = self.run(args)?;
+        self.finished(args, &output);
+        #[cfg(feature = "fixtures")]
+        crate::fixtures::recorded(args, &output);
+        Ok(output)
+    }
+
+    /// Runs `brew` with `args`, according to the child output mode.
+    fn run(&self, args: &[&str]) -> Result<Output> {
+        let mode = if is_action(args) {
+            self.child_output
+        } else {
+            ChildOutput::Captured
+        };
+        let mut command = self.command(args);
+        let start = Instant::now();
+        #[cfg(feature = "pty")]
+        {
+            if self.pty && is_action(args) {
+                let pty = self.command(args);
+                let (output, spawn) = match mode {
+                    ChildOutput::Captured => run_in_pty(pty, start, std::io::sink(), false)?,
+                    ChildOutput::Streamed => run_in_pty(pty, start, std::io::stdout(), false)?,
+                    ChildOutput::Inherited => run_in_pty(pty, start, std::io::stdout(), true)?,
+                };
+                return Ok(output.timed(start, spawn).ran(&command));
+            }
+        }
+        let (output, spawn) = match mode {
+            ChildOutput::Captured => {
+                let child = command
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()?;
+                let spawn = start.elapsed();
+                let output = child.wait_with_output()?;
+                (
+                    Output::new(output.stdout, output.stderr, output.status.into()),
+                    spawn,
+                )
+            }
+            ChildOutput::Streamed => {
+                let mut child = command
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()?;
+                let spawn = start.elapsed();
+                let stdout = child.stdout.take().expect("stdout is piped");
+                let stdout = std::thread::spawn(move || tee(stdout, std::io::stdout()));
+                let stderr = tee(
+                    child.stderr.take().expect("stderr is piped"),
+                    std::io::stderr(),
+                )?;
+                let status = child.wait()?;
+                let stdout = stdout.join().expect("stdout reader panicked")?;
+                (Output::new(stdout, stderr, status.into()), spawn)
+            }
+            ChildOutput::Inherited => {
+                let mut child = command.spawn()?;
+                let spawn = start.elapsed();
+                let status = child.wait()?;
+                (Output::new(Vec::new(), Vec::new(), status.into()), spawn)
+            }
+        };
+        Ok(output.timed(start, spawn).ran(&command))
+    }
+
+    /// Reports how a brew command exited to the metrics, and logs it with the
+    /// `log` feature.
+    ///
+    /// Commands that change the system and failures are logged at the info
+    /// level, and everything else at the debug level.
+    fn finished(&self, args: &[&str], output: &Output) {
+        #[cfg(feature = "log")]
+        {
+            let level = if is_action(args) || !output.success() {
+                log::Level::Info
+            } else {
+                log::Level::Debug
+            };
+            let status = match (output.status.code, output.status.signal) {
+                (Some(0), _) => "succeeded".to_owned(),
+                (Some(code), _) => format!("failed with exit code {}", code),
+                (None, Some(signal)) => format!("was killed by signal {}", signal),
+                (None, None) => "failed".to_owned(),
+            };
+            log::log!(
+                level,
+                "brew {} {} after {:.2?}",
+                args.join(" "),
+                status,
+                output.timing.wall
+            );
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.command_finished(&CommandMetrics {
+                args: args.iter().map(|a| a.to_string()).collect(),
+                status: output.status,
+                timing: output.timing,
+            });
+        }
+    }
+}
+
+/// Runs `brew` with `args` through the current client.
+pub(crate) fn brew<S: AsRef<str>>(args: &[S]) -> Result<Output> {
+    let args: Vec<&str> = args.iter().map(|s| s.as_ref()).collect();
+    Brew::current().brew(&args)
+}
+
+/// Runs `command` in a pseudo-terminal, copying what it writes to `to`, and
+/// this process's stdin to it if `forward` is set.
+#[cfg(feature = "pty")]
+fn run_in_pty<W: std::io::Write>(
+    command: std::process::Command,
+    start: Instant,
+    to: W,
+    forward: bool,
+) -> Result<(Output, Duration)> {
+    use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+
+    let pair = native_pty_system()
+        .openpty(PtySize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+        .map_err(pty_error)?;
+    let mut builder = CommandBuilder::new(command.get_program());
+    builder.args(command.get_args());
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => builder.env(key, value),
+            None => builder.env_remove(key),
+        }
+    }
+    let mut child = pair.slave.spawn_command(builder).map_err(pty_error)?;
+    let spawn = start.elapsed();
+    // Only the child holds the terminal open, so reading ends when it exits.
+    drop(pair.slave);
+    let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let forward = if forward {
+        let writer = pair.master.take_writer().map_err(pty_error)?;
+        Some(forward_stdin(writer, done.clone()))
+    } else {
+        None
+    };
+    let reader = PtyReader(pair.master.try_clone_reader().map_err(pty_error)?);
+    let bytes = tee(reader, to);
+    let status = child.wait();
+    done.store(true, std::sync::atomic::Ordering::Relaxed);
+    if let Some(forward) = forward {
+        let _ = forward.join();
+    }
+    let (bytes, status) = (bytes?, status?);
+    let status = ExitStatus {
+        code: Some(status.exit_code() as i32),
+        signal: None,
+    };
+    Ok((Output::new(bytes.clone(), bytes, status), spawn))
+}
+
+#[cfg(feature = "pty")]
+fn pty_error<E: std::fmt::Display>(e: E) -> std::io::Error {
+    std::io::Error::other(e.to_string())
+}
+
+/// Copies this process's stdin to `to` until `done` is set.
+///
+/// stdin is only read when input is waiting, so once the child exits
+/// nothing more is taken from it, and the caller reads what comes next.
+/// Only Unix can wait for input like this, so elsewhere nothing is copied.
+#[cfg(feature = "pty")]
+fn forward_stdin(
+    mut to: Box<dyn std::io::Write + Send>,
+    done: Arc<std::sync::atomic::AtomicBool>,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        // As large as stdin's own buffer, so nothing is left waiting in it.
+        let mut buf = [0; 8192];
+        while !done.load(std::sync::atomic::Ordering::Relaxed) {
+            if !stdin_ready(Duration::from_millis(50)) {
+                continue;
+            }
+            let n = match std::io::stdin().read(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => n,
+            };
+            if to.write_all(&buf[..n]).and_then(|_| to.flush()).is_err() {
+                return;
+            }
+        }
+    })
+}
+
+/// Waits up to `timeout` for input on stdin.
+#[cfg(all(feature = "pty", unix))]
+fn stdin_ready(timeout: Duration) -> bool {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: `fd` is a single valid pollfd for the duration of the call.
+    unsafe { libc::poll(&mut fd, 1, timeout.as_millis() as libc::c_int) > 0 }
+}
+
+#[cfg(all(feature = "pty", not(unix)))]
+fn stdin_ready(timeout: Duration) -> bool {
+    std::thread::sleep(timeout);
+    false
+}
+
+/// The error code for an I/O error, which Linux returns when reading a
+/// pseudo-terminal whose other end has closed.
+#[cfg(feature = "pty")]
+const EIO: i32 = 5;
+
+/// Reads the master side of a pseudo-terminal, ending at the `EIO` error
+/// reading fails with once the child exits.
+#[cfg(feature = "pty")]
+struct PtyReader<R>(R);
+
+#[cfg(feature = "pty")]
+impl<R: Read> Read for PtyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.0.read(buf) {
+            Err(e) if e.raw_os_error() == Some(EIO) => Ok(0),
+            result => result,
+        }
+    }
+}
+
+/// Reads all of `from`, copying it to `to` as it is read.
+fn tee<R: Read, W: std::io::Write>(mut from: R, mut to: W) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut buf = [0; 8192];
+    loop {
+        let n = from.read(&mut buf)?;
+        if n == 0 {
+            return Ok(bytes);
+        }
+        // The copy is best effort, brew's output is still captured.
+        let _ = to.write_all(&buf[..n]).and_then(|_| to.flush());
+        bytes.extend_from_slice(&buf[..n]);
+    }
+}
+
+/// Runs `brew` with `args`, calling `on_line` with each line of stdout and
+/// stderr as it is written.
+///
+/// Lines are split on carriage returns as well as newlines, so progress bars
+/// are reported each time they are redrawn. brew only has curl draw its
+/// progress bar on a terminal, so with the `pty` feature brew is run in a
+/// pseudo-terminal, and its stdout and stderr are read together.
+pub(crate) fn brew_streaming<S: AsRef<str>>(
+    args: &[S],
+    on_line: &mut dyn FnMut(&str),
+) -> Result<Output> {
+    let args: Vec<&str> = args.iter().map(|s| s.as_ref()).collect();
+    let client = Brew::current();
+    #[cfg(feature = "fixtures")]
+    {
+        if let Some(output) = crate::fixtures::replayed(&args)? {
+            let replayed = output.stdout.split(['\n', '\r']);
+            for line in replayed
+                .chain(output.stderr.split(['\n', '\r']))
+                .filter(|l| !l.is_empty())
+            {
+                on_line(line);
+            }
+            return Ok(output);
+        }
+    }
+    client.update_if_stale(&args);
+    #[cfg(feature = "log")]
+    log::debug!("running brew {}", args.join(" "));
+    let command = client.command(&args);
+    let start = Instant::now();
+    #[cfg(feature = "pty")]
+    let (output, spawn) = {
+        let mut lines = LineWriter::new(on_line);
+        let ran = run_in_pty(client.command(&args), start, &mut lines, false)?;
+        lines.finish();
+        ran
+    };
+    #[cfg(not(feature = "pty"))]
+    let (output, spawn) = stream_piped(client.command(&args), start, on_line)?;
+    let output = output.timed(start, spawn).ran(&command);
+    client.finished(&args, &output);
+    #[cfg(feature = "fixtures")]
+    crate::fixtures::recorded(&args, &output);
+    Ok(output)
+}
+
+/// Runs `command` with its stdout and stderr piped, calling `on_line` with
+/// each line of either as it is written.
+#[cfg(not(feature = "pty"))]
+fn stream_piped(
+    mut command: std::process::Command,
+    start: Instant,
+    on_line: &mut dyn FnMut(&str),
+) -> Result<(Output, Duration)> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let spawn = start.elapsed();
+    let (lines, received) = std::sync::mpsc::channel();
+    let stdout = read_lines(child.stdout.take().expect("stdout is piped"), lines.clone());
+    let stderr = read_lines(child.stderr.take().expect("stderr is piped"), lines);
+    for line in received {
+        on_line(&line);
+    }
+    let status = child.wait()?;
+    let stdout = stdout.join().expect("stdout reader panicked")?;
+    let stderr = stderr.join().expect("stderr reader panicked")?;
+    Ok((Output::new(stdout, stderr, status.into()), spawn))
+}
+
+/// Reads `from` on a new thread, sending each line to `lines`, and returning
+/// everything read.
+#[cfg(not(feature = "pty"))]
+fn read_lines<R: Read + Send + 'static>(
+    from: R,
+    lines: Sender<String>,
+) -> JoinHandle<std::io::Result<Vec<u8>>> {
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let mut line = Vec::new();
+        for byte in BufReader::new(from).bytes() {
+            let byte = byte?;
+            bytes.push(byte);
+            if byte == b'\n' || byte == b'\r' {
+                if !line.is_empty() {
+                    let _ = lines.send(clean(&line));
+                    line.clear();
+                }
+            } else {
+                line.push(byte);
+            }
+        }
+        if !line.is_empty() {
+            let _ = lines.send(clean(&line));
+        }
+        Ok(bytes)
+    })
+}
+
+/// Calls `on_line` with each line written to it, split as `read_lines`
+/// splits them.
+#[cfg(any(feature = "pty", test))]
+pub(crate) struct LineWriter<'a> {
+    line: Vec<u8>,
+    on_line: &'a mut dyn FnMut(&str),
+}
+
+#[cfg(any(feature = "pty", test))]
+impl<'a> LineWriter<'a> {
+    pub(crate) fn new(on_line: &'a mut dyn FnMut(&str)) -> Self {
+        LineWriter {
+            line: Vec::new(),
+            on_line,
+        }
+    }
+
+    /// Passes on the last line, if it did not end in a newline.
+    pub(crate) fn finish(&mut self) {
+        if !self.line.is_empty() {
+            (self.on_line)(&clean(&self.line));
+            self.line.clear();
+        }
+    }
+}
+
+#[cfg(any(feature = "pty", test))]
+impl std::io::Write for LineWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for &byte in buf {
+            if byte == b'\n' || byte == b'\r' {
+                self.finish();
+            } else {
+                self.line.push(byte);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Lossily decodes brew's output, removing ANSI escape sequences such as
+/// colors.
+fn clean(bytes: &[u8]) -> String {
+    strip_ansi(&String::from_utf8_lossy(bytes))
+}
+
+/// Removes ANSI escape sequences from `s`.
+pub(crate) fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\u{1b}' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // Control sequences end with a byte in `@` to `~`.
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // Operating system commands, such as hyperlinks, end with BEL or
+            // `ESC \`.
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\u{7}' {
+                        break;
+                    }
+                    if c == '\u{1b}' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_ansi() {
+        assert_eq!(
+            strip_ansi("\u{1b}[34m==>\u{1b}[0m \u{1b}[1mDownloading\u{1b}[0m"),
+            "==> Downloading"
+        );
+        assert_eq!(
+            strip_ansi("\u{1b}]8;;https://brew.sh\u{7}brew.sh\u{1b}]8;;\u{1b}\\ done"),
+            "brew.sh done"
+        );
+    }
+
+    #[test]
+    fn finds_brew_off_path() {
+        let prefix = std::env::temp_dir().join("brew-rs-find-brew");
+        std::fs::create_dir_all(prefix.join("bin")).unwrap();
+        std::fs::write(prefix.join("bin/brew"), "").unwrap();
+        assert_eq!(
+            find_brew(Some(OsStr::new("")), Some(prefix.as_os_str()), None),
+            prefix.join("bin/brew")
+        );
+        assert_eq!(
+            find_brew(Some(prefix.join("bin").as_os_str()), None, None),
+            PathBuf::from("brew")
+        );
+        std::fs::remove_dir_all(prefix).unwrap();
+    }
+
+    #[test]
+    fn sets_auto_update_env() {
+        let no_auto_update = |command: &std::process::Command| {
+            command
+                .get_envs()
+                .any(|(k, v)| k == "HOMEBREW_NO_AUTO_UPDATE" && v.is_some())
+        };
+        assert!(no_auto_update(&Brew::new().command(&["install", "jq"])));
+        let client = Brew::new().auto_update(AutoUpdate::Always);
+        assert!(!no_auto_update(&client.command(&["install", "jq"])));
+    }
+
+    #[test]
+    fn sets_locale() {
+        let lc_all = |client: Brew| {
+            client
+                .command(&["doctor"])
+                .get_envs()
+                .find(|(k, _)| *k == "LC_ALL")
+                .and_then(|(_, v)| v.map(OsStr::to_owned))
+        };
+        let default = if cfg!(target_os = "macos") {
+            "en_US.UTF-8"
+        } else {
+            "C.UTF-8"
+        };
+        assert_eq!(lc_all(Brew::new()), Some(default.into()));
+        assert_eq!(lc_all(Brew::new().locale(Some("C"))), Some("C".into()));
+        assert_eq!(lc_all(Brew::new().locale(None)), None);
+    }
+
+    #[test]
+    fn isolates_env() {
+        let vars = [
+            "HOMEBREW_NO_INSTALL_CLEANUP",
+            "HOME",
+            "RUBYOPT",
+            "https_proxy",
+            "ALL_PROXY",
+        ];
+        assert_eq!(
+            isolated_vars(vars.iter().map(OsString::from)),
+            [
+                "HOMEBREW_NO_INSTALL_CLEANUP",
+                "RUBYOPT",
+                "https_proxy",
+                "ALL_PROXY"
+            ]
+        );
+        assert_eq!(
+            isolated_path(Path::new("/opt/homebrew/bin/brew")),
+            "/opt/homebrew/bin:/usr/bin:/bin:/usr/sbin:/sbin"
+        );
+        let command = Brew::new().isolated(true).command(&["list"]);
+        assert!(command.get_envs().any(|(k, _)| k == "PATH"));
+    }
+
+    #[test]
+    fn isolates_default_executable() {
+        let dir = std::env::temp_dir().join(format!("brew-rs-isolated-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("brew"), "").unwrap();
+        let path = std::env::join_paths(["/nonexistent".as_ref(), dir.as_path()]).unwrap();
+        assert_eq!(
+            absolute_program(Path::new("brew"), Some(&path)),
+            dir.join("brew")
+        );
+        assert_eq!(
+            absolute_program(Path::new("/usr/local/bin/brew"), Some(&path)),
+            Path::new("/usr/local/bin/brew")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Whatever the default executable is, the isolated `PATH` has to
+        // find the same one.
+        let command = Brew::new().isolated(true).command(&["list"]);
+        let program = Path::new(command.get_program());
+        let expected = absolute_program(brew_executable(), std::env::var_os("PATH").as_deref());
+        assert_eq!(program, expected);
+        let path = command
+            .get_envs()
+            .find(|(k, _)| *k == "PATH")
+            .and_then(|(_, v)| v)
+            .unwrap();
+        if program.is_absolute() {
+            assert!(std::env::split_paths(path).any(|dir| Some(dir.as_path()) == program.parent()));
+        }
+    }
+
+    #[test]
+    fn isolates_inherited_vars() {
+        std::env::set_var("HOMEBREW_BREW_RS_ISOLATION_TEST", "1");
+        let var = || inherited_var("HOMEBREW_BREW_RS_ISOLATION_TEST");
+        assert_eq!(Brew::new().enter(var), Some("1".into()));
+        assert_eq!(Brew::new().isolated(true).enter(var), None);
+        std::env::remove_var("HOMEBREW_BREW_RS_ISOLATION_TEST");
+        assert_eq!(
+            Brew::new()
+                .isolated(true)
+                .bottle_domain("https://mirror.example.com")
+                .enter(bottle_domain)
+                .as_deref(),
+            Some("https://mirror.example.com")
+        );
+    }
+
+    #[test]
+    fn enters_clients() {
+        let client = Brew::new()
+            .executable("/usr/local/bin/brew")
+            .cache_dir("/tmp/brew-cache")
+            .env("HOMEBREW_NO_ANALYTICS", "1")
+            .github_token(Some("entered"));
+        let command = client.command(&["list"]);
+        assert_eq!(command.get_program(), "/usr/local/bin/brew");
+        let env: Vec<_> = command.get_envs().collect();
+        assert!(env.contains(&(
+            OsStr::new("HOMEBREW_CACHE"),
+            Some(OsStr::new("/tmp/brew-cache"))
+        )));
+        assert!(env.contains(&(OsStr::new("HOMEBREW_NO_ANALYTICS"), Some(OsStr::new("1")))));
+        assert_eq!(
+            client.enter(|| (github_token(), cache_dir())),
+            (
+                Some("entered".to_owned()),
+                Some(PathBuf::from("/tmp/brew-cache"))
+            )
+        );
+        assert_eq!(cache_dir(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn caches_version() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("brew-rs-version-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("brew");
+        let write = |body: &str| {
+            std::fs::write(&script, format!("#!/bin/sh\n{}\n", body)).unwrap();
+            std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        let client = Brew::new().executable(&script);
+        write("exit 1");
+        assert!(client.enter(crate::brew_version).is_err());
+        // Failures are not cached, so a brew that starts working is found.
+        write("echo Homebrew 4.2.0");
+        assert_eq!(client.enter(crate::brew_version).unwrap(), (4, 2, 0));
+        write("echo Homebrew 4.3.1");
+        assert_eq!(client.enter(crate::brew_version).unwrap(), (4, 2, 0));
+        client.invalidate();
+        assert_eq!(client.enter(crate::brew_version).unwrap(), (4, 3, 1));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn selects_proxy() {
+        let proxy = Proxy::new()
+            .https("http://proxy.corp:3128")
+            .all("socks5://proxy.corp:1080")
+            .no_proxy(".corp.example.com");
+        assert_eq!(
+            proxy.for_url("https://ghcr.io/v2/homebrew/core/jq/blobs/sha256:abc"),
+            Some("http://proxy.corp:3128")
+        );
+        assert_eq!(
+            proxy.for_url("http://example.com/"),
+            Some("socks5://proxy.corp:1080")
+        );
+        assert_eq!(proxy.for_url("https://git.corp.example.com:8443/x"), None);
+        assert_eq!(proxy.for_url("https://corp.example.com"), None);
+        assert!(proxy
+            .env_vars()
+            .contains(&("NO_PROXY", ".corp.example.com".to_owned())));
+    }
+
+    #[test]
+    fn adds_verbosity_flag() {
+        let args = |command: &std::process::Command| {
+            command
+                .get_args()
+                .map(|a| a.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+        let client = Brew::new().verbosity(Verbosity::Debug);
+        assert_eq!(
+            args(&client.command(&["upgrade", "jq"])),
+            ["upgrade", "--debug", "jq"]
+        );
+        assert_eq!(
+            args(&client.command(&["info", "--json=v2", "jq"])),
+            ["info", "--json=v2", "jq"]
+        );
+    }
+
+    #[test]
+    fn reports_metrics() {
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<CommandMetrics>>);
+        impl Metrics for Recorder {
+            fn command_finished(&self, command: &CommandMetrics) {
+                self.0.lock().unwrap().push(command.clone());
+            }
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let client = Brew::new().metrics(Some(recorder.clone()));
+        let status = ExitStatus {
+            code: Some(0),
+            signal: None,
+        };
+        let output = Output::new(Vec::new(), Vec::new(), status)
+            .timed(Instant::now(), Duration::from_millis(3));
+        client.finished(&["fetch", "jq"], &output);
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![CommandMetrics {
+                args: vec!["fetch".to_owned(), "jq".to_owned()],
+                status,
+                timing: output.timing,
+            }]
+        );
+
+        // Timing an operation adds up its commands, and still reports each.
+        let ((), timing) = client.enter(|| {
+            timed(|| {
+                Brew::current().finished(&["fetch", "jq"], &output);
+                Brew::current().finished(&["install", "jq"], &output);
+            })
+        });
+        assert_eq!(timing.spawn, Duration::from_millis(6));
+        assert_eq!(recorder.0.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn spawns_with_client() {
+        let client = Brew::new().cache_dir("/tmp/brew-rs-spawned");
+        let dir = client.spawn(crate::api::cache_dir).join().unwrap();
+        assert_eq!(dir, Some(PathBuf::from("/tmp/brew-rs-spawned")));
+        let dir = std::thread::spawn(crate::api::cache_dir).join().unwrap();
+        assert_ne!(dir, Some(PathBuf::from("/tmp/brew-rs-spawned")));
+    }
+
+    /// A reader that always fails with the OS error code.
+    struct Failing(i32);
+
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::from_raw_os_error(self.0))
+        }
+    }
+
+    #[test]
+    fn tee_passes_errors_on() {
+        // EIO, as reading a pseudo-terminal fails once the child exits.
+        assert!(tee(Failing(5), std::io::sink()).is_err());
+        let bytes = tee(&b"==> Pouring jq"[..], std::io::sink()).unwrap();
+        assert_eq!(bytes, b"==> Pouring jq");
+    }
+
+    #[cfg(feature = "pty")]
+    #[test]
+    fn pty_ends_at_eio() {
+        assert_eq!(tee(PtyReader(Failing(EIO)), std::io::sink()).unwrap(), b"");
+        // EACCES
+        assert!(tee(PtyReader(Failing(13)), std::io::sink()).is_err());
+    }
+
+    #[test]
+    fn decodes_lossily() {
+        let output = Output::new(
+            b"{\"name\": \"caf\xe9\"}".to_vec(),
+            b"\x1b[31mError:\x1b[0m bad".to_vec(),
+            ExitStatus {
+                code: Some(1),
+                signal: None,
+            },
+        );
+        assert_eq!(output.stdout(), "{\"name\": \"caf\u{fffd}\"}");
+        assert_eq!(output.stderr(), "Error: bad");
+        assert_eq!(output.raw_stderr[0], 0x1b);
+    }
+}
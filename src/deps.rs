@@ -0,0 +1,207 @@
+//! Finding the dependencies of a formula, via `brew deps`.
+use crate::command::brew;
+use crate::{brew_error, Error, Package, Result};
+
+/// Represents command line options with which to find dependencies.
+///
+/// Without any, only the direct runtime and recommended dependencies are
+/// found, whether or not they are installed.
+#[derive(Clone, Debug, Default)]
+pub struct DepsOptions {
+    pub(crate) include_build: bool,
+    pub(crate) include_optional: bool,
+    pub(crate) include_test: bool,
+    pub(crate) include_requirements: bool,
+    pub(crate) installed_only: bool,
+    pub(crate) recursive: bool,
+}
+
+impl DepsOptions {
+    /// Represents no options added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the `--include-build` flag.
+    pub fn include_build(mut self) -> Self {
+        self.include_build = true;
+        self
+    }
+
+    /// Adds the `--include-optional` flag.
+    pub fn include_optional(mut self) -> Self {
+        self.include_optional = true;
+        self
+    }
+
+    /// Adds the `--include-test` flag.
+    pub fn include_test(mut self) -> Self {
+        self.include_test = true;
+        self
+    }
+
+    /// Adds the `--include-requirements` flag, also listing requirements
+    /// such as `:xcode`.
+    pub fn include_requirements(mut self) -> Self {
+        self.include_requirements = true;
+        self
+    }
+
+    /// Adds the `--installed` flag, only listing dependencies that are
+    /// installed.
+    pub fn installed_only(mut self) -> Self {
+        self.installed_only = true;
+        self
+    }
+
+    /// Finds the dependencies of dependencies too. brew does this by
+    /// default, so otherwise the `--direct` flag is added.
+    pub fn recursive(mut self) -> Self {
+        self.recursive = true;
+        self
+    }
+
+    /// Fails if any option needs brew, rather than a package's info, to
+    /// find dependencies.
+    pub(crate) fn check_direct(&self) -> Result<()> {
+        let unsupported: Vec<&str> = [
+            ("include_requirements", self.include_requirements),
+            ("installed_only", self.installed_only),
+            ("recursive", self.recursive),
+        ]
+        .iter()
+        .filter(|(_, set)| *set)
+        .map(|(name, _)| *name)
+        .collect();
+        if unsupported.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::DepsOptionsUnsupported(unsupported.join(", ")))
+        }
+    }
+
+    fn brew_options(&self) -> Vec<&str> {
+        let mut out = Vec::new();
+        if !self.recursive {
+            out.push("--direct")
+        }
+        if self.include_build {
+            out.push("--include-build")
+        }
+        if self.include_optional {
+            out.push("--include-optional")
+        }
+        if self.include_test {
+            out.push("--include-test")
+        }
+        if self.include_requirements {
+            out.push("--include-requirements")
+        }
+        if self.installed_only {
+            out.push("--installed")
+        }
+        out
+    }
+}
+
+impl Package {
+    /// The names of the formulae this one directly depends on, from its
+    /// info rather than brew, in the order brew lists them.
+    ///
+    /// Runtime and recommended dependencies are always included, and build,
+    /// optional and test ones as `options` say. Requirements are not
+    /// formulae, and `recursive` and `installed_only` need other packages,
+    /// so those options fail with `Error::DepsOptionsUnsupported`; use `deps`
+    /// for them.
+    pub fn direct_dependencies(&self, options: &DepsOptions) -> Result<Vec<&str>> {
+        options.check_direct()?;
+        let mut names: Vec<&str> = Vec::new();
+        let mut kinds = vec![&self.dependencies, &self.recommended_dependencies];
+        if options.include_build {
+            kinds.push(&self.build_dependencies);
+        }
+        if options.include_optional {
+            kinds.push(&self.optional_dependencies);
+        }
+        if options.include_test {
+            kinds.push(&self.test_dependencies);
+        }
+        for name in kinds.into_iter().flatten() {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    /// The names of the formulae this one depends on, sorted.
+    pub fn deps(&self, options: &DepsOptions) -> Result<Vec<String>> {
+        let mut args = vec!["deps"];
+        args.extend(options.brew_options());
+        args.push(&self.full_name);
+        let output = brew(&args)?;
+        if output.success() {
+            let mut names: Vec<String> = output
+                .stdout()
+                .split_whitespace()
+                .map(str::to_owned)
+                .collect();
+            names.sort();
+            names.dedup();
+            Ok(names)
+        } else {
+            Err(brew_error(&output, Error::UnknownError))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_flags() {
+        assert_eq!(DepsOptions::new().brew_options(), vec!["--direct"]);
+        assert_eq!(
+            DepsOptions::new()
+                .recursive()
+                .include_build()
+                .installed_only()
+                .brew_options(),
+            vec!["--include-build", "--installed"]
+        );
+    }
+
+    #[test]
+    fn finds_direct_dependencies() {
+        let package = crate::tests::formula(
+            "ffmpeg",
+            serde_json::json!({
+                "dependencies": ["lame", "x264"],
+                "recommended_dependencies": ["x265"],
+                "build_dependencies": ["pkgconf"],
+                "optional_dependencies": ["lame"],
+                "test_dependencies": ["bats"],
+            }),
+        );
+        assert_eq!(
+            package.direct_dependencies(&DepsOptions::new()).unwrap(),
+            ["lame", "x264", "x265"]
+        );
+        assert_eq!(
+            package
+                .direct_dependencies(
+                    &DepsOptions::new()
+                        .include_build()
+                        .include_optional()
+                        .include_test()
+                )
+                .unwrap(),
+            ["lame", "x264", "x265", "pkgconf", "bats"]
+        );
+        assert!(matches!(
+            package.direct_dependencies(&DepsOptions::new().recursive().installed_only()),
+            Err(Error::DepsOptionsUnsupported(options)) if options == "installed_only, recursive"
+        ));
+    }
+}
@@ -0,0 +1,125 @@
+//! How much disk space installed packages use.
+use crate::{cache, cellar, Package, Result};
+use std::path::Path;
+
+/// The disk space used by a package, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiskUsage {
+    /// Space used by the package's kegs in the Cellar.
+    pub kegs: u64,
+    /// Space used by the package's downloads in the Homebrew cache.
+    pub cache: u64,
+}
+
+impl DiskUsage {
+    /// The total space used.
+    pub fn total(&self) -> u64 {
+        self.kegs + self.cache
+    }
+}
+
+impl Package {
+    /// Computes the disk space used by this package's kegs and cached downloads.
+    pub fn disk_usage(&self) -> Result<DiskUsage> {
+        usage_of(&self.name, &cellar()?, &cache()?)
+    }
+}
+
+/// Computes the disk usage of every installed package, largest first.
+pub fn disk_usage_report() -> Result<Vec<(String, DiskUsage)>> {
+    report_of(&cellar()?, &cache()?)
+}
+
+fn report_of(cellar: &Path, cache: &Path) -> Result<Vec<(String, DiskUsage)>> {
+    let mut report = Vec::new();
+    for entry in std::fs::read_dir(cellar)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        let usage = usage_of(&name, cellar, cache)?;
+        report.push((name, usage));
+    }
+    report.sort_by(|(a_name, a), (b_name, b)| b.total().cmp(&a.total()).then(a_name.cmp(b_name)));
+    Ok(report)
+}
+
+fn usage_of(name: &str, cellar: &Path, cache: &Path) -> Result<DiskUsage> {
+    let rack = cellar.join(name);
+    let kegs = if rack.exists() { size(&rack)? } else { 0 };
+    // Downloads live in `downloads/<hash>--<name>--<version>...`, with
+    // `<name>--<version>...` symlinks at the top level, which size skips.
+    let mut cached = 0;
+    let downloads = cache.join("downloads");
+    if downloads.is_dir() {
+        let infix = format!("--{}--", name);
+        for entry in std::fs::read_dir(downloads)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().contains(&infix) {
+                cached += size(&entry.path())?;
+            }
+        }
+    }
+    Ok(DiskUsage {
+        kegs,
+        cache: cached,
+    })
+}
+
+/// The size of `path` and everything under it, without following symlinks.
+fn size(path: &Path) -> Result<u64> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        let mut total = 0;
+        for entry in std::fs::read_dir(path)? {
+            total += size(&entry?.path())?;
+        }
+        Ok(total)
+    } else if metadata.is_file() {
+        Ok(metadata.len())
+    } else {
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sums_kegs_and_downloads() {
+        let root = std::env::temp_dir().join(format!("brew-rs-disk-usage-{}", std::process::id()));
+        let cellar = root.join("Cellar");
+        let cache = root.join("cache");
+        let write = |path: &Path, len: usize| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, vec![0; len]).unwrap();
+        };
+        write(&cellar.join("jq/1.7/bin/jq"), 300);
+        write(&cellar.join("jq/1.6/bin/jq"), 200);
+        write(&cellar.join("oniguruma/6.9.9/lib/libonig.a"), 1000);
+        write(&cache.join("downloads/0123--jq--1.7.bottle.tar.gz"), 40);
+        write(&cache.join("downloads/4567--jql--1.0.bottle.tar.gz"), 7);
+
+        let report = report_of(&cellar, &cache);
+        std::fs::remove_dir_all(&root).unwrap();
+        let report = report.unwrap();
+        assert_eq!(
+            report,
+            vec![
+                (
+                    "oniguruma".to_owned(),
+                    DiskUsage {
+                        kegs: 1000,
+                        cache: 0
+                    }
+                ),
+                (
+                    "jq".to_owned(),
+                    DiskUsage {
+                        kegs: 500,
+                        cache: 40
+                    }
+                ),
+            ]
+        );
+        assert_eq!(report[1].1.total(), 540);
+    }
+}
@@ -0,0 +1,229 @@
+//! Checking for problems with `brew doctor`, and fixing them.
+use crate::command::brew;
+use crate::{brew_error, cache, cellar, prefix, Error, Failure, Result};
+use std::path::{Path, PathBuf};
+
+/// The brew subcommands that are safe to run without asking, as fixes. None
+/// of them remove anything: `link` fails rather than overwrite files, unless
+/// it is given `--overwrite` or `--force`, which are rejected, and `tap` is
+/// only allowed with `--repair`.
+const SAFE_FIXES: [&str; 3] = ["link", "postinstall", "tap"];
+
+/// A problem reported by `brew doctor`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    /// The first line of the warning.
+    pub title: String,
+    /// The whole warning.
+    pub message: String,
+    /// The paths the warning lists.
+    pub paths: Vec<PathBuf>,
+    /// The commands brew suggests running, one per entry, such as
+    /// `["brew", "link", "jq"]`.
+    pub commands: Vec<Vec<String>>,
+}
+
+impl Finding {
+    /// Checks if the suggested fix can be applied without asking: there is
+    /// one, and it only runs brew commands that do not remove anything.
+    pub fn fixable_automatically(&self) -> bool {
+        !self.commands.is_empty()
+            && self.commands.iter().all(|c| {
+                c.first().is_some_and(|p| p == "brew")
+                    && c.get(1).is_some_and(|s| SAFE_FIXES.contains(&s.as_str()))
+                    && (c[1] != "tap" || c.get(2).is_some_and(|a| a == "--repair"))
+                    && !c
+                        .iter()
+                        .any(|a| matches!(a.as_str(), "--overwrite" | "--force" | "-f"))
+            })
+    }
+
+    /// Runs the suggested commands, if `fixable_automatically()`.
+    pub fn apply_fix(&self) -> Result<()> {
+        if !self.fixable_automatically() {
+            return Err(Error::UnknownError(Failure {
+                message: format!("\"{}\" must be fixed manually", self.title),
+                ..Failure::default()
+            }));
+        }
+        for command in &self.commands {
+            let output = brew(&command[1..])?;
+            if !output.success() {
+                return Err(brew_error(&output, Error::UnknownError));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs `brew doctor`, returning what it found.
+pub fn doctor() -> Result<Vec<Finding>> {
+    let output = brew(&["doctor"])?;
+    let findings = parse_findings(&format!("{}\n{}", output.stdout(), output.stderr()));
+    // brew exits with an error when it finds anything.
+    if output.success() || !findings.is_empty() {
+        Ok(findings)
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+/// A directory brew needs to write to that the current user cannot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PermissionProblem {
+    /// The directory, or its nearest existing parent.
+    pub path: PathBuf,
+    /// The user ID of the owner.
+    pub owner_uid: Option<u32>,
+    /// The name of the owner, if it could be looked up.
+    pub owner: Option<String>,
+}
+
+/// Checks that the current user can write to the prefix, Cellar and cache,
+/// returning the directories that it cannot.
+///
+/// Directories that do not exist yet are checked through their nearest
+/// existing parent, since brew would need to create them.
+pub fn check_permissions() -> Result<Vec<PermissionProblem>> {
+    let mut problems: Vec<PermissionProblem> = Vec::new();
+    for dir in [prefix()?, cellar()?, cache()?].iter() {
+        let existing = match dir.ancestors().find(|p| p.exists()) {
+            Some(existing) => existing,
+            None => continue,
+        };
+        if is_writable(existing) || problems.iter().any(|p| p.path == existing) {
+            continue;
+        }
+        let owner_uid = owner_uid(existing);
+        problems.push(PermissionProblem {
+            path: existing.to_owned(),
+            owner_uid,
+            owner: owner_uid.and_then(user_name),
+        });
+    }
+    Ok(problems)
+}
+
+/// Checks if a file can be created in `dir`, by creating one.
+fn is_writable(dir: &Path) -> bool {
+    let probe = dir.join(format!(".brew-rs-write-test-{}", std::process::id()));
+    match std::fs::File::create(&probe) {
+        Ok(_) => {
+            let _ = std::fs::remove_file(probe);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(unix)]
+fn owner_uid(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).ok().map(|m| m.uid())
+}
+
+#[cfg(not(unix))]
+fn owner_uid(_path: &Path) -> Option<u32> {
+    None
+}
+
+fn user_name(uid: u32) -> Option<String> {
+    let output = std::process::Command::new("id")
+        .args(["-nu", &uid.to_string()])
+        .output()
+        .ok()?;
+    let name = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    if output.status.success() && !name.is_empty() {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+fn parse_findings(output: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for block in output.split("Warning: ").skip(1) {
+        let message = block.trim_end().to_owned();
+        let mut lines = message.lines();
+        let title = lines.next().unwrap_or_default().trim().to_owned();
+        let mut paths = Vec::new();
+        let mut commands = Vec::new();
+        for line in lines {
+            if line.starts_with('/') {
+                paths.push(PathBuf::from(line.trim()));
+            } else if line.starts_with("  ") && is_command(line.trim()) {
+                commands.push(line.split_whitespace().map(str::to_owned).collect());
+            }
+        }
+        findings.push(Finding {
+            title,
+            message,
+            paths,
+            commands,
+        });
+    }
+    findings
+}
+
+fn is_command(line: &str) -> bool {
+    let first = line.split_whitespace().next().unwrap_or_default();
+    matches!(
+        first,
+        "brew" | "sudo" | "chmod" | "chown" | "rm" | "mkdir" | "xcode-select" | "softwareupdate"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_writability() {
+        assert!(is_writable(&std::env::temp_dir()));
+        assert!(!is_writable(Path::new("/nonexistent/brew-rs")));
+    }
+
+    #[test]
+    fn parses_findings() {
+        let findings = parse_findings(
+            "Please note that these warnings are just used to help the Homebrew maintainers\n\
+             with debugging if you file an issue.\n\n\
+             Warning: The following directories are not writable by your user:\n\
+             /usr/local/share/man/man8\n\n\
+             You should change the ownership of these directories to your user.\n  \
+             sudo chown -R $(whoami) /usr/local/share/man/man8\n\n\
+             Warning: You have unlinked kegs in your Cellar.\n\
+             Leaving kegs unlinked can lead to build-trouble.\n  \
+             brew link jq\n",
+        );
+        assert_eq!(findings.len(), 2);
+        assert_eq!(
+            findings[0].paths,
+            vec![PathBuf::from("/usr/local/share/man/man8")]
+        );
+        assert_eq!(findings[0].commands[0][0], "sudo");
+        assert!(!findings[0].fixable_automatically());
+        assert_eq!(findings[1].title, "You have unlinked kegs in your Cellar.");
+        assert_eq!(findings[1].commands, vec![vec!["brew", "link", "jq"]]);
+        assert!(findings[1].fixable_automatically());
+    }
+
+    #[test]
+    fn removing_fixes_are_manual() {
+        let finding = |command: &[&str]| Finding {
+            title: "Some warning".to_owned(),
+            message: String::new(),
+            paths: Vec::new(),
+            commands: vec![command.iter().map(|s| s.to_string()).collect()],
+        };
+        assert!(finding(&["brew", "postinstall", "python@3.12"]).fixable_automatically());
+        assert!(finding(&["brew", "tap", "--repair"]).fixable_automatically());
+        assert!(!finding(&["brew", "cleanup"]).fixable_automatically());
+        assert!(!finding(&["brew", "unlink", "node"]).fixable_automatically());
+        assert!(!finding(&["brew", "missing"]).fixable_automatically());
+        assert!(!finding(&["brew", "tap", "user/repo"]).fixable_automatically());
+        assert!(finding(&["brew", "link", "jq"]).fixable_automatically());
+        assert!(!finding(&["brew", "link", "--overwrite", "jq"]).fixable_automatically());
+        assert!(!finding(&["brew", "link", "--force", "openssl@3"]).fixable_automatically());
+    }
+}
@@ -0,0 +1,161 @@
+//! Installing and pinning an exact version of a formula.
+//!
+//! ``` no_run
+//! # fn main() -> brew::Result<()> {
+//! let go = brew::exact_version::ensure_version("go", "1.21.3")?;
+//! assert!(go.pinned);
+//! # Ok(())
+//! # }
+//! ```
+use crate::command::brew;
+use crate::tap::taps;
+use crate::upgrade_summary::split_revision;
+use crate::{brew_error, Error, Options, Package, Result};
+
+/// The local tap formulae are extracted into when no formula provides the
+/// version.
+pub const EXTRACT_TAP: &str = "brew-rs/versions";
+
+/// Makes sure `version` of the formula `name` is installed and pinned,
+/// returning the formula that provides it.
+///
+/// In order, this uses:
+///
+/// - an installed version of `name`, or of a versioned formula such as
+///   `name@1.21`, that is `version`, which is linked with
+///   `Package::link_version` if another version is,
+/// - a versioned formula, or `name` itself, whose current version is
+///   `version`, which is installed, or upgraded to,
+/// - a formula extracted from the history of `name` into `EXTRACT_TAP` with
+///   `brew extract`, which is installed.
+///
+/// The formula used may be a different one than `name`, such as
+/// `brew-rs/versions/go@1.21.3`, so callers should use the name of the
+/// package returned.
+pub fn ensure_version(name: &str, version: &str) -> Result<Package> {
+    let mut candidates = vec![Package::new(name)?];
+    for candidate in versioned_names(name, version) {
+        match Package::new(&candidate) {
+            Ok(package) => candidates.push(package),
+            Err(Error::PackageNotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    if let Some((package, keg)) = installed_keg(&candidates, version) {
+        // Pinning keeps the linked keg, so link the one wanted first.
+        let package = if package.linked_keg.as_deref() == Some(keg) {
+            package.clone()
+        } else if package.pinned {
+            package.unpin()?.link_version(keg)?
+        } else {
+            package.link_version(keg)?
+        };
+        return package.pin();
+    }
+    if let Some(package) = candidates.iter().find(|p| {
+        p.versions
+            .stable
+            .as_ref()
+            .is_some_and(|stable| is_version(stable.original(), version))
+    }) {
+        let package = if !package.is_installed() {
+            package.install(&Options::new())?
+        } else if package.pinned {
+            package.unpin()?.upgrade()?
+        } else {
+            package.upgrade()?
+        };
+        return package.pin();
+    }
+    extract(&candidates[0], version)?
+        .install(&Options::new())?
+        .pin()
+}
+
+/// The first of `candidates` with a keg of `version` installed, with the
+/// name of the keg, such as `1.21.3_1`.
+fn installed_keg<'a>(candidates: &'a [Package], version: &str) -> Option<(&'a Package, &'a str)> {
+    candidates.iter().find_map(|p| {
+        p.installed
+            .iter()
+            .map(|i| i.version.original())
+            .find(|keg| is_version(keg, version))
+            .map(|keg| (p, keg))
+    })
+}
+
+/// Extracts `version` of `formula` into `EXTRACT_TAP`, creating the tap if
+/// needed.
+fn extract(formula: &Package, version: &str) -> Result<Package> {
+    if !taps()?.iter().any(|t| t.name == EXTRACT_TAP) {
+        let output = brew(&["tap-new", "--no-git", EXTRACT_TAP])?;
+        if !output.success() {
+            return Err(brew_error(&output, Error::UnknownError));
+        }
+    }
+    let version_flag = format!("--version={}", version);
+    // The full name, so a formula from a tap is not mistaken for one of the
+    // same name in homebrew/core.
+    let args = [
+        "extract",
+        "--force",
+        &version_flag,
+        &formula.full_name,
+        EXTRACT_TAP,
+    ];
+    let output = brew(&args)?;
+    if !output.success() {
+        return Err(brew_error(&output, Error::UnknownError));
+    }
+    Package::new(&format!("{}/{}@{}", EXTRACT_TAP, formula.name, version))
+}
+
+/// The names versioned formulae providing `version` of `name` may have, most
+/// specific first, such as `go@1.21.3`, `go@1.21` and `go@1`.
+fn versioned_names(name: &str, version: &str) -> Vec<String> {
+    let parts: Vec<&str> = version.split('.').collect();
+    (1..=parts.len())
+        .rev()
+        .map(|n| format!("{}@{}", name, parts[..n].join(".")))
+        .collect()
+}
+
+/// Checks if `installed`, which may have a revision such as `1.21.3_1`, is
+/// `version`.
+fn is_version(installed: &str, version: &str) -> bool {
+    installed == version || split_revision(installed).0 == version
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_versions() {
+        assert_eq!(
+            versioned_names("go", "1.21.3"),
+            ["go@1.21.3", "go@1.21", "go@1"]
+        );
+        assert!(is_version("1.21.3_1", "1.21.3"));
+        assert!(is_version("2023_beta", "2023_beta"));
+        assert!(!is_version("1.21.4", "1.21.3"));
+    }
+
+    #[test]
+    fn finds_installed_kegs() {
+        use crate::tests::{formula, installed};
+        let candidates = vec![
+            formula(
+                "go",
+                serde_json::json!({
+                    "installed": [installed("1.22.0", true), installed("1.21.3_1", true)],
+                    "linked_keg": "1.22.0",
+                }),
+            ),
+            formula("go@1.21", serde_json::json!({})),
+        ];
+        let (package, keg) = installed_keg(&candidates, "1.21.3").unwrap();
+        assert_eq!((package.name.as_str(), keg), ("go", "1.21.3_1"));
+        assert!(installed_keg(&candidates, "1.20.0").is_none());
+    }
+}
@@ -0,0 +1,198 @@
+//! Record and replay brew command output.
+//!
+//! Recording captures every brew invocation made on the current thread
+//! (argv, stdout, stderr and exit status) and writes it to a JSON file.
+//! Replaying answers brew invocations from such a file instead of spawning
+//! brew, so tests can run without Homebrew installed.
+//!
+//! ``` no_run
+//! # fn main() -> brew::Result<()> {
+//! let _fixtures = brew::fixtures::replay("tests/fixtures/jq.json")?;
+//! let jq = brew::Package::new("jq")?; // answered from the fixture file
+//! # Ok(())
+//! # }
+//! ```
+use crate::command::{ExitStatus, Output};
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+
+/// A single recorded brew invocation.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Recording {
+    /// The arguments passed to brew, not including `brew` itself.
+    pub args: Vec<String>,
+    pub stdout: String,
+    pub stderr: String,
+    pub status: ExitStatus,
+}
+
+enum Mode {
+    Record {
+        path: PathBuf,
+        recordings: Vec<Recording>,
+    },
+    Replay {
+        recordings: Vec<(Recording, bool)>,
+    },
+}
+
+thread_local! {
+    static MODE: RefCell<Option<Mode>> = const { RefCell::new(None) };
+}
+
+/// Keeps recording or replaying active until dropped.
+///
+/// When recording, the fixture file is written when the guard is dropped.
+#[must_use = "fixtures are only active while the guard is alive"]
+pub struct Guard {
+    _private: (),
+}
+
+impl Guard {
+    /// Stops recording or replaying, writing the fixture file if recording.
+    pub fn finish(self) -> Result<()> {
+        let mode = MODE.with(|m| m.borrow_mut().take());
+        std::mem::forget(self);
+        match mode {
+            Some(Mode::Record { path, recordings }) => write(&path, &recordings),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if let Some(Mode::Record { path, recordings }) = MODE.with(|m| m.borrow_mut().take()) {
+            let _ = write(&path, &recordings);
+        }
+    }
+}
+
+fn write(path: &Path, recordings: &[Recording]) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(recordings)?)?;
+    Ok(())
+}
+
+/// Record brew invocations on this thread into `path`.
+pub fn record<P: AsRef<Path>>(path: P) -> Guard {
+    MODE.with(|m| {
+        *m.borrow_mut() = Some(Mode::Record {
+            path: path.as_ref().to_owned(),
+            recordings: Vec::new(),
+        })
+    });
+    Guard { _private: () }
+}
+
+/// Answer brew invocations on this thread from the recordings in `path`.
+///
+/// Recordings with the same arguments are replayed in the order they were
+/// recorded, with the last one repeating once all have been used. Invoking
+/// brew with arguments that were never recorded fails with a
+/// `std::io::ErrorKind::NotFound` error.
+pub fn replay<P: AsRef<Path>>(path: P) -> Result<Guard> {
+    let recordings: Vec<Recording> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    Ok(replay_recordings(recordings))
+}
+
+/// Answer brew invocations on this thread from `recordings`.
+pub fn replay_recordings(recordings: Vec<Recording>) -> Guard {
+    MODE.with(|m| {
+        *m.borrow_mut() = Some(Mode::Replay {
+            recordings: recordings.into_iter().map(|r| (r, false)).collect(),
+        })
+    });
+    Guard { _private: () }
+}
+
+/// For internal use, returns the replayed output for `args` if replaying.
+pub(crate) fn replayed(args: &[&str]) -> Result<Option<Output>> {
+    MODE.with(|m| match &mut *m.borrow_mut() {
+        Some(Mode::Replay { recordings }) => {
+            let mut matching = recordings
+                .iter_mut()
+                .filter(|(r, _)| r.args.iter().map(String::as_str).eq(args.iter().copied()));
+            let mut last = None;
+            for (recording, used) in &mut matching {
+                if !*used {
+                    *used = true;
+                    return Ok(Some(output(recording)));
+                }
+                last = Some(recording);
+            }
+            match last {
+                Some(recording) => Ok(Some(output(recording))),
+                None => Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("no fixture recorded for `brew {}`", args.join(" ")),
+                )
+                .into()),
+            }
+        }
+        _ => Ok(None),
+    })
+}
+
+/// For internal use, records `output` for `args` if recording.
+pub(crate) fn recorded(args: &[&str], output: &Output) {
+    MODE.with(|m| {
+        if let Some(Mode::Record { recordings, .. }) = &mut *m.borrow_mut() {
+            recordings.push(Recording {
+                args: args.iter().map(|s| s.to_string()).collect(),
+                stdout: output.stdout.clone(),
+                stderr: output.stderr.clone(),
+                status: output.status,
+            });
+        }
+    })
+}
+
+fn output(recording: &Recording) -> Output {
+    Output::new(
+        recording.stdout.clone().into_bytes(),
+        recording.stderr.clone().into_bytes(),
+        recording.status,
+    )
+    .ran(std::process::Command::new("brew").args(&recording.args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording(args: &[&str], stdout: &str, status: i32) -> Recording {
+        Recording {
+            args: args.iter().map(|s| s.to_string()).collect(),
+            stdout: stdout.to_owned(),
+            stderr: String::new(),
+            status: ExitStatus {
+                code: Some(status),
+                signal: None,
+            },
+        }
+    }
+
+    #[test]
+    fn replays_in_order() {
+        let _guard = replay_recordings(vec![
+            recording(&["--version"], "Homebrew 4.0.0", 0),
+            recording(&["update"], "", 1),
+            recording(&["update"], "Already up-to-date.", 0),
+        ]);
+        // A new client, so the version is not cached from other tests.
+        crate::Brew::new().enter(|| {
+            assert!(crate::test_brew_installed().is_ok());
+            assert!(crate::update().is_err());
+            assert!(crate::update().is_ok());
+            assert!(crate::update().is_ok());
+        });
+    }
+
+    #[test]
+    fn unrecorded_command() {
+        let _guard = replay_recordings(Vec::new());
+        assert!(matches!(crate::update(), Err(crate::Error::IOError(_))));
+    }
+}
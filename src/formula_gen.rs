@@ -0,0 +1,193 @@
+//! Generating Homebrew formulae for Rust crates.
+//!
+//! ```
+//! use brew::formula_gen::CrateFormula;
+//!
+//! let formula = CrateFormula::new("ripgrep", "14.1.0")
+//!     .desc("Search tool like grep and The Silver Searcher")
+//!     .homepage("https://github.com/BurntSushi/ripgrep")
+//!     .license("Unlicense")
+//!     .binary("rg")
+//!     .render("4c3b4ba5ee2a3b9f6d4fb9e0a96a4b3d64b0a8e5d6e7d0cf8a3e2a1b0c9d8e7f");
+//! assert!(formula.starts_with("class Ripgrep < Formula"));
+//! ```
+#[cfg(feature = "download")]
+use crate::Result;
+
+/// Describes a formula that builds a crate with `cargo install`.
+#[derive(Clone, Debug)]
+pub struct CrateFormula {
+    crate_name: String,
+    version: String,
+    formula_name: Option<String>,
+    desc: Option<String>,
+    homepage: Option<String>,
+    license: Option<String>,
+    url: Option<String>,
+    binary: Option<String>,
+}
+
+impl CrateFormula {
+    /// A formula for version `version` of the crate `crate_name`, downloaded
+    /// from crates.io.
+    pub fn new(crate_name: &str, version: &str) -> Self {
+        CrateFormula {
+            crate_name: crate_name.to_owned(),
+            version: version.to_owned(),
+            formula_name: None,
+            desc: None,
+            homepage: None,
+            license: None,
+            url: None,
+            binary: None,
+        }
+    }
+
+    /// Names the formula `name`, instead of after the crate.
+    pub fn formula_name(mut self, name: &str) -> Self {
+        self.formula_name = Some(name.to_owned());
+        self
+    }
+
+    /// Sets the `desc` of the formula.
+    pub fn desc(mut self, desc: &str) -> Self {
+        self.desc = Some(desc.to_owned());
+        self
+    }
+
+    /// Sets the `homepage` of the formula, such as the crate's repository.
+    pub fn homepage(mut self, homepage: &str) -> Self {
+        self.homepage = Some(homepage.to_owned());
+        self
+    }
+
+    /// Sets the `license` of the formula, as an SPDX identifier.
+    pub fn license(mut self, license: &str) -> Self {
+        self.license = Some(license.to_owned());
+        self
+    }
+
+    /// Downloads the source from `url`, such as a GitHub release tarball,
+    /// instead of crates.io.
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_owned());
+        self
+    }
+
+    /// The executable the test block runs with `--version`. Defaults to the
+    /// formula name.
+    pub fn binary(mut self, binary: &str) -> Self {
+        self.binary = Some(binary.to_owned());
+        self
+    }
+
+    /// The URL the source is downloaded from.
+    pub fn source_url(&self) -> String {
+        match &self.url {
+            Some(url) => url.clone(),
+            None => format!(
+                "https://static.crates.io/crates/{0}/{0}-{1}.crate",
+                self.crate_name, self.version
+            ),
+        }
+    }
+
+    /// Renders the formula, with `sha256` as the checksum of the source.
+    pub fn render(&self, sha256: &str) -> String {
+        let name = self.formula_name.as_deref().unwrap_or(&self.crate_name);
+        let binary = self.binary.as_deref().unwrap_or(name);
+        let mut rb = format!("class {} < Formula\n", class_name(name));
+        if let Some(desc) = &self.desc {
+            rb += &format!("  desc {}\n", ruby_string(desc));
+        }
+        if let Some(homepage) = &self.homepage {
+            rb += &format!("  homepage {}\n", ruby_string(homepage));
+        }
+        rb += &format!("  url {}\n", ruby_string(&self.source_url()));
+        if self.url.is_none() {
+            // brew can't detect the version from a crates.io URL.
+            rb += &format!("  version {}\n", ruby_string(&self.version));
+        }
+        rb += &format!("  sha256 {}\n", ruby_string(sha256));
+        if let Some(license) = &self.license {
+            rb += &format!("  license {}\n", ruby_string(license));
+        }
+        rb += "\n  depends_on \"rust\" => :build\n";
+        rb += "\n  def install\n";
+        rb += "    system \"cargo\", \"install\", *std_cargo_args\n";
+        rb += "  end\n";
+        rb += "\n  test do\n";
+        rb += &format!(
+            "    assert_match version.to_s, shell_output(\"#{{bin}}/{} --version\")\n",
+            binary
+        );
+        rb += "  end\n";
+        rb += "end\n";
+        rb
+    }
+
+    /// Downloads the source to compute its checksum, and renders the
+    /// formula.
+    #[cfg(feature = "download")]
+    pub fn generate(&self) -> Result<String> {
+        use sha2::{Digest, Sha256};
+
+        let response = crate::command::http_get(&self.source_url())?
+            .call()
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut response.into_reader(), &mut hasher)?;
+        Ok(self.render(&format!("{:x}", hasher.finalize())))
+    }
+}
+
+/// The Ruby class name brew expects for the formula `name`, such as
+/// `GitDelta` for `git-delta` or `PythonAT312` for `python@3.12`.
+pub fn class_name(name: &str) -> String {
+    let name = name.replace('+', "x").replace('@', "AT");
+    name.split(|c: char| c == '-' || c == '_' || c == '.' || c.is_whitespace())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+fn ruby_string(s: &str) -> String {
+    format!(
+        "\"{}\"",
+        s.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace("#{", "\\#{")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_classes() {
+        assert_eq!(class_name("ripgrep"), "Ripgrep");
+        assert_eq!(class_name("git-delta"), "GitDelta");
+        assert_eq!(class_name("python@3.12"), "PythonAT312");
+        assert_eq!(class_name("libsigc++"), "Libsigcxx");
+    }
+
+    #[test]
+    fn renders_formula() {
+        let rb = CrateFormula::new("git-delta", "0.17.0")
+            .desc("Syntax-highlighting pager")
+            .binary("delta")
+            .render("abc");
+        assert!(rb.starts_with("class GitDelta < Formula\n  desc \"Syntax-highlighting pager\"\n"));
+        assert!(rb.contains(
+            "  url \"https://static.crates.io/crates/git-delta/git-delta-0.17.0.crate\"\n"
+        ));
+        assert!(rb.contains("  version \"0.17.0\"\n"));
+        assert!(rb.contains("shell_output(\"#{bin}/delta --version\")"));
+    }
+}
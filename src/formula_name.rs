@@ -0,0 +1,173 @@
+//! Validated formula and cask names.
+//!
+//! ```
+//! use brew::formula_name::FormulaName;
+//!
+//! let name: FormulaName = "homebrew/core/postgresql@16".parse().unwrap();
+//! assert_eq!(name.tap(), Some("homebrew/core"));
+//! assert_eq!(name.base_name(), "postgresql");
+//! assert_eq!(name.version(), Some("16"));
+//! assert!("--force".parse::<FormulaName>().is_err());
+//! ```
+use crate::{Error, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+/// A formula or cask name, optionally qualified with its tap, as in
+/// `user/tap/name`, and versioned, as in `name@1.2`.
+///
+/// Names can only hold ASCII letters, digits and `-_.+@`, and can't start
+/// with `-` or `.`, so they are never mistaken for flags or paths when
+/// passed to brew. `FormulaName` dereferences to `str`, so it can be passed
+/// wherever a name is taken.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(into = "String")]
+pub struct FormulaName {
+    full: String,
+}
+
+impl FormulaName {
+    /// Validates `name`, failing with `Error::InvalidName` if it is not a
+    /// valid name.
+    pub fn new(name: &str) -> Result<FormulaName> {
+        let invalid = |why: &str| Err(Error::InvalidName(format!("{:?} {}", name, why)));
+        let parts: Vec<&str> = name.split('/').collect();
+        if parts.len() != 1 && parts.len() != 3 {
+            return invalid("is not a name or user/tap/name");
+        }
+        for (i, part) in parts.iter().enumerate() {
+            let last = i == parts.len() - 1;
+            if part.is_empty() || part.starts_with('-') || part.starts_with('.') {
+                return invalid("has an empty part or one starting with - or .");
+            }
+            let allowed = |c: char| {
+                c.is_ascii_alphanumeric() || "-_.".contains(c) || (last && "+@".contains(c))
+            };
+            if !part.chars().all(allowed) {
+                return invalid("has characters other than letters, digits and -_.+@");
+            }
+        }
+        let full = FormulaName {
+            full: name.to_owned(),
+        };
+        if full.name().matches('@').count() > 1
+            || full.base_name().is_empty()
+            || full.version() == Some("")
+        {
+            return invalid("has a misplaced @");
+        }
+        Ok(full)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.full
+    }
+
+    /// The tap, such as `homebrew/core`, if the name is qualified with one.
+    pub fn tap(&self) -> Option<&str> {
+        self.full.rfind('/').map(|i| &self.full[..i])
+    }
+
+    /// The name without its tap, such as `postgresql@16`.
+    pub fn name(&self) -> &str {
+        self.full.rsplit('/').next().unwrap_or(&self.full)
+    }
+
+    /// The name without its tap or version, such as `postgresql`.
+    pub fn base_name(&self) -> &str {
+        self.name().split('@').next().unwrap_or_default()
+    }
+
+    /// The version after `@`, such as `16`, for versioned formulae.
+    pub fn version(&self) -> Option<&str> {
+        self.name().split_once('@').map(|(_, version)| version)
+    }
+}
+
+impl Deref for FormulaName {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.full
+    }
+}
+
+impl AsRef<str> for FormulaName {
+    fn as_ref(&self) -> &str {
+        &self.full
+    }
+}
+
+impl fmt::Display for FormulaName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.full)
+    }
+}
+
+impl FromStr for FormulaName {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        FormulaName::new(s)
+    }
+}
+
+impl TryFrom<String> for FormulaName {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        FormulaName::new(&s)
+    }
+}
+
+impl<'de> Deserialize<'de> for FormulaName {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        let name = String::deserialize(d)?;
+        FormulaName::new(&name).map_err(|e| match e {
+            Error::InvalidName(why) => serde::de::Error::custom(why),
+            e => serde::de::Error::custom(format!("{:?}", e)),
+        })
+    }
+}
+
+impl From<FormulaName> for String {
+    fn from(name: FormulaName) -> String {
+        name.full
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_names() {
+        let name = FormulaName::new("libstdc++").unwrap();
+        assert_eq!((name.tap(), name.version()), (None, None));
+        let name = FormulaName::new("acme/tools/widget@2.1").unwrap();
+        assert_eq!(name.tap(), Some("acme/tools"));
+        assert_eq!(name.name(), "widget@2.1");
+        for bad in [
+            "",
+            "-rf",
+            "acme/tools",
+            "a/b/c/d",
+            "foo bar",
+            "foo@",
+            "@1",
+            "a@b@c",
+            "x;rm",
+        ]
+        .iter()
+        {
+            assert!(
+                matches!(FormulaName::new(bad), Err(Error::InvalidName(_))),
+                "{}",
+                bad
+            );
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Where formula metadata comes from: finding formula files, and checking
+//! them against the checksums brew reports for them.
+use crate::receipt::ReceiptSource;
+use crate::search::split_tap;
+use crate::tap::Tap;
+use crate::{Package, Result};
+use std::path::PathBuf;
+
+/// Where brew read a formula's metadata from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetadataSource {
+    /// Homebrew's JSON API, as for homebrew/core since Homebrew 4.
+    Api,
+    /// A local clone of the formula's tap.
+    Tap,
+}
+
+impl Package {
+    /// The formula's tap, such as `homebrew/core`.
+    pub fn tap_name(&self) -> &str {
+        match &self.tap {
+            Some(tap) => tap,
+            None => split_tap(&self.full_name).0.unwrap_or("homebrew/core"),
+        }
+    }
+
+    /// Where brew reads the formula's metadata from: the JSON API for
+    /// homebrew/core unless `HOMEBREW_NO_INSTALL_FROM_API` is set, and
+    /// otherwise its tap.
+    pub fn metadata_source(&self) -> MetadataSource {
+        if self.tap_name() == "homebrew/core"
+            && crate::command::inherited_var("HOMEBREW_NO_INSTALL_FROM_API").is_none()
+        {
+            MetadataSource::Api
+        } else {
+            MetadataSource::Tap
+        }
+    }
+
+    /// The formula file in its tap's checkout, or `None` if the tap is not
+    /// cloned locally, as for homebrew/core when installing from the API.
+    pub fn ruby_source_file(&self) -> Result<Option<PathBuf>> {
+        let path = match &self.ruby_source_path {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let tap = Tap::new(self.tap_name())?;
+        let file = tap.path.join(path);
+        Ok(if file.is_file() { Some(file) } else { None })
+    }
+
+    /// Checks the formula file against `ruby_source_checksum`, returning
+    /// the file if it matches.
+    ///
+    /// Fails with `Error::ChecksumMismatch` if the file has changed, and
+    /// returns `Ok(None)` if there is no local file or no checksum to check
+    /// it against.
+    #[cfg(feature = "verify")]
+    pub fn verify_ruby_source(&self) -> Result<Option<PathBuf>> {
+        match &self.ruby_source_checksum {
+            Some(checksum) => verify_file(self.ruby_source_file()?, &checksum.sha256),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Checks `file`, if there is one, against the hex encoded SHA-256
+/// checksum `sha256`.
+#[cfg(feature = "verify")]
+fn verify_file(file: Option<PathBuf>, sha256: &str) -> Result<Option<PathBuf>> {
+    match file {
+        Some(file) if crate::bottle::sha256_file(&file)? != sha256 => {
+            Err(crate::Error::ChecksumMismatch(file))
+        }
+        file => Ok(file),
+    }
+}
+
+impl ReceiptSource {
+    /// Where the metadata the formula was installed from came from.
+    pub fn metadata_source(&self) -> Option<MetadataSource> {
+        let path = self.path.as_deref()?;
+        Some(if path.ends_with(".jws.json") || path.contains("/api/") {
+            MetadataSource::Api
+        } else {
+            MetadataSource::Tap
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_receipt_sources() {
+        let source: ReceiptSource = serde_json::from_str(
+            r#"{"path": "/Users/me/Library/Caches/Homebrew/api/formula.jws.json",
+                "tap": "homebrew/core", "tap_git_head": "5b2c1e9", "spec": "stable",
+                "versions": {"stable": "1.7.1", "head": null, "version_scheme": 0}}"#,
+        )
+        .unwrap();
+        assert_eq!(source.metadata_source(), Some(MetadataSource::Api));
+        assert_eq!(source.tap_git_head.as_deref(), Some("5b2c1e9"));
+    }
+
+    #[cfg(feature = "verify")]
+    #[test]
+    fn verifies_files() {
+        let file = std::env::temp_dir().join(format!("brew-rs-formula-{}.rb", std::process::id()));
+        std::fs::write(&file, "abc").unwrap();
+        let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert_eq!(
+            verify_file(Some(file.clone()), sha256).unwrap(),
+            Some(file.clone())
+        );
+        assert!(matches!(
+            verify_file(Some(file.clone()), "0"),
+            Err(crate::Error::ChecksumMismatch(f)) if f == file
+        ));
+        assert_eq!(verify_file(None, sha256).unwrap(), None);
+        std::fs::remove_file(file).unwrap();
+    }
+}
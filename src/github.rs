@@ -0,0 +1,156 @@
+//! GitHub repositories that formulae are built from.
+use crate::Package;
+#[cfg(feature = "github")]
+use crate::Result;
+#[cfg(feature = "github")]
+use serde::Deserialize;
+use std::fmt;
+
+/// A repository on GitHub.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GithubRepo {
+    pub owner: String,
+    pub name: String,
+}
+
+impl GithubRepo {
+    /// Finds the repository in a GitHub URL, such as a release download,
+    /// archive, clone or API URL.
+    pub fn from_url(url: &str) -> Option<GithubRepo> {
+        let rest = url.split("://").nth(1).unwrap_or(url);
+        let rest = rest.strip_prefix("www.").unwrap_or(rest);
+        let path = if let Some(path) = rest.strip_prefix("api.github.com/repos/") {
+            path
+        } else {
+            rest.strip_prefix("github.com/")
+                .or_else(|| rest.strip_prefix("codeload.github.com/"))?
+        };
+        let mut parts = path.split(['/', '?', '#']);
+        let owner = parts.next().filter(|o| !o.is_empty())?;
+        let name = parts.next()?;
+        let name = name.strip_suffix(".git").unwrap_or(name);
+        if name.is_empty() {
+            return None;
+        }
+        Some(GithubRepo {
+            owner: owner.to_owned(),
+            name: name.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for GithubRepo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.owner, self.name)
+    }
+}
+
+impl Package {
+    /// The GitHub repository the package is built from, if its stable, head
+    /// or homepage URL is on GitHub.
+    pub fn github_repo(&self) -> Option<GithubRepo> {
+        self.urls
+            .get("stable")
+            .into_iter()
+            .chain(self.urls.get("head"))
+            .map(|u| u.url.as_str())
+            .chain(self.homepage.as_deref())
+            .find_map(GithubRepo::from_url)
+    }
+}
+
+/// Popularity and release information for a repository.
+#[cfg(feature = "github")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepoStats {
+    pub stars: u64,
+    pub forks: u64,
+    pub archived: bool,
+    /// The tag of the latest release, if the repository has releases.
+    pub latest_release: Option<String>,
+}
+
+#[cfg(feature = "github")]
+impl GithubRepo {
+    /// Fetches the repository's stats from the GitHub API.
+    ///
+    /// Requests are authenticated with `github_token()` if there is one,
+    /// since anonymous requests are heavily rate limited.
+    pub fn stats(&self) -> Result<RepoStats> {
+        #[derive(Deserialize)]
+        struct Repo {
+            stargazers_count: u64,
+            forks_count: u64,
+            #[serde(default)]
+            archived: bool,
+        }
+        #[derive(Deserialize)]
+        struct Release {
+            tag_name: String,
+        }
+
+        let repo: Repo = match github_get(&format!("repos/{}", self))? {
+            Some(repo) => serde_json::from_str(&repo)?,
+            None => return Err(crate::Error::PackageNotFound(crate::Failure::default())),
+        };
+        let latest_release = match github_get(&format!("repos/{}/releases/latest", self))? {
+            Some(release) => Some(serde_json::from_str::<Release>(&release)?.tag_name),
+            None => None,
+        };
+        Ok(RepoStats {
+            stars: repo.stargazers_count,
+            forks: repo.forks_count,
+            archived: repo.archived,
+            latest_release,
+        })
+    }
+}
+
+/// Gets `path` from the GitHub API, returning `None` if it does not exist.
+#[cfg(feature = "github")]
+fn github_get(path: &str) -> Result<Option<String>> {
+    let mut request = crate::command::http_get(&format!("https://api.github.com/{}", path))?
+        .set("Accept", "application/vnd.github+json");
+    if let Some(token) = crate::github_token() {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+    match request.call() {
+        Ok(response) => Ok(Some(response.into_string()?)),
+        Err(ureq::Error::Status(404, _)) => Ok(None),
+        Err(ureq::Error::Status(status, response))
+            if status == 429 || response.header("x-ratelimit-remaining") == Some("0") =>
+        {
+            Err(crate::Error::RateLimited(crate::Failure {
+                message: response.into_string()?,
+                ..crate::Failure::default()
+            }))
+        }
+        Err(e) => Err(std::io::Error::other(e.to_string()).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_github_urls() {
+        let jq = Some(GithubRepo {
+            owner: "jqlang".to_owned(),
+            name: "jq".to_owned(),
+        });
+        assert_eq!(
+            GithubRepo::from_url(
+                "https://github.com/jqlang/jq/releases/download/jq-1.7.1/jq-1.7.1.tar.gz"
+            ),
+            jq
+        );
+        assert_eq!(GithubRepo::from_url("https://github.com/jqlang/jq.git"), jq);
+        assert_eq!(
+            GithubRepo::from_url("https://api.github.com/repos/jqlang/jq/tarball/jq-1.7.1"),
+            jq
+        );
+        assert_eq!(GithubRepo::from_url("https://jqlang.github.io/jq/"), None);
+        assert_eq!(GithubRepo::from_url("https://github.com/jqlang"), None);
+    }
+}
@@ -0,0 +1,379 @@
+//! Installing many formulae at once, in dependency order.
+//!
+//! ``` no_run
+//! # fn main() -> brew::Result<()> {
+//! use brew::install_plan::{InstallExecutor, InstallOutcome, InstallPlan};
+//!
+//! let plan = InstallPlan::new(&["ffmpeg", "imagemagick", "ripgrep"])?;
+//! for (name, outcome) in InstallExecutor::new().jobs(4).run(&plan) {
+//!     if let InstallOutcome::Failed(e) = outcome {
+//!         eprintln!("{} failed: {:?}", name, e);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::command::brew;
+use crate::deps::DepsOptions;
+use crate::{
+    brew_error, packages, retry_while_locked, Brew, Error, Failure, Options, Package, Result,
+};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::time::Duration;
+
+/// A formula to install, with the formulae in the plan it needs first.
+#[derive(Clone, Debug)]
+pub struct PlannedInstall {
+    pub package: Package,
+    /// The names of the steps that must be installed before this one.
+    /// Dependencies that are not in the plan are taken to be installed.
+    pub dependencies: Vec<String>,
+}
+
+/// The formulae to install, with the edges between them.
+#[derive(Clone, Debug, Default)]
+pub struct InstallPlan {
+    pub steps: Vec<PlannedInstall>,
+}
+
+impl InstallPlan {
+    /// Plans installing `names`, along with their dependencies that are not
+    /// installed.
+    pub fn new(names: &[&str]) -> Result<InstallPlan> {
+        let mut steps = Vec::new();
+        let mut seen: HashSet<String> = names.iter().map(|n| short_name(n).to_owned()).collect();
+        let mut frontier: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        let mut requested = true;
+        while !frontier.is_empty() {
+            let args: Vec<&str> = frontier.iter().map(String::as_str).collect();
+            let mut level: Vec<Package> = packages(&args)?.into_values().collect();
+            level.sort_by(|a, b| a.name.cmp(&b.name));
+            frontier.clear();
+            for package in level {
+                if !requested && package.is_installed() {
+                    continue;
+                }
+                let mut dependencies = Vec::new();
+                for dependency in package.direct_dependencies(&DepsOptions::new())? {
+                    let name = short_name(dependency).to_owned();
+                    if seen.insert(name.clone()) {
+                        frontier.push(dependency.to_owned());
+                    }
+                    dependencies.push(name);
+                }
+                steps.push(PlannedInstall {
+                    package,
+                    dependencies,
+                });
+            }
+            requested = false;
+        }
+        let planned: HashSet<String> = steps.iter().map(|s| s.package.name.clone()).collect();
+        for step in &mut steps {
+            step.dependencies.retain(|d| planned.contains(d));
+        }
+        Ok(InstallPlan { steps })
+    }
+}
+
+/// What happened to a step of a plan.
+#[derive(Debug)]
+pub enum InstallOutcome {
+    Installed(Box<Package>),
+    Failed(Error),
+    /// The step was not run because `dependency` could not be installed.
+    Skipped {
+        dependency: String,
+    },
+}
+
+/// Represents how to run an `InstallPlan`.
+///
+/// Steps whose dependencies are installed run at the same time, up to the
+/// number of jobs. Bottles are fetched and installed in parallel, but
+/// formulae that are not keg-only take turns linking into the shared
+/// prefix. Steps that find brew locked
+/// by another process wait for it, up to the lock timeout.
+#[derive(Clone, Debug)]
+pub struct InstallExecutor {
+    jobs: usize,
+    options: Options,
+    lock_timeout: Duration,
+}
+
+impl Default for InstallExecutor {
+    fn default() -> Self {
+        InstallExecutor {
+            jobs: 4,
+            options: Options::default(),
+            lock_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+impl InstallExecutor {
+    /// Represents running 4 jobs, with no install options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs up to `jobs` steps at the same time, at least 1.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Installs each step with `options`.
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Waits up to `timeout` for brew to be unlocked before failing a step
+    /// with `Error::Locked`.
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// Runs `plan`, returning the outcome of each step in the order of the
+    /// plan. A failed step does not stop the others, but the steps that
+    /// depend on it are skipped.
+    pub fn run(&self, plan: &InstallPlan) -> Vec<(String, InstallOutcome)> {
+        let index: HashMap<&str, usize> = plan
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.package.name.as_str(), i))
+            .collect();
+        let dependencies = plan
+            .steps
+            .iter()
+            .map(|s| {
+                s.dependencies
+                    .iter()
+                    .filter_map(|d| index.get(d.as_str()).copied())
+                    .collect()
+            })
+            .collect();
+        let names = plan.steps.iter().map(|s| s.package.name.clone()).collect();
+        let schedule = Mutex::new(Schedule::new(names, dependencies));
+        let changed = Condvar::new();
+        let link = Mutex::new(());
+        let client = Brew::current();
+        std::thread::scope(|scope| {
+            for _ in 0..self.jobs.min(plan.steps.len()) {
+                scope.spawn(|| {
+                    client.enter(|| loop {
+                        let mut guard = schedule.lock().unwrap_or_else(PoisonError::into_inner);
+                        let i = loop {
+                            match guard.next() {
+                                Next::Step(i) => break i,
+                                Next::Wait => {
+                                    guard =
+                                        changed.wait(guard).unwrap_or_else(PoisonError::into_inner)
+                                }
+                                Next::Done => {
+                                    changed.notify_all();
+                                    return;
+                                }
+                            }
+                        };
+                        drop(guard);
+                        let outcome = match self.install(&plan.steps[i], &link) {
+                            Ok(package) => InstallOutcome::Installed(Box::new(package)),
+                            Err(e) => InstallOutcome::Failed(e),
+                        };
+                        let mut guard = schedule.lock().unwrap_or_else(PoisonError::into_inner);
+                        guard.finish(i, outcome);
+                        changed.notify_all();
+                    })
+                });
+            }
+        });
+        let outcomes = schedule
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        plan.steps
+            .iter()
+            .zip(outcomes.outcomes)
+            .map(|(step, outcome)| {
+                let outcome = outcome.unwrap_or_else(|| cycle(&step.package.name));
+                (step.package.name.clone(), outcome)
+            })
+            .collect()
+    }
+
+    /// Fetches and installs the formula of `step` without linking it, then
+    /// links it into the prefix holding `link`, unless it is keg-only.
+    fn install(&self, step: &PlannedInstall, link: &Mutex<()>) -> Result<Package> {
+        let package = &step.package;
+        retry_while_locked(self.lock_timeout, || self.fetch(&package.full_name))?;
+        let options = self.options.clone().skip_link();
+        let installed = retry_while_locked(self.lock_timeout, || package.install(&options))?;
+        if package.keg_only {
+            return Ok(installed);
+        }
+        let _linking = link.lock().unwrap_or_else(PoisonError::into_inner);
+        retry_while_locked(self.lock_timeout, || self.link(&package.full_name))?;
+        Package::new(&package.full_name)
+    }
+
+    /// Links the installed `name` into the prefix, with `brew link`.
+    fn link(&self, name: &str) -> Result<()> {
+        let output = brew(&["link", name])?;
+        if output.success() {
+            Ok(())
+        } else {
+            Err(brew_error(&output, Error::InstallFailed))
+        }
+    }
+
+    /// Downloads what is needed to install `name`, with `brew fetch`.
+    fn fetch(&self, name: &str) -> Result<()> {
+        let mut args = vec!["fetch", "--formula"];
+        if self.options.build_from_source {
+            args.push("--build-from-source");
+        }
+        if self.options.force_bottle {
+            args.push("--force-bottle");
+        }
+        if self.options.head {
+            args.push("--HEAD");
+        }
+        args.push(name);
+        let output = brew(&args)?;
+        if output.success() {
+            Ok(())
+        } else {
+            Err(brew_error(&output, Error::InstallFailed))
+        }
+    }
+}
+
+/// The error for a step left over because of a cycle in the plan.
+fn cycle(name: &str) -> InstallOutcome {
+    InstallOutcome::Failed(Error::InstallFailed(Failure {
+        message: format!("{} is part of a dependency cycle", name),
+        ..Failure::default()
+    }))
+}
+
+/// The name of a formula without its tap, as in `user/tap/name`.
+fn short_name(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+/// What a worker should do next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Next {
+    Step(usize),
+    /// Wait for a running step to finish.
+    Wait,
+    Done,
+}
+
+/// Tracks which steps of a plan have started and how they ended.
+struct Schedule {
+    names: Vec<String>,
+    /// The indices of the dependencies of each step.
+    dependencies: Vec<Vec<usize>>,
+    started: Vec<bool>,
+    outcomes: Vec<Option<InstallOutcome>>,
+    running: usize,
+}
+
+impl Schedule {
+    fn new(names: Vec<String>, dependencies: Vec<Vec<usize>>) -> Schedule {
+        let n = dependencies.len();
+        Schedule {
+            names,
+            dependencies,
+            started: vec![false; n],
+            outcomes: (0..n).map(|_| None).collect(),
+            running: 0,
+        }
+    }
+
+    /// Picks the next step whose dependencies are installed, skipping those
+    /// with a dependency that failed.
+    fn next(&mut self) -> Next {
+        'scan: loop {
+            let mut waiting = false;
+            for i in 0..self.dependencies.len() {
+                if self.started[i] {
+                    continue;
+                }
+                let mut ready = true;
+                for &d in &self.dependencies[i] {
+                    match &self.outcomes[d] {
+                        Some(InstallOutcome::Installed(_)) => {}
+                        Some(InstallOutcome::Failed(_)) | Some(InstallOutcome::Skipped { .. }) => {
+                            let dependency = self.names[d].clone();
+                            self.started[i] = true;
+                            self.outcomes[i] = Some(InstallOutcome::Skipped { dependency });
+                            continue 'scan;
+                        }
+                        None => ready = false,
+                    }
+                }
+                if ready {
+                    self.started[i] = true;
+                    self.running += 1;
+                    return Next::Step(i);
+                }
+                waiting = true;
+            }
+            // With nothing running, the steps left wait on each other.
+            return if waiting && self.running > 0 {
+                Next::Wait
+            } else {
+                Next::Done
+            };
+        }
+    }
+
+    fn finish(&mut self, i: usize, outcome: InstallOutcome) {
+        self.outcomes[i] = Some(outcome);
+        self.running -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed() -> InstallOutcome {
+        InstallOutcome::Failed(Error::InstallFailed(Failure::default()))
+    }
+
+    #[test]
+    fn schedules_in_dependency_order() {
+        // ffmpeg needs x264 and lame; ripgrep needs nothing.
+        let names = vec!["ffmpeg", "x264", "lame", "ripgrep"];
+        let mut schedule = Schedule::new(
+            names.into_iter().map(String::from).collect(),
+            vec![vec![1, 2], vec![], vec![], vec![]],
+        );
+        assert_eq!(schedule.next(), Next::Step(1));
+        assert_eq!(schedule.next(), Next::Step(2));
+        assert_eq!(schedule.next(), Next::Step(3));
+        assert_eq!(schedule.next(), Next::Wait);
+        schedule.finish(1, failed());
+        // lame is still running, but nothing is left to start.
+        assert_eq!(schedule.next(), Next::Done);
+        assert!(matches!(
+            &schedule.outcomes[0],
+            Some(InstallOutcome::Skipped { dependency }) if dependency == "x264"
+        ));
+    }
+
+    #[test]
+    fn stops_on_cycles() {
+        let mut schedule =
+            Schedule::new(vec!["a".to_owned(), "b".to_owned()], vec![vec![1], vec![0]]);
+        assert_eq!(schedule.next(), Next::Done);
+        assert!(schedule.outcomes.iter().all(Option::is_none));
+    }
+}
@@ -0,0 +1,204 @@
+//! Explaining why a package is installed.
+use crate::advisories::dependencies;
+use crate::{all_installed, Package, Result};
+use std::collections::{HashMap, VecDeque};
+
+/// Why a package is installed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InstallReason {
+    NotInstalled,
+    /// The package was installed explicitly.
+    OnRequest,
+    /// The package was installed as a dependency.
+    ///
+    /// Each chain starts with a package installed on request and lists the
+    /// installed packages leading down to, but not including, this one. Only
+    /// the shortest chain from each such package is listed. No chains means
+    /// nothing installed depends on the package any more.
+    Dependency {
+        chains: Vec<Vec<String>>,
+    },
+}
+
+impl Package {
+    /// Explains why the package is installed.
+    pub fn install_reason(&self) -> Result<InstallReason> {
+        Ok(self.install_reason_in(&all_installed()?))
+    }
+
+    /// Explains why the package is installed, given every installed package.
+    pub fn install_reason_in(&self, installed: &HashMap<String, Package>) -> InstallReason {
+        match self.installed.first() {
+            None => InstallReason::NotInstalled,
+            Some(i) if i.installed_on_request => InstallReason::OnRequest,
+            Some(_) => {
+                let dependents = dependents(installed);
+                InstallReason::Dependency {
+                    chains: find_chains(installed, &dependents, &self.name),
+                }
+            }
+        }
+    }
+}
+
+/// For internal use, maps each installed package to the installed packages
+/// that depend on it, preferring the runtime dependencies recorded when they
+/// were installed.
+pub(crate) fn dependents(installed: &HashMap<String, Package>) -> HashMap<&str, Vec<&str>> {
+    let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+    for package in installed.values() {
+        for dependency in dependencies(package) {
+            if let Some((dependency, _)) = installed.get_key_value(&dependency) {
+                dependents
+                    .entry(dependency.as_str())
+                    .or_default()
+                    .push(package.name.as_str());
+            }
+        }
+    }
+    for list in dependents.values_mut() {
+        list.sort_unstable();
+    }
+    dependents
+}
+
+/// Walks up from `name` to the packages installed on request, recording the
+/// shortest chain to each.
+fn find_chains<'a>(
+    installed: &'a HashMap<String, Package>,
+    dependents: &HashMap<&'a str, Vec<&'a str>>,
+    name: &'a str,
+) -> Vec<Vec<String>> {
+    let mut chains = Vec::new();
+    // Breadth first, so the first route found to a package is the shortest.
+    let mut routes: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut queue: VecDeque<&str> = VecDeque::from([name]);
+    while let Some(current) = queue.pop_front() {
+        let route = routes.get(current).cloned().unwrap_or_default();
+        for &dependent in dependents.get(current).into_iter().flatten() {
+            if dependent == name || routes.contains_key(dependent) {
+                continue;
+            }
+            let mut next = route.clone();
+            next.push(dependent);
+            let on_request = installed
+                .get(dependent)
+                .and_then(|p| p.installed.first())
+                .is_some_and(|i| i.installed_on_request);
+            if on_request {
+                chains.push(next.iter().rev().map(|s| s.to_string()).collect());
+            } else {
+                queue.push_back(dependent);
+            }
+            routes.insert(dependent, next);
+        }
+    }
+    chains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{formula, installed};
+    use serde_json::json;
+
+    #[test]
+    fn traces_dependents() {
+        // ffmpeg and imagemagick were requested; both pull in libpng
+        // through other libraries, and libtiff is left over.
+        let packages = vec![
+            formula(
+                "ffmpeg",
+                json!({"dependencies": ["x264", "libpng"], "installed": [installed("6.1", true)]}),
+            ),
+            formula(
+                "imagemagick",
+                json!({"dependencies": ["freetype"], "installed": [installed("7.1", true)]}),
+            ),
+            formula(
+                "freetype",
+                json!({"dependencies": ["libpng"], "installed": [installed("2.13", false)]}),
+            ),
+            formula("x264", json!({"installed": [installed("r3108", false)]})),
+            formula("libpng", json!({"installed": [installed("1.6", false)]})),
+            formula("libtiff", json!({"installed": [installed("4.6", false)]})),
+            formula("jq", json!({})),
+        ];
+        let installed: HashMap<String, Package> =
+            packages.into_iter().map(|p| (p.name.clone(), p)).collect();
+        let reason = |name: &str| installed[name].install_reason_in(&installed);
+        assert_eq!(reason("ffmpeg"), InstallReason::OnRequest);
+        assert_eq!(reason("jq"), InstallReason::NotInstalled);
+        assert_eq!(
+            reason("libpng"),
+            InstallReason::Dependency {
+                chains: vec![
+                    vec!["ffmpeg".to_owned()],
+                    vec!["imagemagick".to_owned(), "freetype".to_owned()],
+                ]
+            }
+        );
+        assert_eq!(
+            reason("libtiff"),
+            InstallReason::Dependency { chains: Vec::new() }
+        );
+    }
+
+    #[test]
+    fn keeps_the_shortest_chain() {
+        // gdal reaches libpng through both libtiff and proj, which itself
+        // goes through libtiff.
+        let packages = vec![
+            formula(
+                "gdal",
+                json!({"dependencies": ["proj", "libtiff"], "installed": [installed("3.8", true)]}),
+            ),
+            formula(
+                "proj",
+                json!({"dependencies": ["libtiff"], "installed": [installed("9.3", false)]}),
+            ),
+            formula(
+                "libtiff",
+                json!({"dependencies": ["libpng"], "installed": [installed("4.6", false)]}),
+            ),
+            formula("libpng", json!({"installed": [installed("1.6", false)]})),
+        ];
+        let installed: HashMap<String, Package> =
+            packages.into_iter().map(|p| (p.name.clone(), p)).collect();
+        assert_eq!(
+            installed["libpng"].install_reason_in(&installed),
+            InstallReason::Dependency {
+                chains: vec![vec!["gdal".to_owned(), "libtiff".to_owned()]]
+            }
+        );
+    }
+
+    #[test]
+    fn prefers_runtime_dependencies() {
+        // ffmpeg declares x264, but was installed against a tap's x265
+        // instead.
+        let mut ffmpeg = installed("6.1", true);
+        ffmpeg["runtime_dependencies"] = json!([{"full_name": "user/tap/x265", "version": "3.5"}]);
+        let packages = vec![
+            formula(
+                "ffmpeg",
+                json!({"dependencies": ["x264"], "installed": [ffmpeg]}),
+            ),
+            formula("x264", json!({"installed": [installed("r3108", false)]})),
+            formula("x265", json!({"installed": [installed("3.5", false)]})),
+        ];
+        let installed: HashMap<String, Package> =
+            packages.into_iter().map(|p| (p.name.clone(), p)).collect();
+        let reason = |name: &str| installed[name].install_reason_in(&installed);
+        assert_eq!(
+            reason("x265"),
+            InstallReason::Dependency {
+                chains: vec![vec!["ffmpeg".to_owned()]]
+            }
+        );
+        assert_eq!(
+            reason("x264"),
+            InstallReason::Dependency { chains: Vec::new() }
+        );
+    }
+}
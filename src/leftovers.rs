@@ -0,0 +1,142 @@
+//! Finding files a formula or cask leaves behind once uninstalled.
+use crate::cask::{strings, Cask};
+use crate::{cellar, prefix, Package, Result};
+use std::path::{Path, PathBuf};
+
+impl Package {
+    /// Lists the files left in the prefix for the formula, such as a
+    /// dangling opt link or data in `var`, to offer to delete after it has
+    /// been uninstalled.
+    pub fn scan_leftovers(&self) -> Result<Vec<PathBuf>> {
+        let prefix = prefix()?;
+        let candidates = [
+            cellar()?.join(&self.name),
+            prefix.join("opt").join(&self.name),
+            prefix.join("var").join(&self.name),
+            prefix.join("var/log").join(&self.name),
+            prefix.join("etc").join(&self.name),
+            prefix.join("var/homebrew/linked").join(&self.name),
+            prefix.join("var/homebrew/pinned").join(&self.name),
+        ];
+        Ok(candidates.iter().filter(|p| exists(p)).cloned().collect())
+    }
+}
+
+impl Cask {
+    /// Lists the files left for the cask: its Caskroom directory, apps it
+    /// installed, and the paths its `zap` stanza would trash or delete.
+    pub fn scan_leftovers(&self) -> Result<Vec<PathBuf>> {
+        let mut found = Vec::new();
+        let caskroom = prefix()?.join("Caskroom").join(&self.token);
+        if exists(&caskroom) {
+            found.push(caskroom);
+        }
+        for app in self.artifact_strings("app") {
+            let path = Path::new("/Applications").join(app);
+            if exists(&path) {
+                found.push(path);
+            }
+        }
+        for path in self.zap().iter().flat_map(|zap| zap.paths()) {
+            found.extend(expand(path));
+        }
+        Ok(found)
+    }
+
+    /// The strings of every `kind` artifact, such as the app names of `app`.
+    fn artifact_strings(&self, kind: &str) -> Vec<String> {
+        self.artifacts
+            .iter()
+            .flat_map(|a| strings(a.get(kind)))
+            .collect()
+    }
+}
+
+/// Whether there is a file at `path`, counting dangling symlinks.
+fn exists(path: &Path) -> bool {
+    path.symlink_metadata().is_ok()
+}
+
+/// The existing files matching `path`, after expanding a leading `~` and
+/// `*` in the last component.
+fn expand(path: &str) -> Vec<PathBuf> {
+    let path = match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    };
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) if name.contains('*') => name.to_owned(),
+        _ => {
+            return if exists(&path) {
+                vec![path]
+            } else {
+                Vec::new()
+            }
+        }
+    };
+    let parent = path.parent().unwrap_or_else(|| Path::new("/"));
+    let mut found: Vec<PathBuf> = std::fs::read_dir(parent)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .filter(|e| matches_glob(&name, &e.file_name().to_string_lossy()))
+        .map(|e| e.path())
+        .collect();
+    found.sort();
+    found
+}
+
+/// Matches `name` against `pattern`, where `*` matches any run of
+/// characters.
+fn matches_glob(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match name.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_zap_paths() {
+        let cask: Cask = serde_json::from_value(serde_json::json!({
+            "token": "firefox",
+            "full_token": "firefox",
+            "version": "121.0",
+            "artifacts": [
+                {"app": ["Firefox.app"]},
+                {"zap": [{
+                    "trash": ["~/Library/Caches/Firefox", "~/Library/Preferences/org.mozilla.firefox*.plist"],
+                    "rmdir": "~/Library/Application Support/Mozilla",
+                }]},
+            ],
+        }))
+        .unwrap();
+        assert_eq!(cask.zap().unwrap().paths().count(), 3);
+        assert_eq!(cask.artifact_strings("app"), vec!["Firefox.app"]);
+        assert!(matches_glob(
+            "org.mozilla.firefox*.plist",
+            "org.mozilla.firefox.menubar.plist"
+        ));
+        assert!(!matches_glob(
+            "org.mozilla.firefox*.plist",
+            "org.mozilla.thunderbird.plist"
+        ));
+        assert!(matches_glob("Firefox", "Firefox"));
+    }
+}
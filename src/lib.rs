@@ -1,17 +1,107 @@
+use analytics::{AnalyticsWindow, VariantCount};
+use command::brew;
 use command_builder::{Command, Single};
+use platform::{Arch, MacOsVersion};
 use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::str::FromStr;
 
-fn brew_return(command: command_builder::Output, name: &str) -> Result<Package> {
+pub mod advisories;
+pub mod analytics;
+pub mod api;
+pub mod bottle;
+pub mod build_logs;
+pub mod build_support;
+pub mod bump;
+pub mod bundle;
+pub mod cask;
+pub mod cleanup;
+mod command;
+pub mod deps;
+pub mod disk_usage;
+pub mod doctor;
+pub mod exact_version;
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
+pub mod formula_gen;
+pub mod formula_name;
+pub mod formula_source;
+pub mod github;
+pub mod install_plan;
+pub mod install_reason;
+pub mod leftovers;
+pub mod link;
+pub mod maintenance;
+pub mod manifest;
+pub mod offline;
+pub mod outdated;
+pub mod platform;
+pub mod plist;
+#[cfg(feature = "pour")]
+pub mod pour;
+pub mod progress;
+#[cfg(feature = "indicatif")]
+pub mod progress_bars;
+pub mod receipt;
+pub mod search;
+pub mod search_index;
+pub mod services;
+pub mod snapshot;
+pub mod system;
+pub mod tap;
+#[cfg(feature = "tarball")]
+pub mod tarball;
+pub mod uninstall;
+pub mod upgrade_summary;
+pub mod uses;
+
+fn brew_return(command: command::Output, name: &str) -> Result<Package> {
     if command.success() {
         Ok(Package::new(name)?)
     } else {
-        test_brew_installed()?;
-        Err(Error::UnknownError(command.stderr().to_owned()))
+        Err(brew_error(&command, Error::UnknownError))
     }
 }
 
+/// For internal use, the error for a brew command that did not succeed.
+///
+/// `error` is used when there is no more specific cause.
+fn brew_error(output: &command::Output, error: fn(Failure) -> Error) -> Error {
+    let stderr = output.stderr();
+    let failure = output.failure();
+    if is_locked(stderr) {
+        Error::Locked(failure)
+    } else if is_rate_limited(stderr) {
+        Error::RateLimited(failure)
+    } else if let Some(path) = tap::corrupted_tap(stderr) {
+        Error::TapCorrupted(path, failure)
+    } else if stderr.contains("No available formula")
+        || stderr.contains("No formulae found")
+        || stderr.contains("No Cask with this name")
+    {
+        Error::PackageNotFound(failure)
+    } else if stderr.contains("No such keg") {
+        Error::NotInstalled(failure)
+    } else if stderr.contains("is already installed") {
+        Error::AlreadyInstalled(failure)
+    } else if let Err(e) = test_brew_installed() {
+        e
+    } else {
+        error(failure)
+    }
+}
+
+/// Checks if brew failed because another brew process holds a lock.
+fn is_locked(stderr: &str) -> bool {
+    stderr.contains("has already locked") || stderr.contains("process is already in progress")
+}
+
+/// Checks if brew failed because it hit the GitHub API rate limit.
+fn is_rate_limited(stderr: &str) -> bool {
+    stderr.contains("API rate limit exceeded")
+}
+
 /// Represents a string which might be a version number for Homebrew.
 /// Homebrew has requirements for version strings, so it is not possible
 /// to definitively parse it.
@@ -35,9 +125,21 @@ impl Version {
 
 /// Represents a Homebrew package, which may or may not be installed.
 #[derive(Deserialize, Serialize, Clone, Debug)]
+// The derived impls are wrapped by the ones below, to read
+// `uses_from_macos_bounds` into `MacOsDependency::since` and back.
+#[serde(remote = "Self")]
+#[cfg_attr(
+    all(feature = "strict-schema", not(feature = "preserve-unknown")),
+    serde(deny_unknown_fields)
+)]
 pub struct Package {
     pub name: String,
     pub full_name: String,
+    /// The tap the formula comes from, such as `homebrew/core`.
+    pub tap: Option<String>,
+    /// The commit of the tap the metadata was read from. brew only reports
+    /// this for taps it has cloned, or for metadata from the JSON API.
+    pub tap_git_head: Option<String>,
     pub aliases: Vec<String>,
     pub oldname: Option<String>,
     pub desc: Option<String>,
@@ -48,63 +150,481 @@ pub struct Package {
     pub version_scheme: usize,
     pub bottle: HashMap<String, Bottle>,
     pub keg_only: bool,
+    #[serde(default)]
     pub bottle_disabled: bool,
     pub options: Vec<BrewOption>,
     pub build_dependencies: Vec<String>,
     pub dependencies: Vec<String>,
     pub recommended_dependencies: Vec<String>,
     pub optional_dependencies: Vec<String>,
-    pub uses_from_macos: Vec<MapOrString>,
-    pub requirements: Vec<Requirment>,
+    #[serde(default)]
+    pub test_dependencies: Vec<String>,
+    /// Dependencies macOS provides, with `since` read from the
+    /// `uses_from_macos_bounds` brew reports alongside them.
+    pub uses_from_macos: Vec<MacOsDependency>,
+    /// Only read while deserializing, and written from `uses_from_macos`.
+    #[serde(default, getter = "Package::macos_bounds")]
+    uses_from_macos_bounds: Vec<MacOsBound>,
+    pub requirements: Vec<Requirement>,
     pub conflicts_with: Vec<String>,
     pub caveats: Option<String>,
+    /// The formula's `service` block, as brew reports it, if it runs as a
+    /// service.
+    #[serde(default)]
+    pub service: Option<serde_json::Value>,
     pub installed: Vec<Installed>,
     pub linked_keg: Option<String>,
     pub pinned: bool,
     pub outdated: bool,
+    /// Whether the formula is deprecated, and will be disabled.
+    #[serde(default)]
+    pub deprecated: bool,
+    pub deprecation_date: Option<String>,
+    /// Why the formula is deprecated, such as `unmaintained`.
+    pub deprecation_reason: Option<String>,
+    /// Whether the formula is disabled, and can no longer be installed.
+    #[serde(default)]
+    pub disabled: bool,
+    pub disable_date: Option<String>,
+    pub disable_reason: Option<String>,
+    /// The path of the formula file in its tap, such as `Formula/j/jq.rb`.
+    pub ruby_source_path: Option<String>,
+    /// The checksum of the formula file.
+    pub ruby_source_checksum: Option<Checksum>,
     pub analytics: Option<Analytics>,
+    #[cfg(feature = "preserve-unknown")]
+    /// Fields brew reported that this crate does not know about, kept so
+    /// that serializing a package again loses nothing.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
-#[serde(untagged)]
-pub enum MapOrString {
-    MapStringString(HashMap<String, String>),
-    String(String),
-    MapStringVecString(HashMap<String, Vec<String>>),
+/// A dependency macOS provides itself, so it is only needed on Linux, and
+/// on macOS older than `since`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MacOsDependency {
+    pub name: String,
+    /// The first macOS version that provides the dependency, or `None` if
+    /// every version does.
+    pub since: Option<MacOsVersion>,
+    /// The kinds of dependency, such as `build` or `test`, or empty for a
+    /// runtime dependency.
+    pub dep_type: Vec<String>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+impl MacOsDependency {
+    /// Checks if the dependency is needed at runtime, rather than only to
+    /// build or test.
+    pub fn is_runtime(&self) -> bool {
+        self.dep_type.is_empty() || self.dep_type.iter().any(|t| t != "build" && t != "test")
+    }
+}
+
+impl<'de> Deserialize<'de> for MacOsDependency {
+    /// Reads a name such as `"zlib"`, or a name with its kinds such as
+    /// `{"llvm": "build"}` or `{"python": ["build", "test"]}`.
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Kinds {
+            One(String),
+            Many(Vec<String>),
+        }
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Name(String),
+            Typed(HashMap<String, Kinds>),
+        }
+        let (name, dep_type) = match Raw::deserialize(d)? {
+            Raw::Name(name) => (name, Vec::new()),
+            Raw::Typed(map) => match map.into_iter().next() {
+                Some((name, Kinds::One(kind))) => (name, vec![kind]),
+                Some((name, Kinds::Many(kinds))) => (name, kinds),
+                None => return Err(serde::de::Error::custom("empty uses_from_macos entry")),
+            },
+        };
+        Ok(MacOsDependency {
+            name,
+            since: None,
+            dep_type,
+        })
+    }
+}
+
+impl Serialize for MacOsDependency {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        if self.dep_type.is_empty() {
+            return s.serialize_str(&self.name);
+        }
+        let mut map = s.serialize_map(Some(1))?;
+        match self.dep_type.as_slice() {
+            [kind] => map.serialize_entry(&self.name, kind)?,
+            kinds => map.serialize_entry(&self.name, kinds)?,
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Package {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        let mut package = Package::deserialize(d)?;
+        let bounds = std::mem::take(&mut package.uses_from_macos_bounds);
+        for (dependency, bound) in package.uses_from_macos.iter_mut().zip(bounds) {
+            dependency.since = bound.since.as_deref().and_then(|v| v.parse().ok());
+        }
+        Ok(package)
+    }
+}
+
+impl Serialize for Package {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        Package::serialize(self, s)
+    }
+}
+
+/// The bounds of a `uses_from_macos` dependency, as brew reports them.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    all(feature = "strict-schema", not(feature = "preserve-unknown")),
+    serde(deny_unknown_fields)
+)]
+struct MacOsBound {
+    /// The codename of the first macOS version that provides the
+    /// dependency, such as `catalina`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    since: Option<String>,
+}
+
+/// The revision a `Url` checks out from version control.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 #[serde(untagged)]
-pub enum NumOrString {
-    Num(u32),
-    String(String),
+pub enum UrlRevision {
+    /// A Subversion revision number.
+    Number(u64),
+    /// A git commit, or another version control system's revision.
+    Commit(String),
+}
+
+impl std::fmt::Display for UrlRevision {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UrlRevision::Number(n) => write!(f, "{}", n),
+            UrlRevision::Commit(commit) => f.write_str(commit),
+        }
+    }
 }
 
+/// Something other than a formula that a formula needs, such as a minimum
+/// macOS version.
+///
+/// `build` is set for requirements only needed to build from source.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(from = "RawRequirement", into = "RawRequirement")]
+pub enum Requirement {
+    /// macOS, at least `minimum` if set.
+    MacOs {
+        minimum: Option<MacOsVersion>,
+        build: bool,
+    },
+    /// macOS no newer than `maximum`.
+    MaximumMacOs {
+        maximum: MacOsVersion,
+        build: bool,
+    },
+    /// Xcode, at least `minimum` if set, such as `14.3`.
+    Xcode {
+        minimum: Option<String>,
+        build: bool,
+    },
+    Arch(Arch),
+    Linux,
+    /// A requirement this crate does not know, as brew reported it.
+    Other {
+        name: String,
+        cask: Option<String>,
+        download: Option<String>,
+        version: Option<String>,
+        contexts: Vec<String>,
+        #[cfg(feature = "preserve-unknown")]
+        /// Unknown fields, as for `Package::extra`.
+        extra: serde_json::Map<String, serde_json::Value>,
+    },
+}
+
+/// A requirement as brew reports it.
 #[derive(Deserialize, Serialize, Clone, Debug)]
-pub struct Requirment {
+#[cfg_attr(
+    all(feature = "strict-schema", not(feature = "preserve-unknown")),
+    serde(deny_unknown_fields)
+)]
+struct RawRequirement {
     name: String,
     cask: Option<String>,
     download: Option<String>,
-    version: Option<VersionResult>,
+    version: Option<String>,
     contexts: Vec<String>,
+    #[cfg(feature = "preserve-unknown")]
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl From<RawRequirement> for Requirement {
+    fn from(raw: RawRequirement) -> Self {
+        let build = raw.contexts.iter().any(|c| c == "build");
+        #[cfg(feature = "preserve-unknown")]
+        let known = raw.extra.is_empty();
+        #[cfg(not(feature = "preserve-unknown"))]
+        let known = true;
+        // Requirements with anything the typed ones cannot hold are kept as
+        // `Other`, so they serialize as brew reported them.
+        let typed = known
+            && raw.cask.is_none()
+            && raw.download.is_none()
+            && raw.contexts.iter().all(|c| c == "build");
+        let requirement = match (raw.name.as_str(), raw.version.as_deref()) {
+            _ if !typed => None,
+            ("macos", None) => Some(Requirement::MacOs {
+                minimum: None,
+                build,
+            }),
+            ("macos", Some(v)) => v.parse().ok().map(|minimum| Requirement::MacOs {
+                minimum: Some(minimum),
+                build,
+            }),
+            ("maximum_macos", Some(v)) => v
+                .parse()
+                .ok()
+                .map(|maximum| Requirement::MaximumMacOs { maximum, build }),
+            ("xcode", v) => Some(Requirement::Xcode {
+                minimum: v.map(str::to_owned),
+                build,
+            }),
+            ("arch", Some(v)) if !build => v.parse().ok().map(Requirement::Arch),
+            ("linux", None) if !build => Some(Requirement::Linux),
+            _ => None,
+        };
+        requirement.unwrap_or(Requirement::Other {
+            name: raw.name,
+            cask: raw.cask,
+            download: raw.download,
+            version: raw.version,
+            contexts: raw.contexts,
+            #[cfg(feature = "preserve-unknown")]
+            extra: raw.extra,
+        })
+    }
+}
+
+impl From<Requirement> for RawRequirement {
+    fn from(requirement: Requirement) -> Self {
+        let raw = |name: &str, version: Option<String>, build: bool| RawRequirement {
+            name: name.to_owned(),
+            cask: None,
+            download: None,
+            version,
+            contexts: if build {
+                vec!["build".to_owned()]
+            } else {
+                Vec::new()
+            },
+            #[cfg(feature = "preserve-unknown")]
+            extra: serde_json::Map::new(),
+        };
+        match requirement {
+            Requirement::MacOs { minimum, build } => {
+                raw("macos", minimum.map(|v| v.number().to_owned()), build)
+            }
+            Requirement::MaximumMacOs { maximum, build } => {
+                raw("maximum_macos", Some(maximum.number().to_owned()), build)
+            }
+            Requirement::Xcode { minimum, build } => raw("xcode", minimum, build),
+            Requirement::Arch(arch) => raw("arch", Some(arch.as_str().to_owned()), false),
+            Requirement::Linux => raw("linux", None, false),
+            Requirement::Other {
+                name,
+                cask,
+                download,
+                version,
+                contexts,
+                #[cfg(feature = "preserve-unknown")]
+                extra,
+            } => RawRequirement {
+                name,
+                cask,
+                download,
+                version,
+                contexts,
+                #[cfg(feature = "preserve-unknown")]
+                extra,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    all(feature = "strict-schema", not(feature = "preserve-unknown")),
+    serde(deny_unknown_fields)
+)]
+pub struct Checksum {
+    pub sha256: String,
+    #[cfg(feature = "preserve-unknown")]
+    /// Unknown fields, as for `Package::extra`.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(
+    all(feature = "strict-schema", not(feature = "preserve-unknown")),
+    serde(deny_unknown_fields)
+)]
 pub struct BrewOption {
     option: String,
     description: String,
+    #[cfg(feature = "preserve-unknown")]
+    /// Unknown fields, as for `Package::extra`.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+pub use command::{
+    api_domain, auto_update, bottle_domain, child_output, github_token, isolated, locale, proxy,
+    set_api_domain, set_auto_update, set_bottle_domain, set_child_output, set_github_token,
+    set_isolated, set_locale, set_metrics, set_proxy, set_verbosity, verbosity, AutoUpdate, Brew,
+    ChildOutput, CommandMetrics, DownloadMetrics, ExitStatus, Metrics, Proxy, Timing, Verbosity,
+    DEFAULT_API_DOMAIN, DEFAULT_BOTTLE_DOMAIN,
+};
+#[cfg(feature = "pty")]
+pub use command::{pty, set_pty};
+
 #[derive(Debug)]
 pub enum Error {
-    NotInstalled,
-    PackageNotFound,
+    /// brew, or the package, is not installed. The failure is empty unless a
+    /// brew command reported it.
+    NotInstalled(Failure),
+    /// The formula or cask does not exist. The failure is empty unless a
+    /// brew command reported it.
+    PackageNotFound(Failure),
     IOError(std::io::Error),
     ParseError(serde_json::Error),
-    InstallFailed(String),
-    UnknownError(String),
+    InstallFailed(Failure),
+    /// The package is already installed, and brew refused to install it again.
+    AlreadyInstalled(Failure),
+    /// A Homebrew API cache file was not a well formed JWS file with a
+    /// signature.
+    InvalidApiCache(std::path::PathBuf),
+    /// A file was not a well formed XML property list.
+    InvalidPlist(std::path::PathBuf),
+    /// A file did not match its expected SHA-256 checksum.
+    ChecksumMismatch(std::path::PathBuf),
+    /// A bottle's build provenance attestation could not be verified.
+    AttestationFailed(Failure),
+    /// A file was not a bottle tarball holding a single keg.
+    InvalidBottle(std::path::PathBuf),
+    /// A bottle cannot be poured without brew, for the given reason.
+    UnsupportedBottle(String),
+    /// The named formula is needed, but is not installed.
+    MissingFormula(String),
+    /// A formula or cask name was not valid, for the given reason.
+    InvalidName(String),
+    /// The name is both a formula and a cask, so which was meant is not
+    /// known. Look it up with `Package::new` or `Cask::new` instead.
+    AmbiguousName(String),
+    /// Package options were given for the named formula, which does not
+    /// declare them. homebrew/core formulae no longer accept any.
+    OptionsUnsupported(String),
+    /// `DepsOptions`, named, were given to a lookup that reads a package's
+    /// info, but need brew to find dependencies. See `Package::deps`.
+    DepsOptionsUnsupported(String),
+    /// A manifest could not be read or written, for the given reason.
+    InvalidManifest(String),
+    /// Another brew process holds a lock needed by the command.
+    Locked(Failure),
+    /// The git checkout of a tap at the path is a shallow clone brew will not
+    /// update, or is broken. See `Tap::unshallow` and `Tap::reclone`. The
+    /// failure is that of the brew or git command that found it, if any.
+    TapCorrupted(std::path::PathBuf, Failure),
+    /// GitHub refused a request because its API rate limit was hit. Set a
+    /// token with `set_github_token` for a higher limit.
+    RateLimited(Failure),
+    UnknownError(Failure),
+}
+
+impl Error {
+    /// How the failed brew command exited, if the error came from one.
+    pub fn status(&self) -> Option<ExitStatus> {
+        match self {
+            Error::NotInstalled(f)
+            | Error::PackageNotFound(f)
+            | Error::InstallFailed(f)
+            | Error::AlreadyInstalled(f)
+            | Error::AttestationFailed(f)
+            | Error::Locked(f)
+            | Error::TapCorrupted(_, f)
+            | Error::RateLimited(f)
+            | Error::UnknownError(f) => f.status,
+            _ => None,
+        }
+    }
+}
+
+/// Describes why a brew command failed.
+#[derive(Clone, Debug, Default)]
+pub struct Failure {
+    /// What went wrong, usually brew's stderr.
+    pub message: String,
+    /// How brew exited, if the failure was reported by its exit status.
+    pub status: Option<ExitStatus>,
+    /// brew's stdout, exactly as written, which may not be UTF-8.
+    pub stdout: Vec<u8>,
+    /// brew's stderr, exactly as written, which may not be UTF-8.
+    pub stderr: Vec<u8>,
+    /// The command that failed, if the failure was reported by one.
+    pub command: Option<Box<Invocation>>,
+}
+
+/// A command that was run, as reported in a `Failure`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Invocation {
+    /// The arguments, starting with the program, such as
+    /// `["/opt/homebrew/bin/brew", "install", "jq"]`.
+    pub argv: Vec<String>,
+    /// The working directory the command was run in.
+    pub dir: Option<PathBuf>,
+}
+
+impl Invocation {
+    /// The command, quoted for a POSIX shell so it can be pasted into a
+    /// terminal to reproduce the failure.
+    pub fn command_line(&self) -> String {
+        shell_words(&self.argv)
+    }
+}
+
+/// For internal use, joins `words` with spaces, quoting those a POSIX shell
+/// would split or expand.
+fn shell_words<S: AsRef<str>>(words: &[S]) -> String {
+    words
+        .iter()
+        .map(|word| {
+            let word = word.as_ref();
+            let plain = !word.is_empty()
+                && word
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c));
+            if plain {
+                word.to_owned()
+            } else {
+                format!("'{}'", word.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 impl From<std::io::Error> for Error {
@@ -119,6 +639,56 @@ impl From<serde_json::Error> for Error {
     }
 }
 
+/// Calls `f` until it does not fail with `Error::Locked`, giving up once
+/// `timeout` has passed.
+///
+/// ``` no_run
+/// # use std::time::Duration;
+/// # fn main() -> brew::Result<()> {
+/// let jq = brew::Package::new("jq")?;
+/// let jq = brew::retry_while_locked(Duration::from_secs(300), || jq.upgrade())?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn retry_while_locked<T, F>(timeout: std::time::Duration, mut f: F) -> Result<T>
+where
+    F: FnMut() -> Result<T>,
+{
+    let start = std::time::Instant::now();
+    loop {
+        match f() {
+            Err(Error::Locked(_)) if start.elapsed() < timeout => {
+                std::thread::sleep(std::time::Duration::from_secs(1))
+            }
+            result => return result,
+        }
+    }
+}
+
+/// For internal use, reads the last `n` lines of the file at `path` without
+/// reading the whole file.
+fn tail_lines(path: &std::path::Path, n: usize) -> std::io::Result<Vec<String>> {
+    use std::io::{Read, Seek, SeekFrom};
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut start = len;
+    let mut buf = Vec::new();
+    // Read backwards until there are enough newlines, ignoring a trailing one.
+    while start > 0 && buf.iter().filter(|&&b| b == b'\n').count() <= n {
+        let chunk = start.min(8192);
+        start -= chunk;
+        file.seek(SeekFrom::Start(start))?;
+        let mut bytes = vec![0; chunk as usize];
+        file.read_exact(&mut bytes)?;
+        bytes.extend(buf);
+        buf = bytes;
+    }
+    let text = String::from_utf8_lossy(&buf);
+    let lines: Vec<&str> = text.lines().collect();
+    let skip = lines.len().saturating_sub(n);
+    Ok(lines[skip..].iter().map(|s| s.to_string()).collect())
+}
+
 fn contains<I, J, E>(iter1: I, iter2: J) -> bool
 where
     I: IntoIterator<Item = E>,
@@ -136,67 +706,126 @@ where
 
 impl Package {
     /// Creates package, filling out struct from the command line toole.
+    ///
+    /// `name` may be qualified with its tap, as in `user/tap/formula`, which
+    /// must be tapped. `Package::tap` is set to the tap the formula came
+    /// from.
     pub fn new(name: &str) -> Result<Package> {
-        let output = Single::new("/usr/local/bin/brew")
-            .arg("info")
-            .arg(name)
-            .arg("--json=v1")
-            .env("HOMEBREW_NO_AUTO_UPDATE", "1")
-            .run()?;
+        let output = brew(&["info", name, json_flag()?])?;
         if output.success() {
-            let packages: Vec<Package> = serde_json::from_str(output.stdout())?;
-            packages
+            let mut package = parse_packages(output.stdout())?
                 .into_iter()
                 .next()
-                .map(Result::Ok)
-                .unwrap_or(Err(Error::PackageNotFound))
+                .ok_or_else(|| Error::PackageNotFound(Failure::default()))?;
+            // Older brews do not report the tap, but it is known from the
+            // full name of formulae outside homebrew/core.
+            if package.tap.is_none() {
+                package.tap = search::split_tap(&package.full_name).0.map(str::to_owned);
+            }
+            Ok(package)
         } else {
-            test_brew_installed()?;
-            Err(Error::PackageNotFound)
+            Err(brew_error(&output, Error::PackageNotFound))
+        }
+    }
+
+    /// Creates the package as `Package::new` does, but first taps the tap of
+    /// a tap-qualified name, such as `user/tap` of `user/tap/formula`, if it
+    /// is not tapped.
+    pub fn new_tapping(name: &str) -> Result<Package> {
+        if let (Some(tap), _) = search::split_tap(name) {
+            if !tap::taps()?
+                .iter()
+                .any(|t| t.name.eq_ignore_ascii_case(tap))
+            {
+                tap::tap(tap, &tap::TapOptions::new())?;
+            }
         }
+        Self::new(name)
+    }
+
+    /// Checks if the formula has no stable version, so can only be installed
+    /// from HEAD. Installing it adds `--HEAD` automatically.
+    pub fn is_head_only(&self) -> bool {
+        self.versions.stable.is_none() && self.versions.head.is_some()
+    }
+
+    /// The bounds brew reports for `uses_from_macos`, from their `since`.
+    fn macos_bounds(&self) -> Vec<MacOsBound> {
+        self.uses_from_macos
+            .iter()
+            .map(|dependency| MacOsBound {
+                since: dependency.since.map(|v| v.codename().to_owned()),
+            })
+            .collect()
+    }
+
+    /// Fetches the package's info again, updating it in place, such as after
+    /// installing or upgrading it.
+    pub fn refresh(&mut self) -> Result<()> {
+        *self = Self::new(&self.full_name)?;
+        Ok(())
     }
 
     /// Attempts to install a package, reinstalling a package if it is already installed.
     pub fn install(&self, options: &Options) -> Result<Package> {
-        let command = Single::new("brew")
-            .arg(if self.is_installed() && options.force {
-                "reinstall"
-            } else if self.is_installed() {
-                let opts = self.install_options().unwrap();
-                if contains(opts, options.package_options()) {
-                    return Self::new(&self.name);
-                } else {
-                    "reinstall"
-                }
+        self.install_using(options, |args| brew(args))
+    }
+
+    /// For internal use, installs the package running brew with `run`.
+    fn install_using<F>(&self, options: &Options, run: F) -> Result<Package>
+    where
+        F: FnOnce(&[&str]) -> Result<command::Output>,
+    {
+        let requested = options.package_options();
+        if !requested.is_empty() && !self.supports_options(requested) {
+            return Err(Error::OptionsUnsupported(self.full_name.clone()));
+        }
+        let mut args = vec![if self.is_installed() && options.force {
+            "reinstall"
+        } else if self.is_installed() {
+            let opts = self.install_options().unwrap();
+            if requested.is_empty() || contains(opts, requested) {
+                return Self::new(&self.full_name);
             } else {
-                "install"
-            })
-            .args(options.brew_options().as_slice())
-            .arg(&self.name)
-            .args(
-                &options
-                    .package_options()
-                    .iter()
-                    .map(|f| f.as_str())
-                    .collect::<Vec<_>>(),
-            )
-            .env("HOMEBREW_NO_AUTO_UPDATE", "1")
-            .run()?;
+                "reinstall"
+            }
+        } else {
+            "install"
+        }];
+        args.extend(options.brew_options());
+        if self.is_head_only() && !options.head {
+            args.push("--HEAD");
+        }
+        // The full name, so a formula from a tap is not mistaken for one of
+        // the same name in homebrew/core.
+        args.push(&self.full_name);
+        args.extend(options.package_options().iter().map(|f| f.as_str()));
+        let command = run(&args)?;
         if command.success() {
-            let new = Self::new(&self.name)?;
+            let new = Self::new(&self.full_name)?;
             if new.is_installed() {
                 Ok(new)
             } else {
-                Err(Error::InstallFailed(
-                    "Could not detect new install".to_owned(),
-                ))
+                Err(Error::InstallFailed(Failure {
+                    message: "Could not detect new install".to_owned(),
+                    ..Failure::default()
+                }))
             }
         } else {
-            test_brew_installed()?;
-            Err(Error::InstallFailed(command.stderr().to_owned()))
+            Err(brew_error(&command, Error::InstallFailed))
         }
     }
 
+    /// Checks if the formula can be installed with the package options
+    /// `requested`, such as `--with-openssl`: it must declare them, and not
+    /// come from homebrew/core, which dropped options.
+    pub fn supports_options(&self, requested: &[String]) -> bool {
+        !self.tap_name().eq_ignore_ascii_case("homebrew/core")
+            && requested
+                .iter()
+                .all(|r| self.options.iter().any(|o| &o.option == r))
+    }
+
     /// Check if a package is installed.
     pub fn is_installed(&self) -> bool {
         !self.installed.is_empty()
@@ -209,31 +838,52 @@ impl Package {
             .map(|i: &Installed| i.used_options.as_slice())
     }
 
+    /// The `opt/<name>` path of the package, as printed by
+    /// `brew --prefix <name>`.
+    ///
+    /// This stays the same across upgrades, and exists for keg-only formulae
+    /// too, so is the path to build against. It only exists once the
+    /// package is installed.
+    pub fn opt_prefix(&self) -> Result<PathBuf> {
+        Ok(prefix()?.join("opt").join(&self.name))
+    }
+
+    /// The path of the active keg, such as `Cellar/openssl@3/3.2.0`, or
+    /// `None` if the package is not installed.
+    ///
+    /// The active keg is the one `opt/<name>` points to, falling back to the
+    /// linked keg and then the last installed version.
+    pub fn installed_prefix(&self) -> Result<Option<PathBuf>> {
+        if let Ok(keg) = std::fs::canonicalize(self.opt_prefix()?) {
+            return Ok(Some(keg));
+        }
+        let version = match (&self.linked_keg, self.installed.last()) {
+            (Some(linked), _) => linked.as_str(),
+            (None, Some(installed)) => installed.version.original(),
+            (None, None) => return Ok(None),
+        };
+        let keg = cellar()?.join(&self.name).join(version);
+        Ok(if keg.is_dir() { Some(keg) } else { None })
+    }
+
     /// Uninstalls the package.
     pub fn uninstall(&self, force: bool, ignore_dependencies: bool) -> Result<Package> {
-        let mut args = vec!["uninstall", &self.name];
+        let mut args = vec!["uninstall", &self.full_name];
         if force {
             args.push("--force");
         }
         if ignore_dependencies {
             args.push("--ignore-dependencies");
         }
-        let command = Single::new("brew")
-            .args(args)
-            .env("HOMEBREW_NO_AUTO_UPDATE", "1")
-            .run()?;
-        brew_return(command, &self.name)
+        let command = brew(&args)?;
+        brew_return(command, &self.full_name)
     }
 
     /// Pin forumla to prevent automatic updates/upgrades.
     pub fn pin(&self) -> Result<Package> {
         if !self.pinned {
-            let command = Single::new("brew")
-                .arg("pin")
-                .arg(&self.name)
-                .env("HOMEBREW_NO_AUTO_UPDATE", "1")
-                .run()?;
-            brew_return(command, &self.name)
+            let command = brew(&["pin", &self.full_name])?;
+            brew_return(command, &self.full_name)
         } else {
             Ok(self.clone())
         }
@@ -242,12 +892,8 @@ impl Package {
     /// Unpin formula to allow automatic updates/upgrades.
     pub fn unpin(&self) -> Result<Package> {
         if self.pinned {
-            let command = Single::new("brew")
-                .arg("unpin")
-                .arg(&self.name)
-                .env("HOMEBREW_NO_AUTO_UPDATE", "1")
-                .run()?;
-            brew_return(command, &self.name)
+            let command = brew(&["unpin", &self.full_name])?;
+            brew_return(command, &self.full_name)
         } else {
             Ok(self.clone())
         }
@@ -256,104 +902,400 @@ impl Package {
     /// Upgrade formula.
     pub fn upgrade(&self) -> Result<Package> {
         if self.is_installed() {
-            let command = Single::new("brew")
-                .arg("upgrade")
-                .arg(&self.name)
-                .env("HOMEBREW_NO_AUTO_UPDATE", "1")
-                .run()?;
-            brew_return(command, &self.name)
+            let command = brew(&["upgrade", &self.full_name])?;
+            brew_return(command, &self.full_name)
         } else {
-            Err(Error::NotInstalled)
+            Err(Error::NotInstalled(Failure::default()))
         }
     }
 }
 
 /// Update homebrew, synchronizing the homebrew-core and package list.
-pub fn update() -> Result<()> {
-    let command = Single::new("brew").arg("update").run()?;
+pub fn update() -> Result<UpdateReport> {
+    let command = brew(&["update"])?;
     if command.success() {
-        Ok(())
+        Ok(UpdateReport {
+            timing: command.timing,
+            ..UpdateReport::parse(command.stdout())
+        })
+    } else {
+        Err(brew_error(&command, Error::UnknownError))
+    }
+}
+
+/// Resets Homebrew and its taps to their remote's latest commit, discarding
+/// local changes, with `brew update-reset`.
+///
+/// `repos` are the paths of the repositories to reset, or empty for
+/// Homebrew itself and every tap. Returns the repositories that were reset.
+pub fn update_reset<P: AsRef<std::path::Path>>(repos: &[P]) -> Result<Vec<PathBuf>> {
+    let mut args = vec!["update-reset".to_owned()];
+    args.extend(
+        repos
+            .iter()
+            .map(|r| r.as_ref().to_string_lossy().into_owned()),
+    );
+    let output = brew(&args)?;
+    if output.success() {
+        Ok(parse_reset_repos(output.stdout()))
     } else {
-        test_brew_installed()?;
-        Err(Error::UnknownError(command.stderr().to_owned()))
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+/// Parses the repositories from `brew update-reset` output, such as
+/// `==> Resetting /opt/homebrew...`.
+fn parse_reset_repos(stdout: &str) -> Vec<PathBuf> {
+    stdout
+        .lines()
+        .filter_map(|l| l.trim_start_matches("==> ").strip_prefix("Resetting "))
+        .map(|path| PathBuf::from(path.trim().trim_end_matches("...")))
+        .collect()
+}
+
+/// What changed in a `brew update`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UpdateReport {
+    /// Whether anything was updated, rather than brew being up to date.
+    pub changed: bool,
+    pub new_formulae: Vec<String>,
+    pub updated_formulae: Vec<String>,
+    /// Renamed formulae, as `(old, new)` names.
+    pub renamed_formulae: Vec<(String, String)>,
+    pub deleted_formulae: Vec<String>,
+    pub new_casks: Vec<String>,
+    pub updated_casks: Vec<String>,
+    pub renamed_casks: Vec<(String, String)>,
+    pub deleted_casks: Vec<String>,
+    /// Installed formulae that are now outdated.
+    pub outdated_formulae: Vec<String>,
+    /// Installed casks that are now outdated.
+    pub outdated_casks: Vec<String>,
+    /// How long the update took.
+    pub timing: Timing,
+}
+
+impl UpdateReport {
+    /// Parses the output of `brew update`.
+    ///
+    /// Recent versions of brew only list updated formulae and casks with
+    /// `--verbose`, so `updated_formulae` and `updated_casks` may be empty
+    /// even when `changed` is set.
+    fn parse(stdout: &str) -> UpdateReport {
+        let mut report = UpdateReport {
+            changed: !stdout.contains("Already up-to-date."),
+            ..UpdateReport::default()
+        };
+        let mut section: Option<&mut Vec<String>> = None;
+        let mut renamed: Option<&mut Vec<(String, String)>> = None;
+        for line in stdout.lines() {
+            if let Some(header) = line.strip_prefix("==> ") {
+                section = None;
+                renamed = None;
+                match header.trim() {
+                    "New Formulae" => section = Some(&mut report.new_formulae),
+                    "Updated Formulae" => section = Some(&mut report.updated_formulae),
+                    "Deleted Formulae" => section = Some(&mut report.deleted_formulae),
+                    "Outdated Formulae" => section = Some(&mut report.outdated_formulae),
+                    "New Casks" => section = Some(&mut report.new_casks),
+                    "Updated Casks" => section = Some(&mut report.updated_casks),
+                    "Deleted Casks" => section = Some(&mut report.deleted_casks),
+                    "Outdated Casks" => section = Some(&mut report.outdated_casks),
+                    "Renamed Formulae" => renamed = Some(&mut report.renamed_formulae),
+                    "Renamed Casks" => renamed = Some(&mut report.renamed_casks),
+                    _ => {}
+                }
+            } else if line.trim().is_empty() {
+                // A blank line ends the list, before hints such as how to
+                // upgrade outdated formulae.
+                section = None;
+                renamed = None;
+            } else if let Some(renamed) = renamed.as_mut() {
+                if let Some((old, new)) = line.split_once(" -> ") {
+                    renamed.push((old.trim().to_owned(), new.trim().to_owned()));
+                }
+            } else if let Some(section) = section.as_mut() {
+                let first = line.split_whitespace().next().unwrap_or("");
+                if let Some(name) = first.strip_suffix(':') {
+                    // brew 4 lists new formulae and casks with their
+                    // descriptions, as `name: description`.
+                    section.push(name.to_owned());
+                } else if !line.contains(' ') || !line.trim_end().ends_with('.') {
+                    // Summaries such as "Updated 12 formulae." are not names.
+                    section.extend(line.split_whitespace().map(str::to_owned));
+                }
+            }
+        }
+        report
+    }
+}
+
+/// The names of the formulae that provide the executable `command`, using
+/// `brew which-formula`.
+///
+/// This uses the command-not-found database, which only covers formulae in
+/// homebrew/core.
+pub fn which_formula(command: &str) -> Result<Vec<String>> {
+    let output = brew(&["which-formula", command])?;
+    if output.success() {
+        Ok(output
+            .stdout()
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(str::to_owned)
+            .collect())
+    } else if output.stderr().trim().is_empty() {
+        // brew exits with an error and prints nothing when nothing matches.
+        Ok(Vec::new())
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
     }
 }
 
 /// Return a map of all installed packages.
 pub fn all_installed() -> Result<HashMap<String, Package>> {
-    packages("--installed")
+    packages(&["--installed"])
 }
 
 /// For internal use, wrapper to get package info.
-fn packages(arg: &str) -> Result<HashMap<String, Package>> {
-    let output = Single::new("brew")
-        .arg("info")
-        .arg("--json=v1")
-        .arg(arg)
-        .env("HOMEBREW_NO_AUTO_UPDATE", "1")
-        .run()?;
+fn packages(args: &[&str]) -> Result<HashMap<String, Package>> {
+    let mut command = vec!["info", json_flag()?];
+    command.extend(args);
+    let output = brew(&command)?;
     if output.success() {
-        let v: Vec<Package> = serde_json::from_str(output.stdout())?;
+        let v = parse_packages(output.stdout())?;
         Ok(v.into_iter().map(|p| (p.name.clone(), p)).collect())
     } else {
-        test_brew_installed()?;
-        Err(Error::UnknownError(output.stdout().to_string()))
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+/// For internal use, the `--json` flag to get package info with.
+///
+/// brew 2.6.0 and later understand `--json=v2`, which older versions reject.
+fn json_flag() -> Result<&'static str> {
+    let output =
+        command::version_output().ok_or_else(|| Error::NotInstalled(Failure::default()))?;
+    // Assume a brew too new for us to understand its version is new.
+    let v2 = match parse_brew_version(&output) {
+        Some(version) => version >= (2, 6, 0),
+        None => true,
+    };
+    Ok(if v2 { "--json=v2" } else { "--json=v1" })
+}
+
+/// The version of the installed brew, such as `(4, 2, 0)`.
+///
+/// Once `brew --version` succeeds, it is not run again for the same `Brew`
+/// client until `Brew::invalidate` is called.
+pub fn brew_version() -> Result<(u32, u32, u32)> {
+    let output =
+        command::version_output().ok_or_else(|| Error::NotInstalled(Failure::default()))?;
+    parse_brew_version(&output).ok_or_else(|| {
+        Error::UnknownError(Failure {
+            message: format!("unrecognized brew version: {}", output.trim()),
+            ..Failure::default()
+        })
+    })
+}
+
+/// Parses the version from `brew --version`, such as
+/// `Homebrew 4.2.0-45-g8f3e6c1`.
+fn parse_brew_version(stdout: &str) -> Option<(u32, u32, u32)> {
+    let version = stdout
+        .lines()
+        .next()?
+        .strip_prefix("Homebrew ")?
+        .trim_start_matches(">=");
+    let end = version
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(version.len());
+    let mut parts = version[..end].split('.').map(|p| p.parse().ok());
+    Some((
+        parts.next()??,
+        parts.next().flatten().unwrap_or(0),
+        parts.next().flatten().unwrap_or(0),
+    ))
+}
+
+/// For internal use, parses the formulae from `brew info --json=v1` or
+/// `--json=v2` output.
+fn parse_packages(json: &str) -> Result<Vec<Package>> {
+    #[derive(Deserialize)]
+    struct V2 {
+        formulae: Vec<Package>,
+    }
+    if json.trim_start().starts_with('[') {
+        Ok(serde_json::from_str(json)?)
+    } else {
+        Ok(serde_json::from_str::<V2>(json)?.formulae)
     }
 }
 
 /// Returns a map of all packages in the downloaded homebrew repository.
 pub fn all_packages() -> Result<HashMap<String, Package>> {
-    packages("--all")
+    packages(&["--all"])
+}
+
+/// The Homebrew prefix, as reported by `brew --prefix`.
+pub fn prefix() -> Result<PathBuf> {
+    brew_path("--prefix")
+}
+
+/// The Homebrew Cellar, as reported by `brew --cellar`.
+pub fn cellar() -> Result<PathBuf> {
+    brew_path("--cellar")
+}
+
+/// The Homebrew download cache, as reported by `brew --cache`.
+pub fn cache() -> Result<PathBuf> {
+    brew_path("--cache")
+}
+
+/// For internal use, runs brew with `arg` and returns the printed path.
+fn brew_path(arg: &str) -> Result<PathBuf> {
+    let output = brew(&[arg])?;
+    if output.success() {
+        Ok(PathBuf::from(output.stdout().trim()))
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(
+    all(feature = "strict-schema", not(feature = "preserve-unknown")),
+    serde(deny_unknown_fields)
+)]
 pub struct Analytics {
     pub install: Analytic,
     pub install_on_request: Analytic,
     pub build_error: Analytic,
+    #[cfg(feature = "preserve-unknown")]
+    /// Unknown fields, as for `Package::extra`.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(
+    all(feature = "strict-schema", not(feature = "preserve-unknown")),
+    serde(deny_unknown_fields)
+)]
 pub struct Analytic {
     #[serde(rename = "30d")]
     d30: Option<HashMap<String, usize>>,
     #[serde(rename = "90d")]
     d90: Option<HashMap<String, usize>>,
-    #[serde(rename = "d365")]
+    #[serde(rename = "365d")]
     d365: Option<HashMap<String, usize>>,
 }
 
+impl Analytic {
+    /// The counts over `window` for each variant, such as `jq` and
+    /// `jq --HEAD`, or `None` if brew did not report the window.
+    pub fn counts(&self, window: AnalyticsWindow) -> Option<&HashMap<String, usize>> {
+        match window {
+            AnalyticsWindow::Days30 => self.d30.as_ref(),
+            AnalyticsWindow::Days90 => self.d90.as_ref(),
+            AnalyticsWindow::Days365 => self.d365.as_ref(),
+        }
+    }
+
+    /// The counts over `window` for each variant, most counted first.
+    pub fn variant_counts(&self, window: AnalyticsWindow) -> Vec<VariantCount> {
+        let mut counts: Vec<VariantCount> = self
+            .counts(window)
+            .into_iter()
+            .flatten()
+            .map(|(variant, &count)| VariantCount::new(variant, count))
+            .collect();
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+        counts
+    }
+
+    /// The count over `window`, summed across variants.
+    pub fn total(&self, window: AnalyticsWindow) -> usize {
+        self.counts(window).iter().flat_map(|d| d.values()).sum()
+    }
+
+    /// The count over the last 90 days, summed across variants such as
+    /// `jq --HEAD`.
+    pub fn total_90d(&self) -> usize {
+        self.total(AnalyticsWindow::Days90)
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(
+    all(feature = "strict-schema", not(feature = "preserve-unknown")),
+    serde(deny_unknown_fields)
+)]
 pub struct Versions {
-    pub stable: VersionResult,
+    /// The stable version, or `None` for formulae that can only be built
+    /// from HEAD.
+    pub stable: Option<VersionResult>,
     pub devel: Option<VersionResult>,
     pub head: Option<String>,
     pub bottle: bool,
+    #[cfg(feature = "preserve-unknown")]
+    /// Unknown fields, as for `Package::extra`.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(
+    all(feature = "strict-schema", not(feature = "preserve-unknown")),
+    serde(deny_unknown_fields)
+)]
 pub struct Bottle {
     pub rebuild: usize,
     pub cellar: Option<String>,
     pub prefix: Option<String>,
     pub root_url: String,
     pub files: HashMap<String, File>,
+    #[cfg(feature = "preserve-unknown")]
+    /// Unknown fields, as for `Package::extra`.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(
+    all(feature = "strict-schema", not(feature = "preserve-unknown")),
+    serde(deny_unknown_fields)
+)]
 pub struct File {
     pub url: String,
     pub sha256: String,
+    #[cfg(feature = "preserve-unknown")]
+    /// Unknown fields, as for `Package::extra`.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(
+    all(feature = "strict-schema", not(feature = "preserve-unknown")),
+    serde(deny_unknown_fields)
+)]
 pub struct Url {
     pub url: String,
     pub tag: Option<String>,
-    pub revision: Option<NumOrString>,
+    pub revision: Option<UrlRevision>,
+    #[cfg(feature = "preserve-unknown")]
+    /// Unknown fields, as for `Package::extra`.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(
+    all(feature = "strict-schema", not(feature = "preserve-unknown")),
+    serde(deny_unknown_fields)
+)]
 pub struct Installed {
     pub version: VersionResult,
     pub used_options: Vec<String>,
@@ -363,6 +1305,10 @@ pub struct Installed {
     pub runtime_dependencies: Vec<Dependency>,
     pub installed_as_dependency: bool,
     pub installed_on_request: bool,
+    #[cfg(feature = "preserve-unknown")]
+    /// Unknown fields, as for `Package::extra`.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 fn parse_null_as_empty_vec<'de, D>(d: D) -> std::result::Result<Vec<Dependency>, D::Error>
@@ -373,26 +1319,30 @@ where
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(
+    all(feature = "strict-schema", not(feature = "preserve-unknown")),
+    serde(deny_unknown_fields)
+)]
 pub struct Dependency {
     pub full_name: String,
     pub version: VersionResult,
+    #[cfg(feature = "preserve-unknown")]
+    /// Unknown fields, as for `Package::extra`.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 type VersionResult = Version;
 
 /// Tests weither homebrew is installed by seeing if "brew --version" returns
 /// successfully.
+///
+/// The result is cached like `brew_version`'s, so checking again is cheap.
 pub fn test_brew_installed() -> Result<()> {
-    if Single::new("brew")
-        .arg("--version")
-        .env("HOMEBREW_NO_AUTO_UPDATE", "1")
-        .run()
-        .map(|o| o.success())
-        .unwrap_or(false)
-    {
+    if command::version_output().is_some() {
         Ok(())
     } else {
-        Err(Error::NotInstalled)
+        Err(Error::NotInstalled(Failure::default()))
     }
 }
 
@@ -425,6 +1375,7 @@ fn install_homebrew_at(dir: &str) -> Result<()> {
         )
         .with_dir(dir)
         .run()?;
+    Brew::current().invalidate();
     test_brew_installed()?;
     Ok(())
 }
@@ -445,6 +1396,7 @@ pub struct Options {
     bottle_arch: bool,
     force: bool,
     git: bool,
+    skip_link: bool,
     package_options: Vec<String>,
 }
 
@@ -532,6 +1484,13 @@ impl Options {
         self
     }
 
+    /// Adds the `--skip-link` flag, leaving the keg to be linked into the
+    /// prefix with `brew link`.
+    pub fn skip_link(mut self) -> Self {
+        self.skip_link = true;
+        self
+    }
+
     /// Adds a flag for the package to use directly.
     pub fn option(mut self, opt: &str) -> Self {
         self.package_options.push(opt.to_string());
@@ -593,6 +1552,9 @@ impl Options {
         if self.git {
             out.push("--git")
         }
+        if self.skip_link {
+            out.push("--skip-link")
+        }
         out
     }
 }
@@ -613,6 +1575,80 @@ impl Default for BuildEnv {
 #[cfg(test)]
 mod tests {
 
+    /// For tests, a formula named `name` with no dependencies, not installed,
+    /// with the fields in `fields` replaced.
+    pub(crate) fn formula(name: &str, fields: serde_json::Value) -> crate::Package {
+        serde_json::from_value(formula_json(name, fields)).unwrap()
+    }
+
+    /// For tests, the JSON brew reports for `formula(name, fields)`.
+    pub(crate) fn formula_json(name: &str, fields: serde_json::Value) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "name": name, "full_name": name, "tap": "homebrew/core", "aliases": [],
+            "versions": {"stable": "1.0", "head": null, "bottle": true},
+            "urls": {}, "revision": 0, "version_scheme": 0, "bottle": {}, "keg_only": false,
+            "options": [], "build_dependencies": [], "dependencies": [],
+            "recommended_dependencies": [], "optional_dependencies": [], "uses_from_macos": [],
+            "requirements": [], "conflicts_with": [], "installed": [], "pinned": false,
+            "outdated": false,
+        });
+        for (key, value) in fields.as_object().expect("fields are an object") {
+            json[key] = value.clone();
+        }
+        json
+    }
+
+    /// For tests, the receipt of a keg of `version`.
+    pub(crate) fn installed(version: &str, on_request: bool) -> serde_json::Value {
+        serde_json::json!({
+            "version": version, "used_options": [], "built_as_bottle": true,
+            "poured_from_bottle": true, "runtime_dependencies": [],
+            "installed_as_dependency": !on_request, "installed_on_request": on_request,
+        })
+    }
+
+    /// For tests, a successful run of brew with `args` that printed `stdout`.
+    #[cfg(feature = "fixtures")]
+    pub(crate) fn recording(args: &[&str], stdout: &str) -> crate::fixtures::Recording {
+        crate::fixtures::Recording {
+            args: args.iter().map(|s| s.to_string()).collect(),
+            stdout: stdout.to_owned(),
+            stderr: String::new(),
+            status: crate::ExitStatus {
+                code: Some(0),
+                signal: None,
+            },
+        }
+    }
+
+    #[cfg(feature = "fixtures")]
+    #[test]
+    fn new_reports_brew_errors() {
+        let failed = |stderr: &str| crate::fixtures::Recording {
+            stderr: stderr.to_owned(),
+            status: crate::ExitStatus {
+                code: Some(1),
+                signal: None,
+            },
+            ..recording(&["info", "jq", "--json=v2"], "")
+        };
+        let _guard = crate::fixtures::replay_recordings(vec![
+            recording(&["--version"], "Homebrew 4.2.0"),
+            failed("Error: GitHub API Error: API rate limit exceeded for 1.2.3.4."),
+            failed("Error: No available formula with the name \"jq\"."),
+        ]);
+        crate::Brew::new().enter(|| {
+            assert!(matches!(
+                crate::Package::new("jq"),
+                Err(crate::Error::RateLimited(_))
+            ));
+            assert!(matches!(
+                crate::Package::new("jq"),
+                Err(crate::Error::PackageNotFound(_))
+            ));
+        });
+    }
+
     #[test]
     fn test_brew_install_test() {
         assert!(matches!(crate::test_brew_installed(), Ok(())));
@@ -625,8 +1661,380 @@ mod tests {
         assert_eq!(exa.name, "exa");
         assert_eq!(exa.desc.unwrap(), "Modern replacement for 'ls'");
         assert!(
-            exa.versions.stable.parse().unwrap() >= version_rs::Version::from((0 as u32, 9 as u32))
+            exa.versions.stable.unwrap().parse().unwrap()
+                >= version_rs::Version::from((0 as u32, 9 as u32))
+        );
+    }
+
+    #[cfg(feature = "fixtures")]
+    #[test]
+    fn refreshes_packages() {
+        use crate::cask::Cask;
+        use serde_json::json;
+
+        let jq =
+            |installed| json!({"formulae": [formula_json("jq", json!({"installed": installed}))]});
+        let firefox = |installed| {
+            json!({"casks": [{
+                "token": "firefox", "full_token": "firefox", "version": "121.0",
+                "installed": installed,
+            }]})
+        };
+        let _guard = crate::fixtures::replay_recordings(vec![
+            recording(&["--version"], "Homebrew 4.2.0"),
+            recording(&["info", "jq", "--json=v2"], &jq(json!([])).to_string()),
+            recording(
+                &["info", "jq", "--json=v2"],
+                &jq(json!([installed("1.0", true)])).to_string(),
+            ),
+            recording(
+                &["info", "--json=v2", "--cask", "firefox"],
+                &firefox(json!(null)).to_string(),
+            ),
+            recording(
+                &["info", "--json=v2", "--cask", "firefox"],
+                &firefox(json!("121.0")).to_string(),
+            ),
+        ]);
+        // A new client, so the version is not cached from other tests.
+        crate::Brew::new().enter(|| {
+            let mut jq = crate::Package::new("jq").unwrap();
+            assert!(!jq.is_installed());
+            jq.refresh().unwrap();
+            assert!(jq.is_installed());
+            let mut firefox = Cask::new("firefox").unwrap();
+            assert!(!firefox.is_installed());
+            firefox.refresh().unwrap();
+            assert_eq!(firefox.installed.as_deref(), Some("121.0"));
+        });
+    }
+
+    #[cfg(feature = "fixtures")]
+    #[test]
+    fn finds_taps_from_full_names() {
+        use serde_json::json;
+
+        let jqp = formula_json("jqp", json!({"full_name": "acme/tools/jqp", "tap": null}));
+        let _guard = crate::fixtures::replay_recordings(vec![
+            recording(&["--version"], "Homebrew 4.2.0"),
+            recording(
+                &["info", "acme/tools/jqp", "--json=v2"],
+                &json!({ "formulae": [jqp] }).to_string(),
+            ),
+        ]);
+        // A new client, so the version is not cached from other tests.
+        let jqp = crate::Brew::new().enter(|| crate::Package::new("acme/tools/jqp").unwrap());
+        assert_eq!(jqp.tap.as_deref(), Some("acme/tools"));
+    }
+
+    #[test]
+    fn rejects_unsupported_options() {
+        use serde_json::json;
+
+        let options = crate::Options::new().option("--with-x");
+        let declared = json!({"options": [{"option": "--with-x", "description": ""}]});
+        // Without a tap, brew means homebrew/core, which dropped options.
+        let jq = formula("jq", json!({"tap": null, "options": declared["options"]}));
+        assert!(!jq.supports_options(options.package_options()));
+        let result = jq.install_using(&options, |_| panic!("brew should not run"));
+        assert!(matches!(result, Err(crate::Error::OptionsUnsupported(name)) if name == "jq"));
+
+        let jqp = formula("jqp", json!({"full_name": "acme/tools/jqp", "tap": null}));
+        assert!(!jqp.supports_options(options.package_options()));
+        let jqp = formula(
+            "jqp",
+            json!({"full_name": "acme/tools/jqp", "tap": null, "options": declared["options"]}),
+        );
+        assert!(jqp.supports_options(options.package_options()));
+    }
+
+    #[cfg(feature = "fixtures")]
+    #[test]
+    fn skips_reinstalling_with_same_options() {
+        use serde_json::json;
+
+        let mut installed = installed("1.0", true);
+        installed["used_options"] = json!(["--with-x"]);
+        let jqp = formula_json(
+            "jqp",
+            json!({
+                "full_name": "acme/tools/jqp", "tap": "acme/tools",
+                "options": [{"option": "--with-x", "description": ""}],
+                "installed": [installed],
+            }),
+        );
+        let _guard = crate::fixtures::replay_recordings(vec![
+            recording(&["--version"], "Homebrew 4.2.0"),
+            recording(
+                &["info", "acme/tools/jqp", "--json=v2"],
+                &json!({ "formulae": [jqp] }).to_string(),
+            ),
+        ]);
+        let package: crate::Package = serde_json::from_value(jqp).unwrap();
+        let options = crate::Options::new().option("--with-x");
+        // A new client, so the version is not cached from other tests.
+        let reinstalled = crate::Brew::new().enter(|| {
+            package
+                .install_using(&options, |_| panic!("brew should not reinstall"))
+                .unwrap()
+        });
+        assert!(reinstalled.is_installed());
+    }
+
+    #[test]
+    fn detects_lock() {
+        assert!(crate::is_locked(
+            "Error: A `brew install jq` process has already locked /usr/local/var/homebrew/locks/jq.formula.lock."
+        ));
+        assert!(crate::is_locked(
+            "Error: Another active Homebrew update process is already in progress."
+        ));
+        assert!(!crate::is_locked(
+            "Error: No available formula with the name \"jqq\"."
+        ));
+    }
+
+    #[test]
+    fn maps_known_failures() {
+        let output = crate::command::Output::new(
+            Vec::new(),
+            b"Error: jq 1.6 is already installed".to_vec(),
+            crate::ExitStatus {
+                code: Some(1),
+                signal: None,
+            },
+        )
+        .ran(std::process::Command::new("brew").args(["install", "it's"]));
+        let error = crate::brew_error(&output, crate::Error::InstallFailed);
+        assert!(matches!(error, crate::Error::AlreadyInstalled(_)));
+        assert_eq!(error.status().unwrap().code, Some(1));
+    }
+
+    #[test]
+    fn records_failed_commands() {
+        let output = crate::command::Output::new(
+            Vec::new(),
+            b"Error: No available formula with the name \"it's\".".to_vec(),
+            crate::ExitStatus {
+                code: Some(1),
+                signal: None,
+            },
+        )
+        .ran(std::process::Command::new("brew").args(["install", "it's"]));
+        let error = crate::brew_error(&output, crate::Error::InstallFailed);
+        let command = match &error {
+            crate::Error::PackageNotFound(failure) => failure.command.as_deref().unwrap(),
+            _ => panic!("unexpected error {:?}", error),
+        };
+        assert_eq!(command.command_line(), "brew install 'it'\\''s'");
+        assert_eq!(command.dir, std::env::current_dir().ok());
+    }
+
+    #[test]
+    fn detects_rate_limits() {
+        let output = crate::command::Output::new(
+            Vec::new(),
+            b"Error: GitHub API Error: API rate limit exceeded for 203.0.113.7.".to_vec(),
+            crate::ExitStatus {
+                code: Some(1),
+                signal: None,
+            },
+        );
+        assert!(matches!(
+            crate::brew_error(&output, crate::Error::UnknownError),
+            crate::Error::RateLimited(_)
+        ));
+        assert!(!crate::is_rate_limited(
+            "Error: Failed to download resource"
+        ));
+    }
+
+    #[test]
+    fn parses_brew_version() {
+        assert_eq!(
+            crate::parse_brew_version("Homebrew 4.2.0-45-g8f3e6c1\n"),
+            Some((4, 2, 0))
+        );
+        assert_eq!(
+            crate::parse_brew_version("Homebrew >=2.5 (shallow or no git repository)\n"),
+            Some((2, 5, 0))
+        );
+        assert_eq!(crate::parse_brew_version("brew: command not found"), None);
+    }
+
+    #[test]
+    fn parses_either_json_version() {
+        assert!(crate::parse_packages("[]").unwrap().is_empty());
+        assert!(crate::parse_packages(r#"{"formulae": [], "casks": []}"#)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[cfg(feature = "preserve-unknown")]
+    #[test]
+    fn preserves_unknown_fields() {
+        let json = serde_json::json!({"url": "https://example.com", "sha256": "abc", "size": 12});
+        let file: crate::File = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(file.extra["size"], 12);
+        assert_eq!(serde_json::to_value(&file).unwrap(), json);
+
+        let json = serde_json::json!({
+            "name": "macos", "cask": null, "download": null, "version": "13",
+            "contexts": [], "specs": ["stable"],
+        });
+        let requirement: crate::Requirement = serde_json::from_value(json.clone()).unwrap();
+        assert!(
+            matches!(&requirement, crate::Requirement::Other { extra, .. } if extra["specs"] == serde_json::json!(["stable"]))
+        );
+        assert_eq!(serde_json::to_value(&requirement).unwrap(), json);
+    }
+
+    #[cfg(all(feature = "strict-schema", not(feature = "preserve-unknown")))]
+    #[test]
+    fn rejects_unknown_fields() {
+        let json = serde_json::json!({"url": "https://example.com", "sha256": "abc", "size": 12});
+        assert!(serde_json::from_value::<crate::File>(json).is_err());
+    }
+
+    #[test]
+    fn parses_update_report() {
+        let report = crate::UpdateReport::parse(
+            "Updated 2 taps (homebrew/core and homebrew/cask).\n\
+             ==> New Formulae\n\
+             jj\n\
+             zizmor\n\
+             ==> Renamed Formulae\n\
+             exa -> eza\n\
+             ==> Deleted Formulae\n\
+             youtube-dl\n\
+             ==> Updated Formulae\n\
+             Updated 12 formulae.\n",
+        );
+        assert!(report.changed);
+        assert_eq!(report.new_formulae, vec!["jj", "zizmor"]);
+        assert_eq!(
+            report.renamed_formulae,
+            vec![("exa".to_owned(), "eza".to_owned())]
+        );
+        assert_eq!(report.deleted_formulae, vec!["youtube-dl"]);
+        assert!(report.updated_formulae.is_empty());
+        assert!(!crate::UpdateReport::parse("Already up-to-date.\n").changed);
+    }
+
+    #[test]
+    fn parses_update_report_with_descriptions() {
+        let report = crate::UpdateReport::parse(
+            "==> Updating Homebrew...\n\
+             Updated 2 taps (homebrew/core and homebrew/cask).\n\
+             ==> New Formulae\n\
+             aider: AI pair programming in your terminal\n\
+             ruff-lsp: Language Server Protocol implementation for Ruff\n\
+             ==> New Casks\n\
+             font-maple-mono: Open source monospace font with round corners\n\
+             ==> Outdated Formulae\n\
+             jq                                       ripgrep\n\
+             \n\
+             You have 2 outdated formulae installed.\n\
+             You can upgrade them with brew upgrade\n\
+             or list them with brew outdated.\n",
+        );
+        assert_eq!(report.new_formulae, vec!["aider", "ruff-lsp"]);
+        assert_eq!(report.new_casks, vec!["font-maple-mono"]);
+        assert_eq!(report.outdated_formulae, vec!["jq", "ripgrep"]);
+    }
+
+    #[test]
+    fn parses_reset_repos() {
+        assert_eq!(
+            crate::parse_reset_repos(
+                "==> Fetching /opt/homebrew...\n\
+                 ==> Resetting /opt/homebrew...\n\
+                 HEAD is now at 0123abc\n\
+                 ==> Resetting /opt/homebrew/Library/Taps/homebrew/homebrew-core...\n"
+            ),
+            vec![
+                std::path::PathBuf::from("/opt/homebrew"),
+                std::path::PathBuf::from("/opt/homebrew/Library/Taps/homebrew/homebrew-core"),
+            ]
+        );
+    }
+
+    #[test]
+    fn tails_files() {
+        let path = std::env::temp_dir().join("brew-rs-tail.log");
+        let text: String = (0..5000).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(&path, text).unwrap();
+        assert_eq!(
+            crate::tail_lines(&path, 2).unwrap(),
+            vec!["line 4998", "line 4999"]
+        );
+        assert_eq!(crate::tail_lines(&path, 10000).unwrap().len(), 5000);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn parses_uses_from_macos() {
+        let deps: Vec<crate::MacOsDependency> =
+            serde_json::from_str(r#"["zlib", {"llvm": "build"}, {"python": ["build", "test"]}]"#)
+                .unwrap();
+        assert_eq!(deps[1].dep_type, vec!["build"]);
+        assert_eq!(
+            deps.iter().map(|d| d.is_runtime()).collect::<Vec<_>>(),
+            vec![true, false, false]
+        );
+        assert_eq!(
+            serde_json::to_value(&deps).unwrap(),
+            serde_json::json!(["zlib", {"llvm": "build"}, {"python": ["build", "test"]}])
+        );
+    }
+
+    #[test]
+    fn reads_macos_bounds() {
+        let json = formula_json(
+            "curl",
+            serde_json::json!({
+                "uses_from_macos": ["zlib", {"llvm": "build"}],
+                "uses_from_macos_bounds": [{"since": "catalina"}, {}],
+            }),
+        );
+        let package: crate::Package = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(
+            package
+                .uses_from_macos
+                .iter()
+                .map(|d| d.since)
+                .collect::<Vec<_>>(),
+            [Some(crate::platform::MacOsVersion::Catalina), None]
+        );
+        let written = serde_json::to_value(&package).unwrap();
+        assert_eq!(
+            written["uses_from_macos_bounds"],
+            json["uses_from_macos_bounds"]
+        );
+    }
+
+    #[test]
+    fn parses_head_only_versions() {
+        let versions: crate::Versions = serde_json::from_str(
+            r#"{"stable": null, "devel": null, "head": "HEAD", "bottle": false}"#,
+        )
+        .unwrap();
+        assert!(versions.stable.is_none());
+        assert_eq!(versions.head.as_deref(), Some("HEAD"));
+    }
+
+    #[test]
+    fn parses_url_revisions() {
+        let url: crate::Url = serde_json::from_str(
+            r#"{"url": "https://github.com/jqlang/jq.git", "tag": "jq-1.7", "revision": "71c2ab5"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            url.revision,
+            Some(crate::UrlRevision::Commit("71c2ab5".to_owned()))
         );
+        let revision: crate::UrlRevision = serde_json::from_str("1234").unwrap();
+        assert_eq!(revision.to_string(), "1234");
     }
 
     #[test]
@@ -0,0 +1,114 @@
+//! Linking a specific installed version of a formula into the prefix.
+use crate::command::brew;
+use crate::{brew_error, cellar, prefix, Error, Failure, Package, Result};
+use std::path::Path;
+
+impl Package {
+    /// Links `version`, which must be installed, into the prefix in place of
+    /// the linked version, as `brew switch` used to.
+    ///
+    /// `version` is the name of the keg, with its revision, such as
+    /// `3.1.4_1`. `opt/<name>` is pointed at the keg, which is the one
+    /// `brew link` then links, and keg-only formulae are only linked there.
+    /// Fails with `Error::NotInstalled` if the version is not in the
+    /// Cellar, and with `Error::UnknownError` if brew linked another keg.
+    pub fn link_version(&self, version: &str) -> Result<Package> {
+        let keg = cellar()?.join(&self.name).join(version);
+        if version.starts_with('.') || version.contains('/') || !keg.is_dir() {
+            return Err(Error::NotInstalled(Failure::default()));
+        }
+        let prefix = prefix()?;
+        // What is linked now, rather than when this package was read.
+        if linked_keg(&prefix, &self.name).is_some() {
+            run(&["unlink", &self.full_name])?;
+        }
+        let target = match keg.strip_prefix(&prefix) {
+            Ok(relative) => Path::new("..").join(relative),
+            Err(_) => keg.clone(),
+        };
+        opt_link(&prefix.join("opt").join(&self.name), &target)?;
+        if !self.keg_only {
+            run(&["link", &self.full_name])?;
+            let linked = linked_keg(&prefix, &self.name);
+            if linked.as_deref() != Some(version) {
+                return Err(Error::UnknownError(Failure {
+                    message: format!(
+                        "Linked {} {} instead of {}",
+                        self.name,
+                        linked.as_deref().unwrap_or("nothing"),
+                        version
+                    ),
+                    ..Failure::default()
+                }));
+            }
+        }
+        Package::new(&self.full_name)
+    }
+}
+
+/// The keg of `name` linked into `prefix`, such as `1.7.1`, as brew records
+/// it in `var/homebrew/linked`.
+fn linked_keg(prefix: &Path, name: &str) -> Option<String> {
+    let keg = std::fs::read_link(prefix.join("var/homebrew/linked").join(name)).ok()?;
+    Some(keg.file_name()?.to_string_lossy().into_owned())
+}
+
+/// Points the `opt` link at `keg`, which is relative to the `opt`
+/// directory if the Cellar is in the prefix, as brew makes it.
+#[cfg(unix)]
+fn opt_link(opt: &Path, keg: &Path) -> Result<()> {
+    match std::fs::remove_file(opt) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    std::os::unix::fs::symlink(keg, opt)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn opt_link(_opt: &Path, _keg: &Path) -> Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "kegs can only be linked on Unix",
+    )
+    .into())
+}
+
+fn run(args: &[&str]) -> Result<()> {
+    let output = brew(args)?;
+    if output.success() {
+        Ok(())
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_opt_links() {
+        let dir = std::env::temp_dir().join(format!("brew-rs-opt-link-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let opt = dir.join("jq");
+        opt_link(&opt, Path::new("../Cellar/jq/1.6")).unwrap();
+        opt_link(&opt, Path::new("../Cellar/jq/1.7.1")).unwrap();
+        assert_eq!(
+            std::fs::read_link(&opt).unwrap(),
+            Path::new("../Cellar/jq/1.7.1")
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reads_linked_kegs() {
+        let prefix = std::env::temp_dir().join(format!("brew-rs-linked-{}", std::process::id()));
+        let linked = prefix.join("var/homebrew/linked");
+        std::fs::create_dir_all(&linked).unwrap();
+        assert_eq!(linked_keg(&prefix, "jq"), None);
+        std::os::unix::fs::symlink("../../../Cellar/jq/1.7.1", linked.join("jq")).unwrap();
+        assert_eq!(linked_keg(&prefix, "jq").as_deref(), Some("1.7.1"));
+        std::fs::remove_dir_all(prefix).unwrap();
+    }
+}
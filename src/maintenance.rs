@@ -0,0 +1,302 @@
+//! Running a maintenance routine, such as from a launchd job: update,
+//! upgrade, clean up and remove unneeded dependencies.
+//!
+//! ``` no_run
+//! use brew::maintenance::{Maintenance, StepPolicy};
+//!
+//! let report = Maintenance::new()
+//!     .never_upgrade(&["postgresql@14"])
+//!     .autoremove(StepPolicy::Skip)
+//!     .run();
+//! if !report.success() {
+//!     std::process::exit(1);
+//! }
+//! ```
+use crate::cleanup::{cleanup, CleanupOptions, CleanupReport};
+use crate::command::{brew, timed};
+use crate::outdated::outdated;
+use crate::upgrade_summary::UpgradeKind;
+use crate::{brew_error, update, Error, Result, Timing, UpdateReport};
+use std::collections::HashSet;
+
+/// What to do with a step of the routine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepPolicy {
+    Skip,
+    /// Run the step, going on with the routine if it fails.
+    Run,
+    /// Run the step, stopping the routine if it fails.
+    Required,
+}
+
+/// How a step of the routine went.
+#[derive(Debug)]
+pub enum StepOutcome<T> {
+    /// The step's policy was `StepPolicy::Skip`.
+    Skipped,
+    /// An earlier required step failed.
+    NotReached,
+    Done(T),
+    Failed(Error),
+}
+
+impl<T> StepOutcome<T> {
+    /// Whether the step failed.
+    pub fn failed(&self) -> bool {
+        matches!(self, StepOutcome::Failed(_))
+    }
+
+    /// What the step did, if it ran and succeeded.
+    pub fn done(&self) -> Option<&T> {
+        match self {
+            StepOutcome::Done(t) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+/// Which outdated packages to upgrade.
+#[derive(Clone, Debug)]
+enum Allowed {
+    All,
+    Only(HashSet<String>),
+    Except(HashSet<String>),
+}
+
+impl Allowed {
+    fn allows(&self, name: &str) -> bool {
+        match self {
+            Allowed::All => true,
+            Allowed::Only(names) => names.contains(name),
+            Allowed::Except(names) => !names.contains(name),
+        }
+    }
+}
+
+/// Represents a maintenance routine, and the policy for each step.
+///
+/// By default every step runs, `update` is required, every outdated formula
+/// and cask that is not pinned is upgraded, and nothing is pruned from the
+/// cache.
+#[derive(Clone, Debug)]
+pub struct Maintenance {
+    update: StepPolicy,
+    upgrade: StepPolicy,
+    allowed: Allowed,
+    skip_rebuilds: bool,
+    cleanup: StepPolicy,
+    cleanup_options: CleanupOptions,
+    autoremove: StepPolicy,
+}
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Maintenance {
+            update: StepPolicy::Required,
+            upgrade: StepPolicy::Run,
+            allowed: Allowed::All,
+            skip_rebuilds: false,
+            cleanup: StepPolicy::Run,
+            cleanup_options: CleanupOptions::default(),
+            autoremove: StepPolicy::Run,
+        }
+    }
+}
+
+impl Maintenance {
+    /// Represents the default routine.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the policy for `brew update`.
+    pub fn update(mut self, policy: StepPolicy) -> Self {
+        self.update = policy;
+        self
+    }
+
+    /// Sets the policy for upgrading outdated packages.
+    pub fn upgrade(mut self, policy: StepPolicy) -> Self {
+        self.upgrade = policy;
+        self
+    }
+
+    /// Only upgrades the formulae and casks in `names`.
+    pub fn only_upgrade<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.allowed = Allowed::Only(names.iter().map(|s| s.as_ref().to_owned()).collect());
+        self
+    }
+
+    /// Upgrades every outdated formula and cask but those in `names`.
+    pub fn never_upgrade<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.allowed = Allowed::Except(names.iter().map(|s| s.as_ref().to_owned()).collect());
+        self
+    }
+
+    /// Only upgrades packages with a new upstream version, leaving those
+    /// that would only be rebuilt with a new revision or bottle.
+    pub fn skip_rebuilds(mut self) -> Self {
+        self.skip_rebuilds = true;
+        self
+    }
+
+    /// Sets the policy for `brew cleanup`.
+    pub fn cleanup(mut self, policy: StepPolicy) -> Self {
+        self.cleanup = policy;
+        self
+    }
+
+    /// Cleans up with `options`.
+    pub fn cleanup_options(mut self, options: CleanupOptions) -> Self {
+        self.cleanup_options = options;
+        self
+    }
+
+    /// Sets the policy for `brew autoremove`.
+    pub fn autoremove(mut self, policy: StepPolicy) -> Self {
+        self.autoremove = policy;
+        self
+    }
+
+    /// Runs the routine. Failures are recorded in the report rather than
+    /// returned.
+    pub fn run(&self) -> MaintenanceReport {
+        let mut stopped = false;
+        let ((update, upgraded, cleanup, autoremoved), timing) = timed(|| {
+            let update = step(self.update, &mut stopped, update);
+            let upgraded = step(self.upgrade, &mut stopped, || self.upgrade_allowed());
+            let cleanup = step(self.cleanup, &mut stopped, || {
+                cleanup(&self.cleanup_options)
+            });
+            let autoremoved = step(self.autoremove, &mut stopped, autoremove);
+            (update, upgraded, cleanup, autoremoved)
+        });
+        MaintenanceReport {
+            update,
+            upgraded,
+            cleanup,
+            autoremoved,
+            timing,
+        }
+    }
+
+    /// Upgrades the allowed outdated packages that are not pinned,
+    /// returning their names.
+    fn upgrade_allowed(&self) -> Result<Vec<String>> {
+        let (casks, formulae): (Vec<_>, Vec<_>) = outdated()?
+            .into_iter()
+            .filter(|p| !p.pinned && self.allowed.allows(&p.name))
+            .filter(|p| !self.skip_rebuilds || p.kind() == UpgradeKind::Version)
+            .partition(|p| p.cask);
+        let mut upgraded = Vec::new();
+        for (flag, packages) in [("--formula", formulae), ("--cask", casks)].iter() {
+            if packages.is_empty() {
+                continue;
+            }
+            let mut args = vec!["upgrade", flag];
+            args.extend(packages.iter().map(|p| p.name.as_str()));
+            let output = brew(&args)?;
+            if !output.success() {
+                return Err(brew_error(&output, Error::UnknownError));
+            }
+            upgraded.extend(packages.iter().map(|p| p.name.clone()));
+        }
+        Ok(upgraded)
+    }
+}
+
+/// Runs `f` as allowed by `policy`, unless a required step has `stopped`
+/// the routine.
+fn step<T, F>(policy: StepPolicy, stopped: &mut bool, f: F) -> StepOutcome<T>
+where
+    F: FnOnce() -> Result<T>,
+{
+    if policy == StepPolicy::Skip {
+        return StepOutcome::Skipped;
+    }
+    if *stopped {
+        return StepOutcome::NotReached;
+    }
+    match f() {
+        Ok(t) => StepOutcome::Done(t),
+        Err(e) => {
+            *stopped = policy == StepPolicy::Required;
+            StepOutcome::Failed(e)
+        }
+    }
+}
+
+/// Removes the formulae that were only installed as dependencies and are no
+/// longer needed, returning their names.
+fn autoremove() -> Result<Vec<String>> {
+    let output = brew(&["autoremove"])?;
+    if output.success() {
+        Ok(parse_autoremoved(output.stdout()))
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+/// Parses the names listed after `==> Uninstalling 2 unneeded formulae:`.
+fn parse_autoremoved(stdout: &str) -> Vec<String> {
+    stdout
+        .lines()
+        .skip_while(|l| !(l.starts_with("==> ") && l.contains("unneeded formula")))
+        .skip(1)
+        .take_while(|l| !l.is_empty() && !l.starts_with("==>") && !l.starts_with("Uninstalling"))
+        .map(|l| l.trim().to_owned())
+        .collect()
+}
+
+/// How each step of a maintenance routine went.
+#[derive(Debug)]
+pub struct MaintenanceReport {
+    pub update: StepOutcome<UpdateReport>,
+    /// The names of the formulae and casks upgraded.
+    pub upgraded: StepOutcome<Vec<String>>,
+    pub cleanup: StepOutcome<CleanupReport>,
+    /// The names of the formulae removed.
+    pub autoremoved: StepOutcome<Vec<String>>,
+    /// How long the whole routine took, with the time spent starting brew
+    /// for each command added up.
+    pub timing: Timing,
+}
+
+impl MaintenanceReport {
+    /// Whether no step failed.
+    pub fn success(&self) -> bool {
+        !(self.update.failed()
+            || self.upgraded.failed()
+            || self.cleanup.failed()
+            || self.autoremoved.failed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_after_required_failures() {
+        let mut stopped = false;
+        let failed: StepOutcome<()> = step(StepPolicy::Run, &mut stopped, || {
+            Err(Error::NotInstalled(crate::Failure::default()))
+        });
+        assert!(failed.failed() && !stopped);
+        let failed: StepOutcome<()> = step(StepPolicy::Required, &mut stopped, || {
+            Err(Error::NotInstalled(crate::Failure::default()))
+        });
+        assert!(failed.failed() && stopped);
+        assert!(matches!(
+            step(StepPolicy::Run, &mut stopped, || Ok(())),
+            StepOutcome::NotReached
+        ));
+        assert_eq!(
+            parse_autoremoved(
+                "==> Uninstalling 2 unneeded formulae:\nlame\nx264\n\
+                 Uninstalling /opt/homebrew/Cellar/lame/3.100... (28 files, 2.2MB)\n"
+            ),
+            vec!["lame", "x264"]
+        );
+    }
+}
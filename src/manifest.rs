@@ -0,0 +1,160 @@
+//! Exporting and importing snapshots as manifest files, to keep in git.
+//!
+//! The format is picked from the file extension: `.json`, `.toml` with the
+//! `manifest-toml` feature, or `.yaml` and `.yml` with the `manifest-yaml`
+//! feature. Every format has the same schema, that of `SystemSnapshot`:
+//!
+//! ```toml
+//! # Taps, with the remote if it is not the default GitHub repository.
+//! [[taps]]
+//! name = "homebrew/cask-fonts"
+//!
+//! [[taps]]
+//! name = "acme/tools"
+//! remote = "https://git.acme.com/homebrew-tools.git"
+//!
+//! # Formulae, by full name. Those installed as dependencies are recorded,
+//! # but only those installed on request are installed by `restore`.
+//! [[formulae]]
+//! name = "jq"
+//! version = "1.7.1"
+//! options = []
+//! pinned = false
+//! installed_on_request = true
+//!
+//! [[casks]]
+//! name = "firefox"
+//! version = "121.0"
+//!
+//! # The status of each service, such as `started` or `none`.
+//! [services]
+//! redis = "started"
+//! ```
+use crate::snapshot::{snapshot, SystemSnapshot};
+use crate::{Error, Result};
+use std::path::Path;
+
+/// A manifest file format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// The format of the file at `path`, from its extension.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Format::Json),
+            "toml" => Some(Format::Toml),
+            "yaml" | "yml" => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+}
+
+impl SystemSnapshot {
+    /// Writes the snapshot as a manifest in `format`.
+    pub fn to_manifest(&self, format: Format) -> Result<String> {
+        match format {
+            Format::Json => Ok(serde_json::to_string_pretty(self)?),
+            #[cfg(feature = "manifest-toml")]
+            Format::Toml => toml::to_string_pretty(self).map_err(invalid),
+            #[cfg(not(feature = "manifest-toml"))]
+            Format::Toml => Err(unsupported("manifest-toml")),
+            #[cfg(feature = "manifest-yaml")]
+            Format::Yaml => serde_yaml::to_string(self).map_err(invalid),
+            #[cfg(not(feature = "manifest-yaml"))]
+            Format::Yaml => Err(unsupported("manifest-yaml")),
+        }
+    }
+
+    /// Reads a snapshot from a manifest in `format`.
+    pub fn from_manifest(manifest: &str, format: Format) -> Result<SystemSnapshot> {
+        match format {
+            Format::Json => Ok(serde_json::from_str(manifest)?),
+            #[cfg(feature = "manifest-toml")]
+            Format::Toml => toml::from_str(manifest).map_err(invalid),
+            #[cfg(not(feature = "manifest-toml"))]
+            Format::Toml => Err(unsupported("manifest-toml")),
+            #[cfg(feature = "manifest-yaml")]
+            Format::Yaml => serde_yaml::from_str(manifest).map_err(invalid),
+            #[cfg(not(feature = "manifest-yaml"))]
+            Format::Yaml => Err(unsupported("manifest-yaml")),
+        }
+    }
+}
+
+/// Writes a snapshot of what is installed to the manifest at `path`,
+/// returning the snapshot.
+pub fn export<P: AsRef<Path>>(path: P) -> Result<SystemSnapshot> {
+    let path = path.as_ref();
+    let snapshot = snapshot()?;
+    std::fs::write(path, snapshot.to_manifest(format_of(path)?)?)?;
+    Ok(snapshot)
+}
+
+/// Reads the manifest at `path`, to pass to `snapshot::restore`.
+pub fn import<P: AsRef<Path>>(path: P) -> Result<SystemSnapshot> {
+    let path = path.as_ref();
+    SystemSnapshot::from_manifest(&std::fs::read_to_string(path)?, format_of(path)?)
+}
+
+fn format_of(path: &Path) -> Result<Format> {
+    Format::from_path(path).ok_or_else(|| {
+        Error::InvalidManifest(format!(
+            "{} is not a .json, .toml or .yaml file",
+            path.display()
+        ))
+    })
+}
+
+#[cfg(any(feature = "manifest-toml", feature = "manifest-yaml"))]
+fn invalid<E: std::fmt::Display>(e: E) -> Error {
+    Error::InvalidManifest(e.to_string())
+}
+
+#[cfg(not(all(feature = "manifest-toml", feature = "manifest-yaml")))]
+fn unsupported(feature: &str) -> Error {
+    Error::InvalidManifest(format!(
+        "this manifest format needs the {} feature",
+        feature
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_format_from_extension() {
+        assert_eq!(
+            Format::from_path(Path::new("brew/manifest.yml")),
+            Some(Format::Yaml)
+        );
+        assert_eq!(Format::from_path(Path::new("Brewfile")), None);
+    }
+
+    #[cfg(all(feature = "manifest-toml", feature = "manifest-yaml"))]
+    #[test]
+    fn round_trips_manifests() {
+        let snapshot = SystemSnapshot::from_manifest(
+            "[[taps]]\nname = \"acme/tools\"\nremote = \"https://git.acme.com/homebrew-tools.git\"\n\n\
+             [[formulae]]\nname = \"jq\"\nversion = \"1.7.1\"\noptions = []\npinned = false\n\
+             installed_on_request = true\n\n\
+             [[casks]]\nname = \"firefox\"\nversion = \"121.0\"\n\n\
+             [services]\nredis = \"started\"\n",
+            Format::Toml,
+        )
+        .unwrap();
+        assert_eq!(snapshot.formulae[0].name, "jq");
+        for format in [Format::Json, Format::Toml, Format::Yaml].iter() {
+            let manifest = snapshot.to_manifest(*format).unwrap();
+            assert_eq!(
+                SystemSnapshot::from_manifest(&manifest, *format).unwrap(),
+                snapshot
+            );
+        }
+    }
+}
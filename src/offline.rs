@@ -0,0 +1,394 @@
+//! Installing on machines without network access.
+//!
+//! On a connected machine, `export_bundle` downloads the bottles of some
+//! formulae and their dependencies, for the platform of the offline machine,
+//! cask downloads, and Homebrew's API metadata into a directory. Copied to
+//! the offline machine, `install_bundle` installs everything from that
+//! directory.
+//!
+//! ``` no_run
+//! # fn main() -> brew::Result<()> {
+//! use brew::offline::{export_bundle, install_bundle, OfflineOutcome};
+//! use brew::platform::Platform;
+//!
+//! // On the connected machine.
+//! let platform = Platform::from_bottle_tag("arm64_sonoma").unwrap();
+//! export_bundle(&["ripgrep", "jq"], &[], &platform, "/Volumes/usb/brew")?;
+//!
+//! // On the offline machine.
+//! for (name, outcome) in install_bundle("/Volumes/usb/brew")? {
+//!     if let OfflineOutcome::Failed(e) = outcome {
+//!         eprintln!("{} failed: {:?}", name, e);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::api::cache_dir;
+use crate::command::brew;
+use crate::deps::DepsOptions;
+use crate::platform::Platform;
+use crate::{brew_error, cellar, packages, Brew, Error, Failure, Package, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// The name of the file in a bundle directory that lists its contents.
+pub const MANIFEST: &str = "offline-bundle.json";
+
+/// The files of Homebrew's API cache a bundle carries, in its `api`
+/// directory.
+const API_FILES: [&str; 2] = ["formula.jws.json", "cask.jws.json"];
+
+/// What a bundle directory holds, as listed in its `MANIFEST`.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct OfflineBundle {
+    /// The bottle tag the bottles are for, such as `arm64_sonoma`.
+    pub bottle_tag: String,
+    /// The formulae, each after its dependencies.
+    pub formulae: Vec<OfflineFormula>,
+    pub casks: Vec<OfflineCask>,
+    /// The files of Homebrew's API cache in the bundle's `api` directory,
+    /// such as `formula.jws.json`.
+    #[serde(default)]
+    pub api_files: Vec<String>,
+}
+
+/// A formula in an `OfflineBundle`.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct OfflineFormula {
+    pub name: String,
+    pub full_name: String,
+    /// The version, with its revision, such as `3.1.4_1`.
+    pub version: String,
+    /// The name of the bottle file in the bundle directory.
+    pub file: String,
+    /// The names of the formulae in the bundle it depends on.
+    pub dependencies: Vec<String>,
+}
+
+/// A cask in an `OfflineBundle`.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct OfflineCask {
+    pub token: String,
+    /// The name of the downloaded file in the bundle directory.
+    pub file: String,
+}
+
+impl OfflineBundle {
+    /// Reads the manifest of the bundle in `dir`.
+    pub fn read<P: AsRef<Path>>(dir: P) -> Result<OfflineBundle> {
+        let json = std::fs::read_to_string(dir.as_ref().join(MANIFEST))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+/// Downloads into `dir` the bottles for `platform` of `formulae` and their
+/// runtime dependencies, and the downloads of `casks`, and writes the
+/// bundle's `MANIFEST`.
+///
+/// Bottles are fetched with `brew fetch --bottle-tag`, so brew's cache is
+/// reused. Casks are downloaded for the platform brew is running on.
+///
+/// brew also needs the metadata of the formulae and casks to install them,
+/// so the signed files of the API cache are copied too, if there are any.
+/// brew checks their signatures, so they hold everything in the API rather
+/// than just what the bundle needs.
+pub fn export_bundle<P: AsRef<Path>>(
+    formulae: &[&str],
+    casks: &[&str],
+    platform: &Platform,
+    dir: P,
+) -> Result<OfflineBundle> {
+    let dir = dir.as_ref();
+    let tag = platform
+        .bottle_tag()
+        .ok_or_else(|| Error::UnsupportedBottle("the platform has no bottle tag".to_owned()))?;
+    std::fs::create_dir_all(dir)?;
+    let tag_flag = format!("--bottle-tag={}", tag);
+    let mut bundle = OfflineBundle {
+        bottle_tag: tag,
+        formulae: Vec::new(),
+        casks: Vec::new(),
+        api_files: Vec::new(),
+    };
+    for package in closure(formulae, platform)? {
+        let version = package.pkg_version().ok_or_else(|| {
+            Error::UnsupportedBottle(format!("{} is HEAD-only", package.full_name))
+        })?;
+        let args = ["--formula", tag_flag.as_str(), package.full_name.as_str()];
+        let file = fetch_into(&args, dir)?;
+        let dependencies = package
+            .dependencies_for(platform, &DepsOptions::new())?
+            .into_iter()
+            .map(|d| d.rsplit('/').next().unwrap_or(d).to_owned())
+            .collect();
+        bundle.formulae.push(OfflineFormula {
+            version,
+            name: package.name,
+            full_name: package.full_name,
+            file,
+            dependencies,
+        });
+    }
+    for &token in casks {
+        let file = fetch_into(&["--cask", token], dir)?;
+        bundle.casks.push(OfflineCask {
+            token: token.to_owned(),
+            file,
+        });
+    }
+    if let Some(api) = cache_dir().map(|cache| cache.join("api")) {
+        for file in API_FILES.iter().filter(|f| api.join(f).is_file()) {
+            std::fs::create_dir_all(dir.join("api"))?;
+            std::fs::copy(api.join(file), dir.join("api").join(file))?;
+            bundle.api_files.push(file.to_string());
+        }
+    }
+    std::fs::write(dir.join(MANIFEST), serde_json::to_string_pretty(&bundle)?)?;
+    Ok(bundle)
+}
+
+/// `names` and their runtime dependencies on `platform`, each after its
+/// dependencies, whether or not they are installed here.
+fn closure(names: &[&str], platform: &Platform) -> Result<Vec<Package>> {
+    let mut found = HashMap::new();
+    let mut frontier: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+    while !frontier.is_empty() {
+        let args: Vec<&str> = frontier.iter().map(String::as_str).collect();
+        let level = packages(&args)?;
+        frontier.clear();
+        for package in level.values() {
+            for dependency in package.dependencies_for(platform, &DepsOptions::new())? {
+                let name = dependency.rsplit('/').next().unwrap_or(dependency);
+                if !found.contains_key(name) && !level.contains_key(name) {
+                    frontier.push(dependency.to_owned());
+                }
+            }
+        }
+        frontier.sort();
+        frontier.dedup();
+        found.extend(level);
+    }
+    ordered(found, platform)
+}
+
+/// The packages of `found`, by name, each after its dependencies on
+/// `platform`.
+fn ordered(mut found: HashMap<String, Package>, platform: &Platform) -> Result<Vec<Package>> {
+    let mut names: Vec<&String> = found.keys().collect();
+    names.sort();
+    let mut ordered = Vec::new();
+    let mut visited = HashSet::new();
+    for name in names {
+        visit(name, &found, platform, &mut visited, &mut ordered)?;
+    }
+    Ok(ordered
+        .into_iter()
+        .filter_map(|name| found.remove(&name))
+        .collect())
+}
+
+/// Adds `name` to `ordered` after its dependencies.
+fn visit(
+    name: &str,
+    found: &HashMap<String, Package>,
+    platform: &Platform,
+    visited: &mut HashSet<String>,
+    ordered: &mut Vec<String>,
+) -> Result<()> {
+    if !visited.insert(name.to_owned()) {
+        return Ok(());
+    }
+    if let Some(package) = found.get(name) {
+        for dependency in package.dependencies_for(platform, &DepsOptions::new())? {
+            let dependency = dependency.rsplit('/').next().unwrap_or(dependency);
+            visit(dependency, found, platform, visited, ordered)?;
+        }
+        ordered.push(name.to_owned());
+    }
+    Ok(())
+}
+
+/// Downloads with `brew fetch`, then copies the file from brew's cache into
+/// `dir`, returning its name.
+fn fetch_into(args: &[&str], dir: &Path) -> Result<String> {
+    let cached = cached_path(args)?;
+    let mut fetch = vec!["fetch"];
+    fetch.extend(args);
+    let output = brew(&fetch)?;
+    if !output.success() {
+        return Err(brew_error(&output, Error::UnknownError));
+    }
+    let name = cached
+        .file_name()
+        .ok_or_else(|| Error::PackageNotFound(Failure::default()))?
+        .to_string_lossy()
+        .into_owned();
+    std::fs::copy(&cached, dir.join(&name))?;
+    Ok(name)
+}
+
+/// Where brew keeps the download for `args`, as printed by `brew --cache`.
+fn cached_path(args: &[&str]) -> Result<PathBuf> {
+    let mut command = vec!["--cache"];
+    command.extend(args);
+    let output = brew(&command)?;
+    if output.success() {
+        Ok(PathBuf::from(output.stdout().trim()))
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+/// What happened to a formula or cask of a bundle.
+#[derive(Debug)]
+pub enum OfflineOutcome {
+    Installed,
+    AlreadyInstalled,
+    Failed(Error),
+    /// The formula was not installed because `dependency` could not be.
+    Skipped {
+        dependency: String,
+    },
+}
+
+/// Installs the formulae and casks of the bundle in `dir`, exported with
+/// `export_bundle`, without network access.
+///
+/// The bundle's API metadata is put in brew's cache, unless the cache
+/// has newer files, and brew is run without auto-updating, so it uses the
+/// metadata rather than fetching it. Each formula is then installed from
+/// its bottle file with `brew install`, after its dependencies. Cask
+/// downloads are put in brew's cache before `brew install --cask`.
+pub fn install_bundle<P: AsRef<Path>>(dir: P) -> Result<Vec<(String, OfflineOutcome)>> {
+    let dir = dir.as_ref();
+    let bundle = OfflineBundle::read(dir)?;
+    if let Some(api) = cache_dir().map(|cache| cache.join("api")) {
+        for file in &bundle.api_files {
+            let (from, to) = (dir.join("api").join(file), api.join(file));
+            if !is_newer(&to, &from) {
+                place(&from, &to)?;
+            }
+        }
+    }
+    Brew::current()
+        .env("HOMEBREW_NO_AUTO_UPDATE", "1")
+        .enter(|| install_from(dir, &bundle))
+}
+
+/// Checks if the file at `a` was modified after the one at `b`.
+fn is_newer(a: &Path, b: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(a), modified(b)) {
+        (Some(a), Some(b)) => a > b,
+        _ => false,
+    }
+}
+
+/// Installs the formulae and casks of `bundle`, whose files are in `dir`.
+fn install_from(dir: &Path, bundle: &OfflineBundle) -> Result<Vec<(String, OfflineOutcome)>> {
+    let cellar = cellar()?;
+    let mut outcomes: Vec<(String, OfflineOutcome)> = Vec::new();
+    for formula in &bundle.formulae {
+        let failed = formula.dependencies.iter().find(|d| {
+            outcomes.iter().any(|(name, outcome)| {
+                name == *d
+                    && matches!(
+                        outcome,
+                        OfflineOutcome::Failed(_) | OfflineOutcome::Skipped { .. }
+                    )
+            })
+        });
+        let outcome = if let Some(dependency) = failed {
+            OfflineOutcome::Skipped {
+                dependency: dependency.clone(),
+            }
+        } else if cellar.join(&formula.name).join(&formula.version).is_dir() {
+            OfflineOutcome::AlreadyInstalled
+        } else {
+            let file = dir.join(&formula.file);
+            let file = file.to_string_lossy();
+            run(&["install", "--formula", "--ignore-dependencies", &file])
+        };
+        outcomes.push((formula.name.clone(), outcome));
+    }
+    for cask in &bundle.casks {
+        let outcome = match cached_path(&["--cask", &cask.token]) {
+            Ok(cached) => match place(&dir.join(&cask.file), &cached) {
+                Ok(()) => run(&["install", "--cask", &cask.token]),
+                Err(e) => OfflineOutcome::Failed(e),
+            },
+            Err(e) => OfflineOutcome::Failed(e),
+        };
+        outcomes.push((cask.token.clone(), outcome));
+    }
+    Ok(outcomes)
+}
+
+/// Copies `file` to `cached`, creating its directory.
+fn place(file: &Path, cached: &Path) -> Result<()> {
+    if let Some(parent) = cached.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::copy(file, cached)?;
+    Ok(())
+}
+
+fn run(args: &[&str]) -> OfflineOutcome {
+    match brew(args) {
+        Ok(output) if output.success() => OfflineOutcome::Installed,
+        Ok(output) if output.stderr().contains("is already installed") => {
+            OfflineOutcome::AlreadyInstalled
+        }
+        Ok(output) => OfflineOutcome::Failed(brew_error(&output, Error::InstallFailed)),
+        Err(e) => OfflineOutcome::Failed(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_dependencies_first() {
+        use crate::tests::formula;
+        let platform = Platform::from_bottle_tag("arm64_sonoma").unwrap();
+        let found: HashMap<String, Package> = vec![
+            formula("jq", serde_json::json!({"dependencies": ["oniguruma"]})),
+            formula("ripgrep", serde_json::json!({"dependencies": ["pcre2"]})),
+            formula("pcre2", serde_json::json!({})),
+            formula("oniguruma", serde_json::json!({})),
+        ]
+        .into_iter()
+        .map(|p| (p.name.clone(), p))
+        .collect();
+        let names: Vec<String> = ordered(found, &platform)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, ["oniguruma", "jq", "pcre2", "ripgrep"]);
+    }
+
+    #[test]
+    fn reads_manifests() {
+        let dir = std::env::temp_dir().join(format!("brew-rs-offline-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bundle = OfflineBundle {
+            bottle_tag: "arm64_sonoma".to_owned(),
+            formulae: vec![OfflineFormula {
+                name: "oniguruma".to_owned(),
+                full_name: "oniguruma".to_owned(),
+                version: "6.9.9".to_owned(),
+                file: "oniguruma--6.9.9.arm64_sonoma.bottle.tar.gz".to_owned(),
+                dependencies: Vec::new(),
+            }],
+            casks: Vec::new(),
+            api_files: vec!["formula.jws.json".to_owned()],
+        };
+        std::fs::write(dir.join(MANIFEST), serde_json::to_string(&bundle).unwrap()).unwrap();
+        assert_eq!(OfflineBundle::read(&dir).unwrap(), bundle);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+//! Finding outdated formulae and casks, and watching for new ones.
+//!
+//! ``` no_run
+//! use brew::outdated::watch_outdated;
+//! use std::time::Duration;
+//!
+//! let watcher = watch_outdated(Duration::from_secs(6 * 60 * 60), |result| match result {
+//!     Ok(outdated) => {
+//!         for package in outdated {
+//!             println!("{} {} is available", package.name, package.current_version);
+//!         }
+//!     }
+//!     Err(e) => eprintln!("could not check for updates: {:?}", e),
+//! });
+//! // ...
+//! watcher.stop();
+//! ```
+use crate::command::brew;
+use crate::upgrade_summary::UpgradeKind;
+use crate::{brew_error, update, Brew, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// An installed formula or cask with a newer version available.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct OutdatedPackage {
+    pub name: String,
+    pub installed_versions: Vec<String>,
+    pub current_version: String,
+    #[serde(default)]
+    pub pinned: bool,
+    /// Whether this is a cask, rather than a formula.
+    #[serde(skip)]
+    pub cask: bool,
+}
+
+impl OutdatedPackage {
+    /// Whether upgrading brings a new version, or only rebuilds the
+    /// installed one.
+    pub fn kind(&self) -> UpgradeKind {
+        match self.installed_versions.last() {
+            Some(installed) => UpgradeKind::between(installed, &self.current_version),
+            None => UpgradeKind::Version,
+        }
+    }
+}
+
+/// Lists the outdated formulae and casks, with `brew outdated`.
+///
+/// This uses what brew knows, so run `update` first for the latest versions.
+pub fn outdated() -> Result<Vec<OutdatedPackage>> {
+    let output = brew(&["outdated", "--json=v2"])?;
+    if output.success() {
+        parse_outdated(output.stdout())
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+pub(crate) fn parse_outdated(json: &str) -> Result<Vec<OutdatedPackage>> {
+    #[derive(Deserialize)]
+    struct V2 {
+        formulae: Vec<OutdatedPackage>,
+        casks: Vec<OutdatedPackage>,
+    }
+    let v2: V2 = serde_json::from_str(json)?;
+    let casks = v2
+        .casks
+        .into_iter()
+        .map(|c| OutdatedPackage { cask: true, ..c });
+    Ok(v2.formulae.into_iter().chain(casks).collect())
+}
+
+/// A thread started by `watch_outdated`. Dropping it stops the thread
+/// without waiting for it.
+#[derive(Debug)]
+pub struct OutdatedWatcher {
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl OutdatedWatcher {
+    /// Stops watching, waiting for a check in progress to finish.
+    pub fn stop(self) {
+        drop(self.stop);
+        // The callback panicking has already been reported.
+        let _ = self.thread.join();
+    }
+}
+
+/// Runs `update` and `outdated` every `interval` on a new thread, calling
+/// `callback` with the packages that became outdated since the last check.
+///
+/// The first check is made right away, and reports everything outdated.
+/// A package is reported again if it is upgraded and later outdated, or if a
+/// newer version comes out. Errors are passed to `callback`, and the next
+/// check is made as usual.
+pub fn watch_outdated<F>(interval: Duration, mut callback: F) -> OutdatedWatcher
+where
+    F: FnMut(Result<Vec<OutdatedPackage>>) + Send + 'static,
+{
+    let (stop, stopped) = mpsc::channel::<()>();
+    let thread = Brew::current().spawn(move || {
+        let mut seen = HashSet::new();
+        loop {
+            match update().and_then(|_| outdated()) {
+                Ok(current) => {
+                    let new = newly_outdated(&mut seen, current);
+                    if !new.is_empty() {
+                        callback(Ok(new));
+                    }
+                }
+                Err(e) => callback(Err(e)),
+            }
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => return,
+            }
+        }
+    });
+    OutdatedWatcher { stop, thread }
+}
+
+/// The packages in `current` that were not in `seen` at the same version,
+/// replacing `seen` with `current`.
+fn newly_outdated(
+    seen: &mut HashSet<(String, String)>,
+    current: Vec<OutdatedPackage>,
+) -> Vec<OutdatedPackage> {
+    let keys: HashSet<(String, String)> = current
+        .iter()
+        .map(|p| (p.name.clone(), p.current_version.clone()))
+        .collect();
+    let new = current
+        .into_iter()
+        .filter(|p| !seen.contains(&(p.name.clone(), p.current_version.clone())))
+        .collect();
+    *seen = keys;
+    new
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_newly_outdated() {
+        let check = |json: &str| parse_outdated(json).unwrap();
+        let mut seen = HashSet::new();
+        let first = newly_outdated(
+            &mut seen,
+            check(
+                r#"{"formulae": [{"name": "jq", "installed_versions": ["1.6"],
+                    "current_version": "1.7", "pinned": false, "pinned_version": null}],
+                    "casks": [{"name": "firefox", "installed_versions": ["120.0"],
+                    "current_version": "121.0"}]}"#,
+            ),
+        );
+        assert_eq!(first.len(), 2);
+        assert!(first[1].cask);
+        let second = newly_outdated(
+            &mut seen,
+            check(
+                r#"{"formulae": [{"name": "jq", "installed_versions": ["1.6"],
+                    "current_version": "1.7", "pinned": false, "pinned_version": null}],
+                    "casks": [{"name": "firefox", "installed_versions": ["120.0"],
+                    "current_version": "121.0.1"}]}"#,
+            ),
+        );
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].current_version, "121.0.1");
+        assert_eq!(second[0].kind(), UpgradeKind::Version);
+    }
+}
@@ -0,0 +1,496 @@
+//! Operating systems and architectures, as Homebrew names them.
+use crate::deps::DepsOptions;
+use crate::system::SystemInfo;
+use crate::{Bottle, File, Package, Requirement};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// An operating system Homebrew runs on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Os {
+    MacOs,
+    Linux,
+}
+
+/// A CPU architecture Homebrew runs on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Arch {
+    X86_64,
+    Arm64,
+}
+
+impl Arch {
+    /// The architecture as Homebrew names it.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Arch::X86_64 => "x86_64",
+            Arch::Arm64 => "arm64",
+        }
+    }
+}
+
+impl FromStr for Arch {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "x86_64" | "intel" => Ok(Arch::X86_64),
+            "arm64" | "arm" | "aarch64" => Ok(Arch::Arm64),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A macOS release that Homebrew builds bottles for, oldest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MacOsVersion {
+    ElCapitan,
+    Sierra,
+    HighSierra,
+    Mojave,
+    Catalina,
+    BigSur,
+    Monterey,
+    Ventura,
+    Sonoma,
+    Sequoia,
+    Tahoe,
+}
+
+impl MacOsVersion {
+    /// Every version, oldest first.
+    pub const ALL: [MacOsVersion; 11] = [
+        MacOsVersion::ElCapitan,
+        MacOsVersion::Sierra,
+        MacOsVersion::HighSierra,
+        MacOsVersion::Mojave,
+        MacOsVersion::Catalina,
+        MacOsVersion::BigSur,
+        MacOsVersion::Monterey,
+        MacOsVersion::Ventura,
+        MacOsVersion::Sonoma,
+        MacOsVersion::Sequoia,
+        MacOsVersion::Tahoe,
+    ];
+
+    /// The codename used in bottle tags, such as `big_sur`.
+    pub fn codename(&self) -> &'static str {
+        match self {
+            MacOsVersion::ElCapitan => "el_capitan",
+            MacOsVersion::Sierra => "sierra",
+            MacOsVersion::HighSierra => "high_sierra",
+            MacOsVersion::Mojave => "mojave",
+            MacOsVersion::Catalina => "catalina",
+            MacOsVersion::BigSur => "big_sur",
+            MacOsVersion::Monterey => "monterey",
+            MacOsVersion::Ventura => "ventura",
+            MacOsVersion::Sonoma => "sonoma",
+            MacOsVersion::Sequoia => "sequoia",
+            MacOsVersion::Tahoe => "tahoe",
+        }
+    }
+
+    /// The version number, such as `10.15` or `14`.
+    pub fn number(&self) -> &'static str {
+        match self {
+            MacOsVersion::ElCapitan => "10.11",
+            MacOsVersion::Sierra => "10.12",
+            MacOsVersion::HighSierra => "10.13",
+            MacOsVersion::Mojave => "10.14",
+            MacOsVersion::Catalina => "10.15",
+            MacOsVersion::BigSur => "11",
+            MacOsVersion::Monterey => "12",
+            MacOsVersion::Ventura => "13",
+            MacOsVersion::Sonoma => "14",
+            MacOsVersion::Sequoia => "15",
+            MacOsVersion::Tahoe => "26",
+        }
+    }
+}
+
+impl FromStr for MacOsVersion {
+    type Err = ();
+
+    /// Parses a codename (`big_sur`) or version number (`11`, `11.2.3`,
+    /// `10.15`).
+    fn from_str(s: &str) -> Result<Self, ()> {
+        if let Some(v) = Self::ALL.iter().find(|v| v.codename() == s) {
+            return Ok(*v);
+        }
+        let mut parts = s.split('.');
+        let major = parts.next().ok_or(())?;
+        let number = match major {
+            "10" => format!("10.{}", parts.next().ok_or(())?),
+            major => major.to_owned(),
+        };
+        Self::ALL
+            .iter()
+            .find(|v| v.number() == number)
+            .copied()
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for MacOsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.codename())
+    }
+}
+
+/// The platform brew is running on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Platform {
+    pub os: Os,
+    pub arch: Arch,
+    /// The macOS release, if running on a known version of macOS.
+    pub macos: Option<MacOsVersion>,
+}
+
+impl Platform {
+    /// The bottle tag for this platform, such as `arm64_sonoma` or
+    /// `x86_64_linux`.
+    pub fn bottle_tag(&self) -> Option<String> {
+        match (self.os, self.arch) {
+            (Os::Linux, arch) => Some(format!("{}_linux", arch.as_str())),
+            (Os::MacOs, Arch::X86_64) => self.macos.map(|v| v.codename().to_owned()),
+            (Os::MacOs, Arch::Arm64) => self.macos.map(|v| format!("arm64_{}", v.codename())),
+        }
+    }
+
+    /// Where Homebrew installs itself by default on this platform.
+    pub fn default_prefix(&self) -> PathBuf {
+        PathBuf::from(match (self.os, self.arch) {
+            (Os::Linux, _) => "/home/linuxbrew/.linuxbrew",
+            (Os::MacOs, Arch::Arm64) => "/opt/homebrew",
+            (Os::MacOs, Arch::X86_64) => "/usr/local",
+        })
+    }
+
+    /// Parses a bottle tag, such as `arm64_sonoma` or `x86_64_linux`.
+    pub fn from_bottle_tag(tag: &str) -> Option<Platform> {
+        if let Some(arch) = tag.strip_suffix("_linux") {
+            return Some(Platform {
+                os: Os::Linux,
+                arch: arch.parse().ok()?,
+                macos: None,
+            });
+        }
+        let (arch, codename) = match tag.strip_prefix("arm64_") {
+            Some(codename) => (Arch::Arm64, codename),
+            None => (Arch::X86_64, tag),
+        };
+        Some(Platform {
+            os: Os::MacOs,
+            arch,
+            macos: Some(codename.parse().ok()?),
+        })
+    }
+}
+
+/// Detects the platform this process is running on.
+///
+/// The architecture is the one this crate was compiled for, so a process
+/// running under Rosetta reports `Arch::X86_64`. On macOS the release is
+/// read from `sw_vers`.
+pub fn current_platform() -> Platform {
+    let os = if cfg!(target_os = "macos") {
+        Os::MacOs
+    } else {
+        Os::Linux
+    };
+    let arch = if cfg!(target_arch = "aarch64") {
+        Arch::Arm64
+    } else {
+        Arch::X86_64
+    };
+    let macos = match os {
+        Os::MacOs => std::process::Command::new("sw_vers")
+            .arg("-productVersion")
+            .output()
+            .ok()
+            .and_then(|o| String::from_utf8(o.stdout).ok())
+            .and_then(|v| v.trim().parse().ok()),
+        Os::Linux => None,
+    };
+    Platform { os, arch, macos }
+}
+
+impl Bottle {
+    /// The bottle file to install on `platform`.
+    ///
+    /// Like brew, this falls back to a bottle for an older macOS release on
+    /// the same architecture, and then to an `all` bottle.
+    pub fn file_for(&self, platform: &Platform) -> Option<&File> {
+        if let Some(file) = platform.bottle_tag().and_then(|t| self.files.get(&t)) {
+            return Some(file);
+        }
+        if let (Os::MacOs, Some(current)) = (platform.os, platform.macos) {
+            let older = MacOsVersion::ALL
+                .iter()
+                .rev()
+                .filter(|&&v| v < current)
+                .filter_map(|&v| {
+                    let tag = Platform {
+                        macos: Some(v),
+                        ..*platform
+                    }
+                    .bottle_tag()?;
+                    self.files.get(&tag)
+                })
+                .next();
+            if older.is_some() {
+                return older;
+            }
+        }
+        self.files.get("all")
+    }
+}
+
+impl Package {
+    /// The stable bottle file to install on `platform`, if there is one.
+    pub fn bottle_for(&self, platform: &Platform) -> Option<&File> {
+        self.bottle.get("stable")?.file_for(platform)
+    }
+
+    /// The names of the direct dependencies on `platform`, as
+    /// `direct_dependencies` finds them for `options`.
+    ///
+    /// This includes `uses_from_macos` dependencies of the kinds `options`
+    /// asks for on Linux, and on macOS versions older than the one that
+    /// provides them.
+    pub fn dependencies_for(
+        &self,
+        platform: &Platform,
+        options: &DepsOptions,
+    ) -> crate::Result<Vec<&str>> {
+        let mut deps = self.direct_dependencies(options)?;
+        for dep in &self.uses_from_macos {
+            let provided = match (platform.os, dep.since, platform.macos) {
+                (Os::Linux, _, _) => false,
+                (Os::MacOs, Some(since), Some(current)) => current >= since,
+                (Os::MacOs, _, _) => true,
+            };
+            let wanted = dep.is_runtime()
+                || (options.include_build && dep.dep_type.iter().any(|t| t == "build"))
+                || (options.include_test && dep.dep_type.iter().any(|t| t == "test"));
+            if !provided && wanted && !deps.contains(&dep.name.as_str()) {
+                deps.push(&dep.name);
+            }
+        }
+        Ok(deps)
+    }
+}
+
+impl Requirement {
+    /// Checks if `platform` satisfies the requirement.
+    ///
+    /// Returns `None` for requirements that do not depend on the platform,
+    /// such as Xcode.
+    pub fn is_satisfied_by(&self, platform: &Platform) -> Option<bool> {
+        match self {
+            Requirement::MacOs { minimum, .. } => Some(
+                platform.os == Os::MacOs
+                    && match (minimum, platform.macos) {
+                        (Some(minimum), Some(current)) => current >= *minimum,
+                        _ => true,
+                    },
+            ),
+            Requirement::MaximumMacOs { maximum, .. } => Some(
+                platform.os == Os::MacOs
+                    && !matches!(platform.macos, Some(current) if current > *maximum),
+            ),
+            Requirement::Linux => Some(platform.os == Os::Linux),
+            Requirement::Arch(arch) => Some(*arch == platform.arch),
+            Requirement::Xcode { .. } | Requirement::Other { .. } => None,
+        }
+    }
+
+    /// Checks if the system described by `info` satisfies the requirement,
+    /// such as before installing, to fail early on "needs macOS 13".
+    ///
+    /// Returns `None` for requirements this crate does not know.
+    pub fn is_satisfied(&self, info: &SystemInfo) -> Option<bool> {
+        match self {
+            Requirement::Xcode { minimum, .. } => Some(match (&info.xcode, minimum) {
+                (Some(installed), Some(minimum)) => !is_older(installed, minimum),
+                (installed, _) => installed.is_some(),
+            }),
+            _ => self.is_satisfied_by(&info.platform),
+        }
+    }
+}
+
+/// Checks if the dotted version `version` is older than `than`, such as
+/// `14.3` than `15.0`.
+fn is_older(version: &str, than: &str) -> bool {
+    let parts = |v: &str| -> Vec<u32> { v.split('.').map(|p| p.parse().unwrap_or(0)).collect() };
+    parts(version) < parts(than)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bottle_tags() {
+        let platform = Platform {
+            os: Os::MacOs,
+            arch: Arch::Arm64,
+            macos: Some(MacOsVersion::Sonoma),
+        };
+        assert_eq!(platform.bottle_tag().as_deref(), Some("arm64_sonoma"));
+        assert_eq!(Platform::from_bottle_tag("arm64_sonoma"), Some(platform));
+        assert_eq!(
+            Platform::from_bottle_tag("x86_64_linux").unwrap().os,
+            Os::Linux
+        );
+        assert_eq!("10.15.7".parse(), Ok(MacOsVersion::Catalina));
+        assert_eq!("14.2".parse(), Ok(MacOsVersion::Sonoma));
+    }
+
+    #[test]
+    fn checks_requirements() {
+        let requirements: Vec<Requirement> = serde_json::from_str(
+            r#"[{"name":"macos","cask":null,"download":null,"version":"13","contexts":[]},
+                {"name":"xcode","cask":null,"download":null,"version":"14.3","contexts":["build"]},
+                {"name":"arch","cask":null,"download":null,"version":"arm64","contexts":[]},
+                {"name":"java","cask":null,"download":null,"version":"1.8+","contexts":[]}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            requirements[0],
+            Requirement::MacOs {
+                minimum: Some(MacOsVersion::Ventura),
+                build: false
+            }
+        );
+        let mut info = crate::system::SystemInfo {
+            platform: Platform::from_bottle_tag("arm64_monterey").unwrap(),
+            homebrew_version: None,
+            macos_version: None,
+            rosetta: false,
+            xcode: Some("15.1".to_owned()),
+            clt: None,
+            linux_distro: None,
+            glibc: None,
+            gcc: None,
+            config: Default::default(),
+        };
+        let satisfied: Vec<_> = requirements.iter().map(|r| r.is_satisfied(&info)).collect();
+        assert_eq!(satisfied, [Some(false), Some(true), Some(true), None]);
+        info.xcode = Some("14.2".to_owned());
+        assert_eq!(requirements[1].is_satisfied(&info), Some(false));
+        assert_eq!(
+            serde_json::to_value(&requirements[1]).unwrap()["contexts"],
+            serde_json::json!(["build"])
+        );
+    }
+
+    #[test]
+    fn falls_back_to_older_bottles() {
+        let file = |url: &str| File {
+            url: url.to_owned(),
+            sha256: String::new(),
+            #[cfg(feature = "preserve-unknown")]
+            extra: Default::default(),
+        };
+        let bottle = Bottle {
+            rebuild: 0,
+            cellar: None,
+            prefix: None,
+            root_url: String::new(),
+            files: vec![
+                ("arm64_ventura".to_owned(), file("ventura")),
+                ("monterey".to_owned(), file("intel")),
+            ]
+            .into_iter()
+            .collect(),
+            #[cfg(feature = "preserve-unknown")]
+            extra: Default::default(),
+        };
+        let platform = Platform {
+            os: Os::MacOs,
+            arch: Arch::Arm64,
+            macos: Some(MacOsVersion::Sequoia),
+        };
+        assert_eq!(bottle.file_for(&platform).unwrap().url, "ventura");
+        let platform = Platform {
+            arch: Arch::X86_64,
+            ..platform
+        };
+        assert_eq!(bottle.file_for(&platform).unwrap().url, "intel");
+    }
+
+    #[test]
+    fn selects_linux_bottles() {
+        let file = |url: &str| File {
+            url: url.to_owned(),
+            sha256: String::new(),
+            #[cfg(feature = "preserve-unknown")]
+            extra: Default::default(),
+        };
+        let mut bottle = Bottle {
+            rebuild: 0,
+            cellar: None,
+            prefix: None,
+            root_url: String::new(),
+            files: vec![
+                ("sonoma".to_owned(), file("sonoma")),
+                ("x86_64_linux".to_owned(), file("linux")),
+            ]
+            .into_iter()
+            .collect(),
+            #[cfg(feature = "preserve-unknown")]
+            extra: Default::default(),
+        };
+        let linux = Platform::from_bottle_tag("x86_64_linux").unwrap();
+        assert_eq!(bottle.file_for(&linux).unwrap().url, "linux");
+        let arm = Platform {
+            arch: Arch::Arm64,
+            ..linux
+        };
+        assert!(bottle.file_for(&arm).is_none());
+        bottle.files.insert("all".to_owned(), file("all"));
+        assert_eq!(bottle.file_for(&arm).unwrap().url, "all");
+        assert_eq!(
+            linux.default_prefix(),
+            PathBuf::from("/home/linuxbrew/.linuxbrew")
+        );
+    }
+
+    #[test]
+    fn finds_platform_dependencies() {
+        let package = crate::tests::formula(
+            "git",
+            serde_json::json!({
+                "dependencies": ["gettext"],
+                "recommended_dependencies": ["pcre2"],
+                "uses_from_macos": ["curl", {"zlib": "build"}, "pcre2"],
+            }),
+        );
+        let linux = Platform::from_bottle_tag("x86_64_linux").unwrap();
+        let sonoma = Platform::from_bottle_tag("arm64_sonoma").unwrap();
+        assert_eq!(
+            package
+                .dependencies_for(&linux, &DepsOptions::new())
+                .unwrap(),
+            ["gettext", "pcre2", "curl"]
+        );
+        assert_eq!(
+            package
+                .dependencies_for(&linux, &DepsOptions::new().include_build())
+                .unwrap(),
+            ["gettext", "pcre2", "curl", "zlib"]
+        );
+        assert_eq!(
+            package
+                .dependencies_for(&sonoma, &DepsOptions::new().include_build())
+                .unwrap(),
+            ["gettext", "pcre2"]
+        );
+        assert!(package
+            .dependencies_for(&linux, &DepsOptions::new().recursive())
+            .is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! A minimal reader for XML property lists, as written by brew for launchd.
+use std::collections::BTreeMap;
+
+/// A value in a property list.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlistValue {
+    String(String),
+    Bool(bool),
+    Integer(i64),
+    Real(f64),
+    /// A `<date>`, in its ISO 8601 form.
+    Date(String),
+    /// A `<data>` element, still base64 encoded.
+    Data(String),
+    Array(Vec<PlistValue>),
+    Dict(BTreeMap<String, PlistValue>),
+}
+
+impl PlistValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PlistValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            PlistValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict(&self) -> Option<&BTreeMap<String, PlistValue>> {
+        match self {
+            PlistValue::Dict(d) => Some(d),
+            _ => None,
+        }
+    }
+}
+
+/// Parses an XML property list, returning its root value.
+///
+/// Returns `None` if `xml` is not a well formed XML property list. Binary
+/// property lists are not supported.
+pub fn parse(xml: &str) -> Option<PlistValue> {
+    let mut parser = Parser { rest: xml };
+    parser.skip_misc();
+    if !parser.rest.starts_with("<plist") {
+        return None;
+    }
+    parser.rest = &parser.rest[parser.rest.find('>')? + 1..];
+    let value = parser.value()?;
+    if parser.closes("plist") {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    /// Skips whitespace, comments, the XML declaration and the doctype.
+    fn skip_misc(&mut self) {
+        loop {
+            self.rest = self.rest.trim_start();
+            let end = if self.rest.starts_with("<!--") {
+                self.rest.find("-->").map(|i| i + 3)
+            } else if self.rest.starts_with("<?") || self.rest.starts_with("<!") {
+                self.rest.find('>').map(|i| i + 1)
+            } else {
+                return;
+            };
+            match end {
+                Some(end) => self.rest = &self.rest[end..],
+                None => return,
+            }
+        }
+    }
+
+    /// Reads a tag, returning its name and whether it is self closing.
+    fn tag(&mut self) -> Option<(&'a str, bool)> {
+        self.skip_misc();
+        let rest = self.rest.strip_prefix('<')?;
+        let end = rest.find('>')?;
+        let inner = &rest[..end];
+        self.rest = &rest[end + 1..];
+        match inner.strip_suffix('/') {
+            Some(name) => Some((name.trim(), true)),
+            None => Some((inner.trim(), false)),
+        }
+    }
+
+    /// Reads text up to `</name>`.
+    fn text(&mut self, name: &str) -> Option<String> {
+        let close = format!("</{}>", name);
+        let end = self.rest.find(&close)?;
+        let text = unescape(&self.rest[..end]);
+        self.rest = &self.rest[end + close.len()..];
+        Some(text)
+    }
+
+    fn value(&mut self) -> Option<PlistValue> {
+        let (name, empty) = self.tag()?;
+        let text = |p: &mut Self| {
+            if empty {
+                Some(String::new())
+            } else {
+                p.text(name)
+            }
+        };
+        Some(match name {
+            "string" => PlistValue::String(text(self)?),
+            "true" if empty => PlistValue::Bool(true),
+            "false" if empty => PlistValue::Bool(false),
+            "integer" => PlistValue::Integer(text(self)?.trim().parse().ok()?),
+            "real" => PlistValue::Real(text(self)?.trim().parse().ok()?),
+            "date" => PlistValue::Date(text(self)?.trim().to_owned()),
+            "data" => PlistValue::Data(text(self)?.split_whitespace().collect()),
+            "array" => {
+                let mut values = Vec::new();
+                if !empty {
+                    while !self.closes("array") {
+                        values.push(self.value()?);
+                    }
+                }
+                PlistValue::Array(values)
+            }
+            "dict" => {
+                let mut dict = BTreeMap::new();
+                if !empty {
+                    while !self.closes("dict") {
+                        match self.tag()? {
+                            ("key", false) => {
+                                let key = self.text("key")?;
+                                dict.insert(key, self.value()?);
+                            }
+                            _ => return None,
+                        }
+                    }
+                }
+                PlistValue::Dict(dict)
+            }
+            _ => return None,
+        })
+    }
+
+    /// Consumes `</name>` if it is next.
+    fn closes(&mut self, name: &str) -> bool {
+        self.skip_misc();
+        let close = format!("</{}>", name);
+        match self.rest.strip_prefix(close.as_str()) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_launchd_plist() {
+        let plist = parse(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>KeepAlive</key>
+	<dict>
+		<key>SuccessfulExit</key>
+		<false/>
+	</dict>
+	<key>Label</key>
+	<string>homebrew.mxcl.redis</string>
+	<key>ProgramArguments</key>
+	<array>
+		<string>/opt/homebrew/opt/redis/bin/redis-server</string>
+		<string>/opt/homebrew/etc/redis.conf</string>
+	</array>
+	<key>RunAtLoad</key>
+	<true/>
+	<key>StandardErrorPath</key>
+	<string>/opt/homebrew/var/log/redis.log</string>
+</dict>
+</plist>
+"#,
+        )
+        .unwrap();
+        let dict = plist.as_dict().unwrap();
+        assert_eq!(dict["Label"].as_str(), Some("homebrew.mxcl.redis"));
+        assert_eq!(dict["RunAtLoad"].as_bool(), Some(true));
+        assert_eq!(
+            dict["KeepAlive"].as_dict().unwrap()["SuccessfulExit"],
+            PlistValue::Bool(false)
+        );
+    }
+}
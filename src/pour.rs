@@ -0,0 +1,285 @@
+//! Experimental: installing bottles without brew.
+//!
+//! Pouring extracts a verified bottle into the Cellar, writes its
+//! INSTALL_RECEIPT.json and links it into the prefix, the way
+//! `brew install` does for a bottle. It is meant for minimal containers
+//! where installing brew itself is too heavy.
+//!
+//! Only bottles that can be used without relocation are supported: those
+//! built for the same Cellar, or for any Cellar without placeholders to
+//! replace. Other bottles fail with `Error::UnsupportedBottle`. Nothing is
+//! done about dependencies, which must be poured first.
+use crate::platform::current_platform;
+use crate::tarball::{self, Placeholder};
+use crate::{Error, Failure, Package, Result};
+use std::path::{Path, PathBuf};
+
+/// The directories in a keg that are linked into the prefix.
+const LINKED_DIRS: [&str; 7] = [
+    "bin",
+    "sbin",
+    "include",
+    "lib",
+    "share",
+    "etc",
+    "Frameworks",
+];
+
+/// Represents how to pour a bottle.
+#[derive(Clone, Debug, Default)]
+pub struct PourOptions {
+    prefix: Option<PathBuf>,
+    as_dependency: bool,
+    no_link: bool,
+}
+
+impl PourOptions {
+    /// Represents no options added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pours into the Homebrew installation at `prefix`, instead of the
+    /// default prefix for the current platform.
+    pub fn prefix<P: Into<PathBuf>>(mut self, prefix: P) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Records the formula as installed as a dependency, rather than on
+    /// request.
+    pub fn as_dependency(mut self) -> Self {
+        self.as_dependency = true;
+        self
+    }
+
+    /// Only creates the `opt` link, without linking the keg into the
+    /// prefix, as for keg-only formulae.
+    pub fn no_link(mut self) -> Self {
+        self.no_link = true;
+        self
+    }
+
+    fn prefix_path(&self) -> PathBuf {
+        match &self.prefix {
+            Some(prefix) => prefix.clone(),
+            None => current_platform().default_prefix(),
+        }
+    }
+}
+
+/// Pours the bottle tarball at `bottle`, after checking it has the checksum
+/// `sha256`, returning the path of the installed keg.
+pub fn pour<P: AsRef<Path>>(bottle: P, sha256: &str, options: &PourOptions) -> Result<PathBuf> {
+    let bottle = bottle.as_ref();
+    let prefix = options.prefix_path();
+    let cellar = prefix.join("Cellar");
+    if crate::bottle::sha256_file(bottle)? != sha256 {
+        return Err(Error::ChecksumMismatch(bottle.to_owned()));
+    }
+
+    let contents = tarball::list(bottle)?;
+    // brew rewrites the receipt when pouring, so placeholders in it are fine.
+    if let Some(entry) = contents
+        .relocatable()
+        .find(|e| e.path != Path::new("INSTALL_RECEIPT.json"))
+    {
+        return Err(Error::UnsupportedBottle(format!(
+            "{} contains {} and must be relocated by brew",
+            entry.path.display(),
+            entry.placeholders[0].as_str()
+        )));
+    }
+    let keg = cellar.join(&contents.name).join(&contents.version);
+    if keg.exists() {
+        return Err(Error::AlreadyInstalled(Failure {
+            message: format!("{} is already installed", keg.display()),
+            ..Failure::default()
+        }));
+    }
+
+    std::fs::create_dir_all(&cellar)?;
+    tarball::extract(bottle, &cellar)?;
+    write_receipt(&keg, options)?;
+    let rel_keg = Path::new("Cellar")
+        .join(&contents.name)
+        .join(&contents.version);
+    std::fs::create_dir_all(prefix.join("opt"))?;
+    replace_symlink(
+        &Path::new("..").join(&rel_keg),
+        &prefix.join("opt").join(&contents.name),
+    )?;
+    if !options.no_link {
+        for dir in LINKED_DIRS.iter() {
+            link_tree(&prefix, &rel_keg, Path::new(dir))?;
+        }
+    }
+    Ok(keg)
+}
+
+impl Package {
+    /// Pours the downloaded stable bottle at `bottle` for the current
+    /// platform, returning the path of the installed keg.
+    ///
+    /// Keg-only formulae are not linked into the prefix. See the module
+    /// documentation for what is not supported.
+    pub fn pour<P: AsRef<Path>>(&self, bottle: P, options: &PourOptions) -> Result<PathBuf> {
+        let platform = current_platform();
+        let (stable, file) = match self
+            .bottle
+            .get("stable")
+            .and_then(|b| Some((b, b.file_for(&platform)?)))
+        {
+            Some(found) => found,
+            None => {
+                return Err(Error::UnsupportedBottle(format!(
+                    "{} has no bottle for this platform",
+                    self.name
+                )))
+            }
+        };
+        check_cellar(
+            stable.cellar.as_deref(),
+            &options.prefix_path().join("Cellar"),
+        )?;
+        let mut options = options.clone();
+        if self.keg_only {
+            options.no_link = true;
+        }
+        pour(bottle, &file.sha256, &options)
+    }
+}
+
+/// Checks that a bottle built for `required`, the `cellar` of its metadata,
+/// can be poured into `cellar` without relocation.
+fn check_cellar(required: Option<&str>, cellar: &Path) -> Result<()> {
+    match required {
+        None | Some(":any") | Some(":any_skip_relocation") => Ok(()),
+        Some(required) if Path::new(required) == cellar => Ok(()),
+        Some(required) => Err(Error::UnsupportedBottle(format!(
+            "the bottle was built for {} and must be relocated by brew to use {}",
+            required,
+            cellar.display()
+        ))),
+    }
+}
+
+/// Updates the receipt shipped in the bottle, as brew does when pouring.
+fn write_receipt(keg: &Path, options: &PourOptions) -> Result<()> {
+    let path = keg.join("INSTALL_RECEIPT.json");
+    let mut receipt = match std::fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents)?,
+        Err(_) => serde_json::json!({ "built_as_bottle": true }),
+    };
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let prefix = keg
+        .ancestors()
+        .nth(3)
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if let Some(receipt) = receipt.as_object_mut() {
+        receipt.insert("poured_from_bottle".to_owned(), true.into());
+        receipt.insert(
+            "installed_on_request".to_owned(),
+            (!options.as_dependency).into(),
+        );
+        receipt.insert(
+            "installed_as_dependency".to_owned(),
+            options.as_dependency.into(),
+        );
+        receipt.insert("time".to_owned(), time.into());
+    }
+    let contents = serde_json::to_string_pretty(&receipt)?
+        .replace(Placeholder::Prefix.as_str(), &prefix)
+        .replace(Placeholder::Cellar.as_str(), &format!("{}/Cellar", prefix));
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Links `dir` of the keg at `rel_keg` (relative to `prefix`) into the
+/// prefix, creating directories and symlinking everything else.
+fn link_tree(prefix: &Path, rel_keg: &Path, dir: &Path) -> Result<()> {
+    let source = prefix.join(rel_keg).join(dir);
+    if !source.is_dir() {
+        return Ok(());
+    }
+    let target = prefix.join(dir);
+    if !target.is_dir() {
+        std::fs::create_dir_all(&target)?;
+    }
+    for entry in std::fs::read_dir(&source)? {
+        let entry = entry?;
+        let rel = dir.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            link_tree(prefix, rel_keg, &rel)?;
+        } else {
+            let link = prefix.join(&rel);
+            let destination = link_destination(rel_keg, &rel);
+            match std::fs::read_link(&link) {
+                Ok(existing) if existing == destination => continue,
+                Err(_) if !link.exists() => {}
+                _ => {
+                    return Err(Error::InstallFailed(Failure {
+                        message: format!(
+                            "Could not symlink {}: {} already exists",
+                            rel.display(),
+                            link.display()
+                        ),
+                        ..Failure::default()
+                    }))
+                }
+            }
+            std::os::unix::fs::symlink(destination, link)?;
+        }
+    }
+    Ok(())
+}
+
+/// The relative symlink destination for `rel` in the prefix, pointing into
+/// the keg at `rel_keg`.
+fn link_destination(rel_keg: &Path, rel: &Path) -> PathBuf {
+    let mut destination = PathBuf::new();
+    for _ in 1..rel.components().count() {
+        destination.push("..");
+    }
+    destination.join(rel_keg).join(rel)
+}
+
+/// Points the symlink at `link` to `destination`, replacing any existing
+/// symlink.
+fn replace_symlink(destination: &Path, link: &Path) -> Result<()> {
+    if std::fs::symlink_metadata(link).is_ok_and(|m| m.file_type().is_symlink()) {
+        std::fs::remove_file(link)?;
+    }
+    std::os::unix::fs::symlink(destination, link)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_relative_to_prefix() {
+        let keg = Path::new("Cellar/jq/1.7.1");
+        assert_eq!(
+            link_destination(keg, Path::new("bin/jq")),
+            PathBuf::from("../Cellar/jq/1.7.1/bin/jq")
+        );
+        assert_eq!(
+            link_destination(keg, Path::new("share/man/man1/jq.1")),
+            PathBuf::from("../../../Cellar/jq/1.7.1/share/man/man1/jq.1")
+        );
+    }
+
+    #[test]
+    fn checks_cellar() {
+        let cellar = Path::new("/opt/homebrew/Cellar");
+        assert!(check_cellar(Some(":any_skip_relocation"), cellar).is_ok());
+        assert!(check_cellar(Some("/opt/homebrew/Cellar"), cellar).is_ok());
+        assert!(check_cellar(Some("/usr/local/Cellar"), cellar).is_err());
+    }
+}
@@ -0,0 +1,360 @@
+//! Download progress and install phases reported while installing or
+//! fetching packages.
+use crate::command::{brew_streaming, Output};
+use crate::{brew_error, Error, Options, Package, Result};
+
+/// The progress of a single download, such as a bottle or source tarball.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DownloadProgress {
+    /// The URL being downloaded.
+    pub url: String,
+    /// How much of the download has completed, from 0 to 100.
+    pub percent: f32,
+    /// The bytes downloaded so far, if known.
+    ///
+    /// brew only reports the percentage on curl's progress bar, so this is
+    /// only known for bottles this crate downloads itself, which it does for
+    /// the package being installed or fetched with the `download` feature.
+    pub downloaded: Option<u64>,
+    /// The size of the download in bytes, if known.
+    pub total: Option<u64>,
+}
+
+/// A stage of installing a package.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InstallPhase {
+    /// Downloading bottles, sources or casks.
+    Fetching,
+    /// Checking checksums or attestations of downloads.
+    Verifying,
+    /// Unpacking a bottle into the Cellar.
+    Pouring,
+    /// Building from source.
+    Building,
+    /// Linking into the prefix, or moving a cask's artifacts into place.
+    Linking,
+    /// Running the formula's `post_install`.
+    Postinstall,
+    /// Showing the package's caveats.
+    Caveats,
+}
+
+impl InstallPhase {
+    /// A label for the phase, such as `pouring`.
+    pub fn label(self) -> &'static str {
+        match self {
+            InstallPhase::Fetching => "fetching",
+            InstallPhase::Verifying => "verifying",
+            InstallPhase::Pouring => "pouring",
+            InstallPhase::Building => "building",
+            InstallPhase::Linking => "linking",
+            InstallPhase::Postinstall => "post-installing",
+            InstallPhase::Caveats => "caveats",
+        }
+    }
+
+    /// The phase brew has entered if it writes `line`, if any.
+    ///
+    /// brew starts each step with a `==>` line. While building, those are
+    /// the commands run, such as `==> ./configure --prefix=...`.
+    pub fn of_line(line: &str) -> Option<InstallPhase> {
+        let line = line.trim();
+        if line.starts_with("Already downloaded") {
+            return Some(InstallPhase::Fetching);
+        }
+        let step = line.strip_prefix("==> ")?;
+        let starts = |prefixes: &[&str]| prefixes.iter().any(|p| step.starts_with(p));
+        if starts(&["Fetching", "Downloading"]) {
+            Some(InstallPhase::Fetching)
+        } else if starts(&["Verifying"]) {
+            Some(InstallPhase::Verifying)
+        } else if starts(&["Pouring"]) {
+            Some(InstallPhase::Pouring)
+        } else if starts(&["Linking", "Moving", "Symlinking", "Creating symlinks"]) {
+            Some(InstallPhase::Linking)
+        } else if starts(&[
+            "Postinstalling",
+            "Running post_install",
+            "Running post-install",
+        ]) {
+            Some(InstallPhase::Postinstall)
+        } else if starts(&["Caveats"]) {
+            Some(InstallPhase::Caveats)
+        } else if starts(&["./", "/"]) || step.starts_with(|c: char| c.is_ascii_lowercase()) {
+            Some(InstallPhase::Building)
+        } else {
+            None
+        }
+    }
+}
+
+/// Something that happened while installing, as reported to
+/// `Package::install_with_events`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InstallEvent {
+    /// brew entered a new phase.
+    Phase(InstallPhase),
+    Download(DownloadProgress),
+}
+
+/// For internal use, turns brew's output into download progress.
+///
+/// Every download is reported at 0% when it starts and at 100% when brew
+/// moves on. In between, brew only draws curl's progress bar when attached
+/// to a terminal, so intermediate updates are only reported with the `pty`
+/// feature.
+#[derive(Default)]
+pub(crate) struct ProgressParser {
+    current: Option<DownloadProgress>,
+}
+
+impl ProgressParser {
+    pub(crate) fn line(&mut self, line: &str, f: &mut dyn FnMut(&DownloadProgress)) {
+        let line = line.trim();
+        if let Some(url) = line.strip_prefix("==> Downloading ") {
+            self.finish(f);
+            let progress = DownloadProgress {
+                url: url.trim().to_owned(),
+                percent: 0.0,
+                downloaded: None,
+                total: None,
+            };
+            f(&progress);
+            self.current = Some(progress);
+        } else if let Some(progress) = &mut self.current {
+            if let Some(percent) = parse_progress_bar(line) {
+                if percent > progress.percent {
+                    progress.percent = percent;
+                    f(progress);
+                }
+            } else if line.starts_with("==>") || line.starts_with("Already downloaded") {
+                self.finish(f);
+            }
+        }
+    }
+
+    pub(crate) fn finish(&mut self, f: &mut dyn FnMut(&DownloadProgress)) {
+        if let Some(mut progress) = self.current.take() {
+            if progress.percent < 100.0 {
+                progress.percent = 100.0;
+                f(&progress);
+            }
+        }
+    }
+}
+
+/// Parses a curl `--progress-bar` line such as `#####     45.2%`.
+fn parse_progress_bar(line: &str) -> Option<f32> {
+    let (bar, percent) = line.rsplit_once(' ').unwrap_or(("", line));
+    if !bar
+        .chars()
+        .all(|c| matches!(c, '#' | '-' | '=' | 'O' | ' '))
+    {
+        return None;
+    }
+    percent.strip_suffix('%')?.parse().ok()
+}
+
+fn run_with_progress<F>(args: &[&str], mut f: F) -> Result<Output>
+where
+    F: FnMut(&DownloadProgress),
+{
+    let mut parser = ProgressParser::default();
+    let output = brew_streaming(args, &mut |line| parser.line(line, &mut f))?;
+    parser.finish(&mut f);
+    Ok(output)
+}
+
+fn run_with_events<F>(args: &[&str], mut f: F) -> Result<Output>
+where
+    F: FnMut(&InstallEvent),
+{
+    let mut parser = ProgressParser::default();
+    let mut phase = None;
+    let output = brew_streaming(args, &mut |line| {
+        if let Some(next) = InstallPhase::of_line(line) {
+            if phase != Some(next) {
+                phase = Some(next);
+                f(&InstallEvent::Phase(next));
+            }
+        }
+        parser.line(line, &mut |p| f(&InstallEvent::Download(p.clone())));
+    })?;
+    parser.finish(&mut |p| f(&InstallEvent::Download(p.clone())));
+    Ok(output)
+}
+
+/// Downloads the bottle brew would pour for `package` into brew's cache,
+/// calling `f` as bytes arrive, so that brew finds it already downloaded.
+///
+/// This is best effort: if anything fails, brew downloads the bottle itself.
+/// Nothing is downloaded if `args` ask brew to build from source.
+#[cfg(feature = "download")]
+fn prefetch_bottle(package: &Package, args: &[&str], f: &mut dyn FnMut(&DownloadProgress)) {
+    if args
+        .iter()
+        .any(|arg| matches!(*arg, "--build-from-source" | "-s" | "--HEAD"))
+    {
+        return;
+    }
+    let download = match package.bottle_download(&crate::platform::current_platform()) {
+        Some(download) => download,
+        None => return,
+    };
+    let path = match crate::command::brew(&["--cache", &package.full_name]) {
+        Ok(output) if output.success() => std::path::PathBuf::from(output.stdout().trim()),
+        _ => return,
+    };
+    if path.as_os_str().is_empty() || path.exists() {
+        return;
+    }
+    let mut progress = DownloadProgress {
+        url: download.url.clone(),
+        percent: 0.0,
+        downloaded: Some(0),
+        total: None,
+    };
+    let finished = download
+        .download_to_with_progress(&path, |downloaded, total| {
+            progress.downloaded = Some(downloaded);
+            progress.total = total;
+            if let Some(total) = total.filter(|&total| total > 0) {
+                progress.percent = (downloaded as f32 / total as f32 * 100.0).min(100.0);
+            }
+            f(&progress);
+        })
+        .is_ok();
+    if finished && progress.percent < 100.0 {
+        progress.percent = 100.0;
+        f(&progress);
+    }
+}
+
+/// Without the `download` feature, brew downloads everything itself.
+#[cfg(not(feature = "download"))]
+fn prefetch_bottle(_: &Package, _: &[&str], _: &mut dyn FnMut(&DownloadProgress)) {}
+
+impl Package {
+    /// Like `install`, calling `f` as brew moves through the phases of
+    /// installing and as downloads progress.
+    pub fn install_with_events<F>(&self, options: &Options, mut f: F) -> Result<Package>
+    where
+        F: FnMut(&InstallEvent),
+    {
+        self.install_using(options, |args| {
+            prefetch_bottle(self, args, &mut |p| f(&InstallEvent::Download(p.clone())));
+            run_with_events(args, f)
+        })
+    }
+
+    /// Like `install`, calling `f` as downloads progress.
+    pub fn install_with_progress<F>(&self, options: &Options, mut f: F) -> Result<Package>
+    where
+        F: FnMut(&DownloadProgress),
+    {
+        self.install_using(options, |args| {
+            prefetch_bottle(self, args, &mut f);
+            run_with_progress(args, f)
+        })
+    }
+
+    /// Downloads the package's bottle or source without installing it.
+    pub fn fetch(&self) -> Result<()> {
+        self.fetch_with_progress(|_| {})
+    }
+
+    /// Like `fetch`, calling `f` as downloads progress.
+    pub fn fetch_with_progress<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&DownloadProgress),
+    {
+        let args = ["fetch", self.full_name.as_str()];
+        prefetch_bottle(self, &args, &mut f);
+        let output = run_with_progress(&args, f)?;
+        if output.success() {
+            Ok(())
+        } else {
+            Err(brew_error(&output, Error::UnknownError))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_progress() {
+        use std::io::Write;
+
+        // What brew writes to a terminal: colored steps, and curl redrawing
+        // its progress bar after carriage returns.
+        let terminal = "\x1b[34m==>\x1b[0m \x1b[1mFetching \x1b[32mjq\x1b[39m\x1b[0m\r\n\
+            \x1b[34m==>\x1b[0m \x1b[1mDownloading https://ghcr.io/v2/homebrew/core/jq/blobs/sha256:abc\x1b[0m\r\n\
+            \r#####                                                                      6.1%\
+            \r####################################                                      50.0%\
+            \r\x1b[34m==>\x1b[0m \x1b[1mPouring jq--1.7.arm64_sonoma.bottle.tar.gz\x1b[0m\r\n";
+        let mut lines = Vec::new();
+        let mut on_line = |line: &str| lines.push(line.to_owned());
+        let mut writer = crate::command::LineWriter::new(&mut on_line);
+        writer.write_all(terminal.as_bytes()).unwrap();
+        writer.finish();
+
+        let mut reports = Vec::new();
+        let mut parser = ProgressParser::default();
+        for line in &lines {
+            parser.line(line, &mut |p| reports.push(p.percent));
+        }
+        parser.finish(&mut |p| reports.push(p.percent));
+        assert_eq!(reports, vec![0.0, 6.1, 50.0, 100.0]);
+    }
+
+    #[test]
+    fn piped_progress_starts_and_finishes() {
+        // Piped, brew writes the steps but curl draws no progress bar.
+        let mut reports = Vec::new();
+        let mut parser = ProgressParser::default();
+        for line in &[
+            "==> Fetching jq",
+            "==> Downloading https://ghcr.io/v2/homebrew/core/jq/blobs/sha256:abc",
+            "==> Pouring jq--1.7.arm64_sonoma.bottle.tar.gz",
+        ] {
+            parser.line(line, &mut |p| reports.push(p.percent));
+        }
+        parser.finish(&mut |p| reports.push(p.percent));
+        assert_eq!(reports, vec![0.0, 100.0]);
+    }
+
+    #[test]
+    fn classifies_phases() {
+        let phases: Vec<_> = [
+            "==> Fetching jq",
+            "==> Downloading https://ghcr.io/v2/homebrew/core/jq/blobs/sha256:abc",
+            "==> Verifying attestation for jq",
+            "==> Installing jq",
+            "==> Pouring jq--1.7.arm64_sonoma.bottle.tar.gz",
+            "==> ./configure --prefix=/opt/homebrew/Cellar/jq/1.7",
+            "==> make install",
+            "==> Running post_install",
+            "==> Caveats",
+            "==> Summary",
+            "jq was installed",
+        ]
+        .iter()
+        .filter_map(|line| InstallPhase::of_line(line))
+        .collect();
+        assert_eq!(
+            phases,
+            [
+                InstallPhase::Fetching,
+                InstallPhase::Fetching,
+                InstallPhase::Verifying,
+                InstallPhase::Pouring,
+                InstallPhase::Building,
+                InstallPhase::Building,
+                InstallPhase::Postinstall,
+                InstallPhase::Caveats,
+            ]
+        );
+    }
+}
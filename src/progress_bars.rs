@@ -0,0 +1,138 @@
+//! Showing install phases and download progress with indicatif progress
+//! bars.
+//!
+//! ``` no_run
+//! # fn main() -> brew::Result<()> {
+//! use brew::progress_bars::ProgressBars;
+//!
+//! let packages = vec![brew::Package::new("jq")?, brew::Package::new("ripgrep")?];
+//! let bars = ProgressBars::new();
+//! for result in bars.install_all(&packages, &brew::Options::new()) {
+//!     result?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::progress::{DownloadProgress, InstallEvent};
+use crate::{Options, Package, Result};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::time::Duration;
+
+const TEMPLATE: &str = "{prefix:.bold} {spinner} {bar:30} {percent:>3}% {wide_msg}";
+
+/// A bar for each package, inside a `MultiProgress`.
+#[derive(Clone)]
+pub struct ProgressBars {
+    multi: MultiProgress,
+    style: ProgressStyle,
+}
+
+impl Default for ProgressBars {
+    fn default() -> Self {
+        Self::with_multi(MultiProgress::new())
+    }
+}
+
+impl ProgressBars {
+    /// Draws bars to stderr.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the bars to `multi`, to show them alongside other bars.
+    pub fn with_multi(multi: MultiProgress) -> Self {
+        ProgressBars {
+            multi,
+            style: ProgressStyle::with_template(TEMPLATE).expect("the template is valid"),
+        }
+    }
+
+    /// Draws the bars in `style` instead of the default.
+    pub fn style(mut self, style: ProgressStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// The `MultiProgress` the bars are drawn in.
+    pub fn multi(&self) -> &MultiProgress {
+        &self.multi
+    }
+
+    /// Adds a bar for `name`, returning it with a callback for
+    /// `Package::install_with_progress` and `Package::fetch_with_progress`
+    /// that updates it.
+    ///
+    /// The bar shows each download in turn, such as the bottles of
+    /// dependencies, and is left for the caller to finish. It counts bytes
+    /// when they are known, and the percentage otherwise. Without the `pty`
+    /// feature brew reports neither while it downloads, so the spinner turns
+    /// while a download is running, even if the bar does not move.
+    pub fn bar(&self, name: &str) -> (ProgressBar, impl FnMut(&DownloadProgress)) {
+        let bar = self.multi.add(ProgressBar::new(100));
+        bar.set_style(self.style.clone());
+        bar.set_prefix(name.to_owned());
+        bar.set_message("waiting");
+        let updated = bar.clone();
+        (bar, move |progress: &DownloadProgress| {
+            if progress.percent == 0.0 && progress.downloaded.unwrap_or(0) == 0 {
+                let file = progress.url.rsplit('/').next().unwrap_or(&progress.url);
+                updated.set_message(file.to_owned());
+                updated.enable_steady_tick(Duration::from_millis(100));
+            } else if progress.percent >= 100.0 {
+                updated.disable_steady_tick();
+            }
+            match (progress.downloaded, progress.total) {
+                (Some(downloaded), Some(total)) => {
+                    updated.set_length(total);
+                    updated.set_position(downloaded);
+                }
+                _ => {
+                    updated.set_length(100);
+                    updated.set_position(progress.percent as u64);
+                }
+            }
+        })
+    }
+
+    /// Adds a bar for `name` as `bar` does, with a callback for
+    /// `Package::install_with_events` that also shows the phase brew is in,
+    /// such as `pouring`, between downloads.
+    pub fn phase_bar(&self, name: &str) -> (ProgressBar, impl FnMut(&InstallEvent)) {
+        let (bar, mut downloaded) = self.bar(name);
+        let updated = bar.clone();
+        (bar, move |event: &InstallEvent| match event {
+            InstallEvent::Phase(phase) => updated.set_message(phase.label()),
+            InstallEvent::Download(progress) => downloaded(progress),
+        })
+    }
+
+    /// Installs `package` as `Package::install` does, with a bar for it.
+    pub fn install(&self, package: &Package, options: &Options) -> Result<Package> {
+        let (bar, f) = self.phase_bar(&package.name);
+        let result = package.install_with_events(options, f);
+        finish(&bar, &result);
+        result
+    }
+
+    /// Installs each of `packages` in turn, with a bar for each from the
+    /// start, so the bars show what is left to do.
+    pub fn install_all(&self, packages: &[Package], options: &Options) -> Vec<Result<Package>> {
+        let bars: Vec<_> = packages.iter().map(|p| self.phase_bar(&p.name)).collect();
+        packages
+            .iter()
+            .zip(bars)
+            .map(|(package, (bar, f))| {
+                let result = package.install_with_events(options, f);
+                finish(&bar, &result);
+                result
+            })
+            .collect()
+    }
+}
+
+fn finish<T>(bar: &ProgressBar, result: &Result<T>) {
+    match result {
+        Ok(_) => bar.finish_with_message("done"),
+        Err(_) => bar.abandon_with_message("failed"),
+    }
+}
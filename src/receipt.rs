@@ -0,0 +1,304 @@
+//! Reading the INSTALL_RECEIPT.json files brew writes into each keg.
+//!
+//! Receipts hold most of what `brew info --installed` reports about an
+//! install, and can be read without spawning brew.
+use crate::{cellar, prefix, Package, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// The contents of a keg's INSTALL_RECEIPT.json.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Receipt {
+    pub homebrew_version: Option<String>,
+    #[serde(default)]
+    pub used_options: Vec<String>,
+    #[serde(default)]
+    pub unused_options: Vec<String>,
+    #[serde(default)]
+    pub built_as_bottle: bool,
+    #[serde(default)]
+    pub poured_from_bottle: bool,
+    #[serde(default)]
+    pub installed_as_dependency: bool,
+    #[serde(default)]
+    pub installed_on_request: bool,
+    pub time: Option<u64>,
+    pub compiler: Option<String>,
+    /// Every runtime dependency, direct or not. `None` for receipts written
+    /// by old versions of brew.
+    pub runtime_dependencies: Option<Vec<ReceiptDependency>>,
+    /// Where the formula was installed from.
+    pub source: Option<ReceiptSource>,
+}
+
+/// Where a formula was installed from, as recorded in its receipt.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ReceiptSource {
+    /// The formula file, or the API cache file it was read from.
+    pub path: Option<String>,
+    pub tap: Option<String>,
+    /// The commit of the tap when the formula was installed.
+    pub tap_git_head: Option<String>,
+    /// Which of the formula's specs was installed, such as `stable`.
+    pub spec: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ReceiptDependency {
+    pub full_name: String,
+    pub version: Option<String>,
+    #[serde(default)]
+    pub declared_directly: bool,
+}
+
+impl Receipt {
+    /// Reads the receipt of the keg at `keg`.
+    pub fn read<P: AsRef<Path>>(keg: P) -> Result<Receipt> {
+        let contents = std::fs::read_to_string(keg.as_ref().join("INSTALL_RECEIPT.json"))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// How the keg was installed.
+    pub fn install_method(&self) -> InstallMethod {
+        match (self.poured_from_bottle, self.built_as_bottle) {
+            (true, _) => InstallMethod::Poured,
+            (false, true) => InstallMethod::BuiltAsBottle,
+            (false, false) => InstallMethod::BuiltFromSource,
+        }
+    }
+}
+
+/// How a keg was installed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstallMethod {
+    /// Poured from a bottle.
+    Poured,
+    BuiltFromSource,
+    /// Built from source to be made into a bottle, with `--build-bottle`.
+    BuiltAsBottle,
+}
+
+/// How a keg of a formula was installed.
+#[derive(Clone, Debug)]
+pub struct KegProvenance {
+    pub keg: PathBuf,
+    /// The version of the keg, with its revision, such as `3.1.4_1`.
+    pub version: String,
+    pub method: InstallMethod,
+    pub used_options: Vec<String>,
+    /// The compiler a source build used, such as `clang`.
+    pub compiler: Option<String>,
+    /// When the keg was installed, in seconds since the Unix epoch.
+    pub time: Option<u64>,
+    pub source: Option<ReceiptSource>,
+}
+
+impl Package {
+    /// Describes how each installed keg of the formula was installed, from
+    /// its receipt, oldest first. Kegs without a readable receipt are
+    /// skipped.
+    pub fn install_provenance(&self) -> Result<Vec<KegProvenance>> {
+        let rack = cellar()?.join(&self.name);
+        if !rack.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut kegs = Vec::new();
+        for entry in std::fs::read_dir(rack)? {
+            let keg = entry?.path();
+            if let Ok(receipt) = Receipt::read(&keg) {
+                kegs.push(KegProvenance {
+                    version: keg
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    method: receipt.install_method(),
+                    used_options: receipt.used_options,
+                    compiler: receipt.compiler,
+                    time: receipt.time,
+                    source: receipt.source,
+                    keg,
+                });
+            }
+        }
+        kegs.sort_by_key(|k| k.time);
+        Ok(kegs)
+    }
+}
+
+/// Reads the receipt of the active keg of every installed formula.
+///
+/// The active keg is the one `opt/<name>` points to, or the most recently
+/// installed keg if there is no opt link. Formulae without a readable
+/// receipt are skipped.
+pub fn receipts() -> Result<HashMap<String, Receipt>> {
+    let opt = prefix()?.join("opt");
+    let mut receipts = HashMap::new();
+    for entry in std::fs::read_dir(cellar()?)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(keg) = active_keg(&opt.join(&name), &entry.path()) {
+            if let Ok(receipt) = Receipt::read(keg) {
+                receipts.insert(name, receipt);
+            }
+        }
+    }
+    Ok(receipts)
+}
+
+fn active_keg(opt: &Path, rack: &Path) -> Option<PathBuf> {
+    if let Ok(keg) = std::fs::canonicalize(opt) {
+        return Some(keg);
+    }
+    std::fs::read_dir(rack)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .max_by_key(|e| e.metadata().and_then(|m| m.modified()).ok())
+        .map(|e| e.path())
+}
+
+/// Names of the formulae that were installed only as dependencies and are no
+/// longer needed by anything installed on request.
+///
+/// Receipts written by old versions of brew do not list runtime
+/// dependencies. If a formula installed on request has one of those, any
+/// formula could be its dependency, so none are reported.
+pub fn orphan_names(receipts: &HashMap<String, Receipt>) -> Vec<String> {
+    let unknown = receipts
+        .values()
+        .any(|r| r.installed_on_request && r.runtime_dependencies.is_none());
+    if unknown {
+        return Vec::new();
+    }
+    let needed: HashSet<&str> = receipts
+        .values()
+        .filter(|r| r.installed_on_request)
+        .flat_map(|r| r.runtime_dependencies.iter().flatten())
+        .map(|d| d.full_name.rsplit('/').next().unwrap_or(&d.full_name))
+        .collect();
+    let mut orphans: Vec<String> = receipts
+        .iter()
+        .filter(|(name, r)| !r.installed_on_request && !needed.contains(name.as_str()))
+        .map(|(name, _)| name.clone())
+        .collect();
+    orphans.sort();
+    orphans
+}
+
+/// Names of the formulae that no other installed formula depends on at
+/// runtime, as `brew leaves` lists them.
+///
+/// With `installed_on_request` of `Some(true)`, only those installed on
+/// request are listed, as with `brew leaves --installed-on-request`, and
+/// with `Some(false)` only those installed as dependencies, as with
+/// `--installed-as-dependency`.
+pub fn leaf_names(
+    receipts: &HashMap<String, Receipt>,
+    installed_on_request: Option<bool>,
+) -> Vec<String> {
+    let needed: HashSet<&str> = receipts
+        .values()
+        .flat_map(|r| r.runtime_dependencies.iter().flatten())
+        .map(|d| d.full_name.rsplit('/').next().unwrap_or(&d.full_name))
+        .collect();
+    let mut leaves: Vec<String> = receipts
+        .iter()
+        .filter(|(name, _)| !needed.contains(name.as_str()))
+        .filter(|(_, r)| installed_on_request.is_none_or(|o| r.installed_on_request == o))
+        .map(|(name, _)| name.clone())
+        .collect();
+    leaves.sort();
+    leaves
+}
+
+/// Returns the names of the installed formulae that no other installed
+/// formula depends on, as `brew leaves` does, filtered by
+/// `installed_on_request` as in `leaf_names`.
+///
+/// This only reads receipts, so is much faster than `brew leaves`, and never
+/// spawns brew.
+pub fn leaves_local(installed_on_request: Option<bool>) -> Result<Vec<String>> {
+    Ok(leaf_names(&receipts()?, installed_on_request))
+}
+
+/// Returns the formulae installed only as dependencies that nothing
+/// installed on request still needs.
+///
+/// This is computed from receipts rather than by asking
+/// `brew autoremove --dry-run`, and only spawns brew to look up the orphans
+/// themselves.
+pub fn orphans() -> Result<Vec<Package>> {
+    let names = orphan_names(&receipts()?);
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    let mut packages: Vec<Package> = crate::packages(&names)?.into_values().collect();
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(packages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt(on_request: bool, deps: &[&str]) -> Receipt {
+        serde_json::from_value(serde_json::json!({
+            "installed_on_request": on_request,
+            "installed_as_dependency": !on_request,
+            "runtime_dependencies": deps
+                .iter()
+                .map(|d| serde_json::json!({ "full_name": d, "version": "1.0" }))
+                .collect::<Vec<_>>(),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn finds_orphans() {
+        let mut receipts = HashMap::new();
+        receipts.insert("ffmpeg".to_owned(), receipt(true, &["x264", "lame"]));
+        receipts.insert("x264".to_owned(), receipt(false, &[]));
+        receipts.insert("lame".to_owned(), receipt(false, &[]));
+        receipts.insert("old-lib".to_owned(), receipt(false, &["older-lib"]));
+        receipts.insert("older-lib".to_owned(), receipt(false, &[]));
+        assert_eq!(orphan_names(&receipts), vec!["old-lib", "older-lib"]);
+    }
+
+    #[test]
+    fn finds_leaves() {
+        let mut receipts = HashMap::new();
+        receipts.insert("ffmpeg".to_owned(), receipt(true, &["x264", "lame"]));
+        receipts.insert("x264".to_owned(), receipt(false, &[]));
+        receipts.insert("lame".to_owned(), receipt(false, &[]));
+        receipts.insert("old-lib".to_owned(), receipt(false, &["older-lib"]));
+        receipts.insert("older-lib".to_owned(), receipt(false, &[]));
+        assert_eq!(leaf_names(&receipts, None), vec!["ffmpeg", "old-lib"]);
+        assert_eq!(leaf_names(&receipts, Some(true)), vec!["ffmpeg"]);
+        assert_eq!(leaf_names(&receipts, Some(false)), vec!["old-lib"]);
+    }
+
+    #[test]
+    fn describes_install_methods() {
+        let mut receipt = receipt(true, &[]);
+        assert_eq!(receipt.install_method(), InstallMethod::BuiltFromSource);
+        receipt.built_as_bottle = true;
+        assert_eq!(receipt.install_method(), InstallMethod::BuiltAsBottle);
+        receipt.poured_from_bottle = true;
+        assert_eq!(receipt.install_method(), InstallMethod::Poured);
+    }
+
+    #[test]
+    fn keeps_dependencies_of_old_receipts() {
+        let mut receipts = HashMap::new();
+        receipts.insert("ffmpeg".to_owned(), receipt(true, &["x264"]));
+        receipts.insert("x264".to_owned(), receipt(false, &[]));
+        receipts.insert("lame".to_owned(), receipt(false, &[]));
+        assert_eq!(orphan_names(&receipts), vec!["lame"]);
+        let mut old = receipt(true, &[]);
+        old.runtime_dependencies = None;
+        receipts.insert("sox".to_owned(), old);
+        assert!(orphan_names(&receipts).is_empty());
+    }
+}
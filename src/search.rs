@@ -0,0 +1,357 @@
+//! Searching for formulae and casks.
+use crate::cask::casks;
+use crate::command::brew;
+use crate::{api, brew_error, packages, Error, Package, Result};
+use std::collections::HashMap;
+
+/// How similar a name must be to be suggested, from 0 to 1.
+const MIN_SCORE: f64 = 0.6;
+/// The most suggestions `suggest` returns.
+const MAX_SUGGESTIONS: usize = 5;
+
+/// A formula found by `search`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormulaHit {
+    pub name: String,
+    /// The tap, such as `homebrew/core`.
+    pub tap: Option<String>,
+    pub desc: Option<String>,
+}
+
+/// A cask found by `search`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaskHit {
+    pub token: String,
+    /// The tap, such as `homebrew/cask`.
+    pub tap: Option<String>,
+    pub desc: Option<String>,
+}
+
+/// The formulae and casks found by `search`, in brew's order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchResults {
+    pub formulae: Vec<FormulaHit>,
+    pub casks: Vec<CaskHit>,
+}
+
+/// A search result, with how popular it is.
+#[derive(Clone, Debug)]
+pub struct RankedResult {
+    pub package: Package,
+    /// Installs over the last 90 days, or 0 if brew did not report any.
+    pub installs_90d: usize,
+}
+
+/// Searches for formulae and casks matching `query`, with `brew search`,
+/// then looks up their descriptions.
+///
+/// `query` may be a regex surrounded by slashes, such as `/^jq$/`.
+pub fn search(query: &str) -> Result<SearchResults> {
+    let (formulae, casks) = search_names(query)?;
+    Ok(SearchResults {
+        formulae: formula_hits(formulae)?,
+        casks: cask_hits(casks)?,
+    })
+}
+
+/// The names of the formulae and casks matching `query`.
+fn search_names(query: &str) -> Result<(Vec<String>, Vec<String>)> {
+    let output = brew(&["search", query])?;
+    if output.success() {
+        Ok(parse_names(output.stdout()))
+    } else if output.stderr().contains("No formulae") {
+        Ok((Vec::new(), Vec::new()))
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+fn formula_hits(names: Vec<String>) -> Result<Vec<FormulaHit>> {
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+    let args: Vec<&str> = names.iter().map(String::as_str).collect();
+    let found = packages(&args)?;
+    Ok(formula_hits_in(names, found))
+}
+
+/// The hits for `names`, taking their taps and descriptions from `found`.
+fn formula_hits_in(names: Vec<String>, mut found: HashMap<String, Package>) -> Vec<FormulaHit> {
+    names
+        .into_iter()
+        .map(|name| {
+            let package = found.remove(split_tap(&name).1);
+            FormulaHit {
+                tap: package
+                    .as_ref()
+                    .and_then(|p| p.tap.clone())
+                    .or_else(|| split_tap(&name).0.map(str::to_owned)),
+                desc: package.and_then(|p| p.desc),
+                name,
+            }
+        })
+        .collect()
+}
+
+fn cask_hits(tokens: Vec<String>) -> Result<Vec<CaskHit>> {
+    if tokens.is_empty() {
+        return Ok(Vec::new());
+    }
+    let args: Vec<&str> = tokens.iter().map(String::as_str).collect();
+    let mut found: HashMap<String, _> = casks(&args)?
+        .into_iter()
+        .map(|c| (c.token.clone(), c))
+        .collect();
+    Ok(tokens
+        .into_iter()
+        .map(|token| {
+            let cask = found.remove(split_tap(&token).1);
+            CaskHit {
+                tap: cask
+                    .as_ref()
+                    .and_then(|c| c.tap.clone())
+                    .or_else(|| split_tap(&token).0.map(str::to_owned)),
+                desc: cask.and_then(|c| c.desc),
+                token,
+            }
+        })
+        .collect())
+}
+
+/// Splits `user/tap/name` into the tap and the name.
+pub(crate) fn split_tap(name: &str) -> (Option<&str>, &str) {
+    match name.rfind('/') {
+        Some(i) => (Some(&name[..i]), &name[i + 1..]),
+        None => (None, name),
+    }
+}
+
+/// Searches for formulae matching `query`, most installed over the last 90
+/// days first.
+///
+/// Results with the same number of installs keep brew's order.
+pub fn search_ranked(query: &str) -> Result<Vec<RankedResult>> {
+    let (names, _) = search_names(query)?;
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    let mut found = packages(&names)?;
+    let mut results: Vec<RankedResult> = names
+        .iter()
+        .filter_map(|name| found.remove(split_tap(name).1))
+        .map(|package| RankedResult {
+            installs_90d: package
+                .analytics
+                .as_ref()
+                .map_or(0, |a| a.install.total_90d()),
+            package,
+        })
+        .collect();
+    results.sort_by_key(|r| std::cmp::Reverse(r.installs_90d));
+    Ok(results)
+}
+
+/// Whether `name` is a formula, formula alias or cask known to Homebrew.
+///
+/// This reads the lists of names brew caches with its API files, and
+/// otherwise asks `brew info` without parsing what it reports. Names missing
+/// from the lists may still be in a tap, or newer than the cache, so they
+/// are looked up with brew too, as are names with a tap, such as
+/// `user/tap/name`.
+pub fn package_exists(name: &str) -> Result<bool> {
+    if !name.contains('/') && cached_names(name) == Some(true) {
+        return Ok(true);
+    }
+    let output = brew(&["info", "--json=v2", name])?;
+    if output.success() {
+        Ok(true)
+    } else {
+        match brew_error(&output, Error::UnknownError) {
+            Error::PackageNotFound(_) => Ok(false),
+            e => Err(e),
+        }
+    }
+}
+
+/// Whether `name` is in the name lists in the API cache, or `None` if they
+/// are missing.
+fn cached_names(name: &str) -> Option<bool> {
+    let dir = api::cache_dir()?.join("api");
+    let read = |file: &str| std::fs::read_to_string(dir.join(file)).ok();
+    let formulae = read("formula_names.txt")?;
+    let casks = read("cask_names.txt")?;
+    let aliases = read("formula_aliases.txt").unwrap_or_default();
+    Some(
+        formulae.lines().chain(casks.lines()).any(|l| l == name)
+            || aliases.lines().any(|l| l.split('|').next() == Some(name)),
+    )
+}
+
+/// Suggests formulae for a possibly misspelled `name`, best first, with
+/// how similar they are from 0 to 1.
+///
+/// This matches against the names and aliases in Homebrew's API cache, so it
+/// does not touch the network, and only spawns brew if there is no cache.
+pub fn suggest(name: &str) -> Result<Vec<(String, f64)>> {
+    // Only names are needed, so there is no need to read what is installed.
+    let packages = match api::cached_formulae()? {
+        Some(packages) => packages,
+        None => crate::all_packages()?,
+    };
+    let candidates = packages.values().flat_map(|p| {
+        std::iter::once((p.name.as_str(), p.name.as_str()))
+            .chain(p.aliases.iter().map(move |a| (a.as_str(), p.name.as_str())))
+    });
+    Ok(suggest_from(name, candidates))
+}
+
+/// Scores each of `candidates`, as pairs of a name to match and the formula
+/// it stands for, keeping the best score for each formula.
+fn suggest_from<'a, I>(name: &str, candidates: I) -> Vec<(String, f64)>
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let name = name.to_lowercase();
+    let mut best: HashMap<&str, f64> = HashMap::new();
+    for (candidate, formula) in candidates {
+        let score = similarity(&name, &candidate.to_lowercase());
+        if score >= MIN_SCORE {
+            let entry = best.entry(formula).or_insert(score);
+            *entry = entry.max(score);
+        }
+    }
+    let mut suggestions: Vec<(String, f64)> = best
+        .into_iter()
+        .map(|(formula, score)| (formula.to_owned(), score))
+        .collect();
+    suggestions.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    suggestions.truncate(MAX_SUGGESTIONS);
+    suggestions
+}
+
+/// 1 minus the edit distance between `a` and `b`, counting swapped
+/// neighbours as one edit, over the length of the longer.
+fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let len = a.len().max(b.len());
+    if len == 0 {
+        return 1.0;
+    }
+    // d[i][j] is the distance between a[..i] and b[..j].
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    1.0 - d[a.len()][b.len()] as f64 / len as f64
+}
+
+/// Parses the formulae and casks listed under the `==> Formulae` and
+/// `==> Casks` headings. Names before any heading are formulae.
+fn parse_names(stdout: &str) -> (Vec<String>, Vec<String>) {
+    let mut formulae = Vec::new();
+    let mut casks = Vec::new();
+    let mut section = &mut formulae;
+    for line in stdout.lines() {
+        match line.strip_prefix("==> ") {
+            Some("Casks") => section = &mut casks,
+            Some(_) => section = &mut formulae,
+            None => section.extend(line.split_whitespace().map(str::to_owned)),
+        }
+    }
+    (formulae, casks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_search_results() {
+        let (formulae, casks) =
+            parse_names("==> Formulae\njq\njql\n\nacme/tools/jqp\n\n==> Casks\njqbrowser\n");
+        assert_eq!(formulae, vec!["jq", "jql", "acme/tools/jqp"]);
+        assert_eq!(casks, vec!["jqbrowser"]);
+        assert_eq!(split_tap(&formulae[2]), (Some("acme/tools"), "jqp"));
+    }
+
+    #[test]
+    fn takes_taps_from_formulae() {
+        use crate::tests::formula;
+        let found = vec![
+            (
+                "jq",
+                formula("jq", serde_json::json!({"desc": "JSON processor"})),
+            ),
+            (
+                "jqp",
+                formula("jqp", serde_json::json!({"tap": "acme/tools"})),
+            ),
+        ];
+        let found = found.into_iter().map(|(n, p)| (n.to_owned(), p)).collect();
+        let names = vec![
+            "jq".to_owned(),
+            "jqp".to_owned(),
+            "other/tap/gone".to_owned(),
+        ];
+        let hits = formula_hits_in(names, found);
+        assert_eq!(hits[0].tap.as_deref(), Some("homebrew/core"));
+        assert_eq!(hits[0].desc.as_deref(), Some("JSON processor"));
+        assert_eq!(hits[1].tap.as_deref(), Some("acme/tools"));
+        assert_eq!(hits[2].tap.as_deref(), Some("other/tap"));
+        assert_eq!(hits[2].desc, None);
+    }
+
+    #[cfg(feature = "fixtures")]
+    #[test]
+    fn looks_up_names_missing_from_the_cache() {
+        let dir = std::env::temp_dir().join(format!("brew-rs-names-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("api")).unwrap();
+        std::fs::write(dir.join("api/formula_names.txt"), "jq\n").unwrap();
+        std::fs::write(dir.join("api/cask_names.txt"), "firefox\n").unwrap();
+        let mut missing = crate::tests::recording(&["info", "--json=v2", "jqq"], "");
+        missing.stderr = "Error: No available formula with the name \"jqq\".".to_owned();
+        missing.status.code = Some(1);
+        let _guard = crate::fixtures::replay_recordings(vec![
+            crate::tests::recording(&["info", "--json=v2", "acme"], "{}"),
+            missing,
+        ]);
+        crate::Brew::new().cache_dir(&dir).enter(|| {
+            assert!(package_exists("jq").unwrap());
+            assert!(package_exists("acme").unwrap());
+            assert!(!package_exists("jqq").unwrap());
+        });
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn suggests_close_names() {
+        let candidates = vec![
+            ("ripgrep", "ripgrep"),
+            ("rg", "ripgrep"),
+            ("ripgrep-all", "ripgrep-all"),
+            ("grep", "grep"),
+            ("jq", "jq"),
+        ];
+        let suggestions = suggest_from("rigprep", candidates);
+        assert_eq!(suggestions[0].0, "ripgrep");
+        assert!((suggestions[0].1 - 6.0 / 7.0).abs() < 1e-9);
+        assert!(suggestions.iter().all(|(name, _)| name != "jq"));
+        assert_eq!(similarity("ripgrpe", "ripgrep"), 1.0 - 1.0 / 7.0);
+    }
+}
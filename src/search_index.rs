@@ -0,0 +1,284 @@
+//! A search index kept on disk, for searching formulae without spawning
+//! brew.
+//!
+//! ``` no_run
+//! # fn main() -> brew::Result<()> {
+//! use brew::search_index::SearchIndex;
+//!
+//! let index = SearchIndex::load()?;
+//! for entry in index.search("json") {
+//!     println!("{}: {}", entry.name, entry.desc.as_deref().unwrap_or(""));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::api::{cache_dir, cached_formulae, formula_cache_path};
+use crate::search::split_tap;
+use crate::tap::taps;
+use crate::{all_packages, Package, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// A formula, as recorded in the index.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub name: String,
+    pub full_name: String,
+    /// The tap, such as `homebrew/core`.
+    pub tap: String,
+    pub desc: Option<String>,
+    pub aliases: Vec<String>,
+}
+
+impl From<&Package> for IndexEntry {
+    fn from(package: &Package) -> Self {
+        IndexEntry {
+            name: package.name.clone(),
+            full_name: package.full_name.clone(),
+            tap: package.tap.clone().unwrap_or_else(|| {
+                split_tap(&package.full_name)
+                    .0
+                    .unwrap_or("homebrew/core")
+                    .to_owned()
+            }),
+            desc: package.desc.clone(),
+            aliases: package.aliases.clone(),
+        }
+    }
+}
+
+/// The lowercased text of an entry, to match queries against.
+#[derive(Clone, Debug)]
+struct Keys {
+    name: String,
+    aliases: Vec<String>,
+    desc: String,
+}
+
+impl From<&IndexEntry> for Keys {
+    fn from(entry: &IndexEntry) -> Self {
+        Keys {
+            name: entry.name.to_lowercase(),
+            aliases: entry.aliases.iter().map(|a| a.to_lowercase()).collect(),
+            desc: entry.desc.as_deref().unwrap_or_default().to_lowercase(),
+        }
+    }
+}
+
+/// What an index was built from, to tell when it is out of date.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+enum Source {
+    /// The API cache, modified this many seconds after the Unix epoch.
+    ApiCache(u64),
+    /// The installed taps, with the commits they had checked out.
+    Taps(Vec<(String, String)>),
+}
+
+impl Source {
+    /// What `refresh` would build the index from now, or `None` if there
+    /// is no API cache and a tap's commit cannot be read.
+    fn current() -> Result<Option<Source>> {
+        let modified = formula_cache_path()
+            .and_then(|path| std::fs::metadata(path).ok()?.modified().ok())
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+        if let Some(since) = modified {
+            return Ok(Some(Source::ApiCache(since.as_secs())));
+        }
+        let heads = taps()?
+            .into_iter()
+            .map(|tap| Some((tap.name.clone(), tap.head().ok()?)))
+            .collect::<Option<Vec<_>>>();
+        Ok(heads.map(Source::Taps))
+    }
+}
+
+/// An index of formula names, descriptions, aliases and taps.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct SearchIndex {
+    /// What the index was built from, or `None` if that is not known.
+    source: Option<Source>,
+    /// Sorted by name.
+    entries: Vec<IndexEntry>,
+    #[serde(skip)]
+    keys: Vec<Keys>,
+}
+
+impl SearchIndex {
+    /// Where `load` keeps the index, under the Homebrew cache.
+    pub fn default_path() -> Option<PathBuf> {
+        cache_dir().map(|dir| dir.join("brew-rs/search-index.json"))
+    }
+
+    /// Reads the index at the default path, refreshing it and writing it
+    /// back if it is missing or out of date.
+    pub fn load() -> Result<SearchIndex> {
+        let path = Self::default_path();
+        let mut index = match &path {
+            Some(path) if path.is_file() => Self::read(path)?,
+            _ => Self::default(),
+        };
+        if index.refresh()? > 0 {
+            if let Some(path) = path {
+                index.write(path)?;
+            }
+        }
+        Ok(index)
+    }
+
+    /// Reads an index written by `write`.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<SearchIndex> {
+        let mut index: SearchIndex = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        index.keys = index.entries.iter().map(Keys::from).collect();
+        Ok(index)
+    }
+
+    /// Writes the index to `path`, creating its directory.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        Ok(std::fs::write(path, serde_json::to_string(self)?)?)
+    }
+
+    /// Brings the index up to date, returning how many entries changed.
+    ///
+    /// The index is built from Homebrew's API cache, and left alone if the
+    /// cache has not changed since. Without a cache, every formula is read
+    /// with `all_packages`, which is slow, so this is only done again once
+    /// a tap has checked out another commit.
+    pub fn refresh(&mut self) -> Result<usize> {
+        let source = Source::current()?;
+        if source.is_some() && source == self.source {
+            return Ok(0);
+        }
+        let packages = match source {
+            Some(Source::ApiCache(_)) => cached_formulae()?.unwrap_or_default(),
+            _ => all_packages()?,
+        };
+        self.source = source;
+        Ok(self.update(packages.values()))
+    }
+
+    /// Makes the index match `packages`, returning how many entries were
+    /// added, changed or removed. Entries that did not change are kept.
+    pub fn update<'a, I>(&mut self, packages: I) -> usize
+    where
+        I: IntoIterator<Item = &'a Package>,
+    {
+        let mut new: Vec<IndexEntry> = packages.into_iter().map(IndexEntry::from).collect();
+        new.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut changed = 0;
+        let mut old = std::mem::take(&mut self.entries).into_iter().peekable();
+        let mut keys = std::mem::take(&mut self.keys).into_iter();
+        for entry in new {
+            // Drop the old entries before this one, which were removed.
+            while old.peek().is_some_and(|o| o.name < entry.name) {
+                old.next();
+                keys.next();
+                changed += 1;
+            }
+            let key = match old.peek() {
+                Some(o) if o.name == entry.name => {
+                    let same = *o == entry;
+                    old.next();
+                    let key = keys.next();
+                    match key {
+                        Some(key) if same => key,
+                        _ => {
+                            changed += 1;
+                            Keys::from(&entry)
+                        }
+                    }
+                }
+                _ => {
+                    changed += 1;
+                    Keys::from(&entry)
+                }
+            };
+            self.entries.push(entry);
+            self.keys.push(key);
+        }
+        changed + old.count()
+    }
+
+    /// The number of formulae in the index.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Finds the formulae whose name, alias or description contains
+    /// `query`, ignoring case.
+    ///
+    /// Exact names come first, then names that start with `query`, other
+    /// names and aliases, and last descriptions. Ties are by name.
+    pub fn search(&self, query: &str) -> Vec<&IndexEntry> {
+        let query = query.to_lowercase();
+        let mut found: Vec<(u8, &IndexEntry)> = self
+            .keys
+            .iter()
+            .zip(&self.entries)
+            .filter_map(|(keys, entry)| Some((rank(keys, &query)?, entry)))
+            .collect();
+        // The entries are already sorted by name, and the sort is stable.
+        found.sort_by_key(|(rank, _)| *rank);
+        found.into_iter().map(|(_, entry)| entry).collect()
+    }
+}
+
+/// How well `keys` match `query`, lower being better.
+fn rank(keys: &Keys, query: &str) -> Option<u8> {
+    if keys.name == query {
+        Some(0)
+    } else if keys.name.starts_with(query) {
+        Some(1)
+    } else if keys.name.contains(query) || keys.aliases.iter().any(|a| a.contains(query)) {
+        Some(2)
+    } else if keys.desc.contains(query) {
+        Some(3)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, desc: &str, aliases: &[&str]) -> IndexEntry {
+        IndexEntry {
+            name: name.to_owned(),
+            full_name: name.to_owned(),
+            tap: "homebrew/core".to_owned(),
+            desc: Some(desc.to_owned()),
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn ranks_matches() {
+        let entries = vec![
+            entry("fx", "Terminal JSON viewer", &[]),
+            entry(
+                "jq",
+                "Lightweight and flexible command-line JSON processor",
+                &[],
+            ),
+            entry("jql", "JSON query language CLI tool", &[]),
+            entry("gojq", "Pure Go implementation of jq", &["jq-go"]),
+        ];
+        let index = SearchIndex {
+            keys: entries.iter().map(Keys::from).collect(),
+            entries,
+            source: None,
+        };
+        let names: Vec<&str> = index.search("JQ").iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["jq", "jql", "gojq"]);
+        assert_eq!(index.search("json viewer")[0].name, "fx");
+    }
+}
@@ -0,0 +1,481 @@
+//! Background services managed by `brew services`.
+use crate::command::brew;
+use crate::plist::PlistValue;
+use crate::{brew_error, prefix, Error, Options, Package, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The state of a service, as reported by `brew services list`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServiceStatus {
+    Started,
+    Stopped,
+    /// The service is loaded but failed, see `Service::exit_code`.
+    Error,
+    /// The service is not loaded.
+    None,
+    /// A status this crate does not know about, such as `scheduled`.
+    Other(String),
+}
+
+impl ServiceStatus {
+    /// The status as brew prints it.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ServiceStatus::Started => "started",
+            ServiceStatus::Stopped => "stopped",
+            ServiceStatus::Error => "error",
+            ServiceStatus::None => "none",
+            ServiceStatus::Other(s) => s,
+        }
+    }
+}
+
+impl From<&str> for ServiceStatus {
+    fn from(s: &str) -> Self {
+        match s {
+            "started" => ServiceStatus::Started,
+            "stopped" => ServiceStatus::Stopped,
+            "error" => ServiceStatus::Error,
+            "none" => ServiceStatus::None,
+            s => ServiceStatus::Other(s.to_owned()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ServiceStatus {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        Ok(String::deserialize(d)?.as_str().into())
+    }
+}
+
+impl Serialize for ServiceStatus {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        s.serialize_str(self.as_str())
+    }
+}
+
+/// A Homebrew service.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Service {
+    pub name: String,
+    pub status: ServiceStatus,
+    /// The user the service runs as, if it is loaded.
+    pub user: Option<String>,
+    /// The launchd plist or systemd unit of the service, if it is loaded.
+    pub file: Option<PathBuf>,
+    pub exit_code: Option<i32>,
+}
+
+impl Service {
+    /// The launchd plist brew generated for the service.
+    ///
+    /// This is the loaded plist if the service is running, and otherwise the
+    /// one installed with the formula, if any. On Linux services are systemd
+    /// units instead, see `unit_path`.
+    pub fn plist_path(&self) -> Result<Option<PathBuf>> {
+        if let Some(file) = &self.file {
+            let file = expand_home(file);
+            if file.extension().is_some_and(|e| e == "plist") && file.is_file() {
+                return Ok(Some(file));
+            }
+        }
+        let path = prefix()?
+            .join("opt")
+            .join(&self.name)
+            .join(format!("homebrew.mxcl.{}.plist", self.name));
+        Ok(if path.is_file() { Some(path) } else { None })
+    }
+
+    /// The systemd unit brew generated for the service, on Linux.
+    ///
+    /// This is the loaded unit if the service is running, and otherwise the
+    /// one installed with the formula, if any.
+    pub fn unit_path(&self) -> Result<Option<PathBuf>> {
+        if let Some(file) = &self.file {
+            let file = expand_home(file);
+            if file.extension().is_some_and(|e| e == "service") && file.is_file() {
+                return Ok(Some(file));
+            }
+        }
+        let path = prefix()?
+            .join("opt")
+            .join(&self.name)
+            .join(format!("homebrew.{}.service", self.name));
+        Ok(if path.is_file() { Some(path) } else { None })
+    }
+
+    /// Reads the service's launchd plist, if it has one.
+    pub fn plist(&self) -> Result<Option<LaunchdPlist>> {
+        match self.plist_path()? {
+            Some(path) => LaunchdPlist::read(path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The log files the service writes to that exist.
+    ///
+    /// These are the stdout and stderr paths from the plist, along with any
+    /// `<name>.log`, `<name>.error.log` or `<name>/*.log` files in
+    /// `var/log` under the prefix.
+    pub fn log_paths(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        if let Some(plist) = self.plist()? {
+            paths.extend(plist.standard_out_path);
+            paths.extend(plist.standard_error_path);
+        }
+        let log = prefix()?.join("var/log");
+        paths.push(log.join(format!("{}.log", self.name)));
+        paths.push(log.join(format!("{}.error.log", self.name)));
+        if let Ok(entries) = std::fs::read_dir(log.join(&self.name)) {
+            let mut logs: Vec<PathBuf> = entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|e| e == "log"))
+                .collect();
+            logs.sort();
+            paths.extend(logs);
+        }
+        let mut seen = std::collections::HashSet::new();
+        paths.retain(|p| p.is_file() && seen.insert(p.clone()));
+        Ok(paths)
+    }
+
+    /// The last `lines` lines of each of the service's log files.
+    pub fn tail_log(&self, lines: usize) -> Result<Vec<(PathBuf, Vec<String>)>> {
+        self.log_paths()?
+            .into_iter()
+            .map(|path| {
+                let tail = crate::tail_lines(&path, lines)?;
+                Ok((path, tail))
+            })
+            .collect()
+    }
+}
+
+/// Replaces a leading `~` with the home directory, as brew abbreviates paths.
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => path.to_owned(),
+    }
+}
+
+/// The fields of a launchd plist that matter for a Homebrew service.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LaunchdPlist {
+    pub path: PathBuf,
+    pub label: Option<String>,
+    pub program_arguments: Vec<String>,
+    pub run_at_load: bool,
+    /// Either a bool, or a dict of conditions under which to restart.
+    pub keep_alive: Option<PlistValue>,
+    pub working_directory: Option<PathBuf>,
+    pub standard_out_path: Option<PathBuf>,
+    pub standard_error_path: Option<PathBuf>,
+    /// Every key in the plist.
+    pub raw: BTreeMap<String, PlistValue>,
+}
+
+impl LaunchdPlist {
+    /// Reads the XML plist at `path`.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<LaunchdPlist> {
+        let path = path.as_ref();
+        let raw = match crate::plist::parse(&std::fs::read_to_string(path)?) {
+            Some(PlistValue::Dict(raw)) => raw,
+            _ => return Err(Error::InvalidPlist(path.to_owned())),
+        };
+        let string = |key: &str| raw.get(key).and_then(PlistValue::as_str).map(str::to_owned);
+        Ok(LaunchdPlist {
+            path: path.to_owned(),
+            label: string("Label"),
+            program_arguments: match raw.get("ProgramArguments") {
+                Some(PlistValue::Array(args)) => args
+                    .iter()
+                    .filter_map(PlistValue::as_str)
+                    .map(str::to_owned)
+                    .collect(),
+                _ => string("Program").into_iter().collect(),
+            },
+            run_at_load: raw
+                .get("RunAtLoad")
+                .and_then(PlistValue::as_bool)
+                .unwrap_or(false),
+            keep_alive: raw.get("KeepAlive").cloned(),
+            working_directory: string("WorkingDirectory").map(PathBuf::from),
+            standard_out_path: string("StandardOutPath").map(PathBuf::from),
+            standard_error_path: string("StandardErrorPath").map(PathBuf::from),
+            raw,
+        })
+    }
+
+    /// Checks if launchd restarts the service in at least some situations.
+    pub fn keeps_alive(&self) -> bool {
+        match &self.keep_alive {
+            Some(PlistValue::Bool(b)) => *b,
+            Some(PlistValue::Dict(conditions)) => !conditions.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+/// Lists every service provided by an installed formula.
+///
+/// Uses `brew services list --json`, falling back to reading the table
+/// printed by versions of brew without `--json`.
+pub fn list() -> Result<Vec<Service>> {
+    let output = brew(&["services", "list", "--json"])?;
+    if output.success() {
+        if output.stdout().trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        return Ok(serde_json::from_str(output.stdout())?);
+    }
+    let output = brew(&["services", "list"])?;
+    if output.success() {
+        Ok(parse_table(output.stdout()))
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+/// Starts the service of the formula `name`, with `brew services start`, so
+/// it also starts at login.
+pub fn start(name: &str) -> Result<()> {
+    let output = brew(&["services", "start", name])?;
+    if output.success() {
+        Ok(())
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+/// The services installing `package` suggests starting: those named by
+/// `brew services start` in its caveats, and its own if it has a `service`
+/// block.
+///
+/// Services that need to run as root are left out: those the caveats say to
+/// start with `sudo`, and the package's own if its `service` block sets
+/// `require_root`.
+pub fn suggested_services(package: &Package) -> Vec<String> {
+    let (mut names, as_root) = package
+        .caveats
+        .as_deref()
+        .map(services_in_caveats)
+        .unwrap_or_default();
+    let require_root = package
+        .service
+        .as_ref()
+        .and_then(|service| service.get("require_root"))
+        .and_then(serde_json::Value::as_bool)
+        .unwrap_or(false);
+    if package.service.is_some()
+        && !require_root
+        && !as_root.contains(&package.name)
+        && !names.contains(&package.name)
+    {
+        names.push(package.name.clone());
+    }
+    names
+}
+
+/// The services named by `brew services start` in `caveats`, split into
+/// those started as the user and those started with `sudo`.
+fn services_in_caveats(caveats: &str) -> (Vec<String>, Vec<String>) {
+    let (mut names, mut as_root): (Vec<String>, Vec<String>) = (Vec::new(), Vec::new());
+    for line in caveats.lines().map(str::trim) {
+        let name = line
+            .split("brew services start ")
+            .nth(1)
+            .and_then(|rest| rest.split_whitespace().next())
+            .map(|name| name.trim_matches(|c: char| c == '`' || c == '.' || c == ','));
+        if let Some(name) = name.filter(|n| !n.is_empty()) {
+            let list = if line.starts_with("sudo ") {
+                &mut as_root
+            } else {
+                &mut names
+            };
+            if !list.iter().any(|n| n == name) {
+                list.push(name.to_owned());
+            }
+        }
+    }
+    names.retain(|name| !as_root.contains(name));
+    (names, as_root)
+}
+
+/// The services `install_starting_services` tried to start, each with the
+/// result of starting it.
+pub type ServiceStarts = Vec<(String, Result<()>)>;
+
+impl Package {
+    /// Installs the package as `install` does, then starts the services it
+    /// suggests, as found by `suggested_services`.
+    ///
+    /// Only failing to install is an error. Each service it tried to start is
+    /// returned with the result of starting it, so one failing does not keep
+    /// the others from starting. Services that are already started are left
+    /// alone and not returned. If `brew services` cannot list them, such as
+    /// on Linux without systemd, none are taken to be started, and starting
+    /// each reports why.
+    pub fn install_starting_services(&self, options: &Options) -> Result<(Package, ServiceStarts)> {
+        let package = self.install(options)?;
+        let running: Vec<String> = list()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|s| s.status == ServiceStatus::Started)
+            .map(|s| s.name)
+            .collect();
+        let started = suggested_services(&package)
+            .into_iter()
+            .filter(|name| !running.contains(name))
+            .map(|name| {
+                let result = start(&name);
+                (name, result)
+            })
+            .collect();
+        Ok((package, started))
+    }
+}
+
+/// Parses the table printed by `brew services list`.
+fn parse_table(stdout: &str) -> Vec<Service> {
+    stdout
+        .lines()
+        .skip_while(|l| !l.starts_with("Name"))
+        .skip(1)
+        .filter_map(|line| {
+            let mut words = line.split_whitespace().peekable();
+            let name = words.next()?.to_owned();
+            let status = ServiceStatus::from(words.next().unwrap_or("none"));
+            let exit_code = if status == ServiceStatus::Error {
+                words.peek().and_then(|w| w.parse().ok())
+            } else {
+                None
+            };
+            if exit_code.is_some() {
+                words.next();
+            }
+            let user = words.next().map(str::to_owned);
+            let file = words.collect::<Vec<_>>().join(" ");
+            Some(Service {
+                name,
+                status,
+                user,
+                file: if file.is_empty() {
+                    None
+                } else {
+                    Some(file.into())
+                },
+                exit_code,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_json() {
+        let services: Vec<Service> = serde_json::from_str(
+            r#"[{"name":"postgresql@14","status":"started","user":"me","file":"/Users/me/Library/LaunchAgents/homebrew.mxcl.postgresql@14.plist","exit_code":0},
+                {"name":"redis","status":"none","user":null,"file":null,"exit_code":null},
+                {"name":"unbound","status":"scheduled","user":"root","file":null,"exit_code":null}]"#,
+        )
+        .unwrap();
+        assert_eq!(services[0].status, ServiceStatus::Started);
+        assert_eq!(services[1].status, ServiceStatus::None);
+        assert_eq!(
+            services[2].status,
+            ServiceStatus::Other("scheduled".to_owned())
+        );
+    }
+
+    #[test]
+    fn finds_services_in_caveats() {
+        let caveats = "To start postgresql@14 now and restart at login:\n  \
+                       brew services start postgresql@14\n\
+                       Or, if you don't want/need a background service you can just run:\n  \
+                       /opt/homebrew/opt/postgresql@14/bin/postgres -D /opt/homebrew/var/postgresql@14\n\
+                       To start unbound now and restart at startup:\n  \
+                       sudo brew services start unbound\n";
+        let (names, as_root) = services_in_caveats(caveats);
+        assert_eq!(names, ["postgresql@14"]);
+        assert_eq!(as_root, ["unbound"]);
+    }
+
+    #[cfg(feature = "fixtures")]
+    #[test]
+    fn starts_services_without_a_listing() {
+        use crate::tests::{formula_json, installed, recording};
+        use serde_json::json;
+
+        let failed = |args: &[&str], stderr: &str| crate::fixtures::Recording {
+            stderr: stderr.to_owned(),
+            status: crate::ExitStatus {
+                code: Some(1),
+                signal: None,
+            },
+            ..recording(args, "")
+        };
+        let redis = |installed| {
+            let mut redis = formula_json("redis", json!({ "installed": installed }));
+            redis["service"] = json!({ "run": ["redis-server"] });
+            redis
+        };
+        let no_systemd = "Error: Formula `redis` is not installed or systemctl is unavailable.";
+        let _guard = crate::fixtures::replay_recordings(vec![
+            recording(&["--version"], "Homebrew 4.2.0"),
+            recording(&["install", "redis"], ""),
+            recording(
+                &["info", "redis", "--json=v2"],
+                &json!({ "formulae": [redis(json!([installed("7.2.4", true)]))] }).to_string(),
+            ),
+            failed(&["services", "list", "--json"], no_systemd),
+            failed(&["services", "list"], no_systemd),
+            failed(&["services", "start", "redis"], no_systemd),
+        ]);
+        let package: Package = serde_json::from_value(redis(json!([]))).unwrap();
+        // A new client, so the version is not cached from other tests.
+        let (installed, started) = crate::Brew::new()
+            .enter(|| package.install_starting_services(&Options::new()))
+            .unwrap();
+        assert!(installed.is_installed());
+        assert_eq!(started.len(), 1);
+        assert_eq!(started[0].0, "redis");
+        assert!(started[0].1.is_err());
+    }
+
+    #[test]
+    fn skips_root_services() {
+        let mut package = crate::tests::formula("unbound", serde_json::json!({}));
+        package.service = Some(serde_json::json!({ "run": ["unbound"] }));
+        assert_eq!(suggested_services(&package), ["unbound"]);
+
+        package.caveats = Some("sudo brew services start unbound\n".to_owned());
+        assert!(suggested_services(&package).is_empty());
+
+        package.caveats = None;
+        package.service = Some(serde_json::json!({ "run": ["unbound"], "require_root": true }));
+        assert!(suggested_services(&package).is_empty());
+    }
+
+    #[test]
+    fn parses_table() {
+        let services = parse_table(
+            "Name          Status  User File\n\
+             postgresql@14 started me   ~/Library/LaunchAgents/homebrew.mxcl.postgresql@14.plist\n\
+             redis         none\n\
+             unbound       error  256 root /Library/LaunchDaemons/homebrew.mxcl.unbound.plist\n",
+        );
+        assert_eq!(services.len(), 3);
+        assert_eq!(services[0].user.as_deref(), Some("me"));
+        assert_eq!(services[1].file, None);
+        assert_eq!(services[2].status, ServiceStatus::Error);
+        assert_eq!(services[2].exit_code, Some(256));
+        assert_eq!(services[2].user.as_deref(), Some("root"));
+    }
+}
@@ -0,0 +1,328 @@
+//! Recording what is installed, and bringing a machine back to it.
+//!
+//! ``` no_run
+//! # fn main() -> brew::Result<()> {
+//! use brew::snapshot::{restore, snapshot, RestorePolicy, SystemSnapshot};
+//!
+//! // On the old machine:
+//! snapshot()?.write("brew-snapshot.json")?;
+//! // On the new one:
+//! let report = restore(&SystemSnapshot::read("brew-snapshot.json")?, &RestorePolicy::new())?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::command::{brew, timed};
+use crate::services::{self, ServiceStatus};
+use crate::tap::taps;
+use crate::{all_installed, brew_error, Error, Result, Timing};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// An installed formula, as recorded in a snapshot.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct FormulaState {
+    /// The full name, which includes the tap for formulae outside
+    /// homebrew/core.
+    pub name: String,
+    pub version: String,
+    /// The options the formula was installed with, such as `--with-x`.
+    pub options: Vec<String>,
+    pub pinned: bool,
+    pub installed_on_request: bool,
+}
+
+/// A tap, as recorded in a snapshot.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct TapState {
+    pub name: String,
+    /// The remote, if it is not the default GitHub repository.
+    pub remote: Option<String>,
+}
+
+/// An installed cask, as recorded in a snapshot.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct CaskState {
+    pub name: String,
+    pub version: String,
+}
+
+/// Everything brew has installed on a machine.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SystemSnapshot {
+    pub taps: Vec<TapState>,
+    pub formulae: Vec<FormulaState>,
+    pub casks: Vec<CaskState>,
+    /// The status of each service.
+    pub services: BTreeMap<String, ServiceStatus>,
+}
+
+impl SystemSnapshot {
+    /// Reads a snapshot written by `write`.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the snapshot to `path`, as JSON.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        Ok(std::fs::write(path, serde_json::to_string_pretty(self)?)?)
+    }
+}
+
+/// Records the installed taps, formulae, casks and services.
+///
+/// Where `brew services` cannot list services, such as on Linux without
+/// systemd, none are recorded.
+pub fn snapshot() -> Result<SystemSnapshot> {
+    let taps = taps()?
+        .into_iter()
+        .map(|tap| TapState {
+            remote: if tap.custom_remote { tap.remote } else { None },
+            name: tap.name,
+        })
+        .collect();
+    let mut formulae: Vec<FormulaState> = all_installed()?
+        .into_values()
+        .filter_map(|package| {
+            let installed = package.installed.last()?;
+            Some(FormulaState {
+                name: package.full_name.clone(),
+                version: installed.version.original().to_owned(),
+                options: installed.used_options.clone(),
+                pinned: package.pinned,
+                installed_on_request: installed.installed_on_request,
+            })
+        })
+        .collect();
+    formulae.sort_by(|a, b| a.name.cmp(&b.name));
+    let services = services::list()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|service| (service.name, service.status))
+        .collect();
+    Ok(SystemSnapshot {
+        taps,
+        formulae,
+        casks: installed_casks()?,
+        services,
+    })
+}
+
+/// Represents how to restore a snapshot.
+#[derive(Clone, Debug, Default)]
+pub struct RestorePolicy {
+    remove_extra: bool,
+    skip_services: bool,
+}
+
+impl RestorePolicy {
+    /// Represents no options added: everything missing is installed, and
+    /// nothing is removed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uninstalls formulae installed on request and casks that are not in
+    /// the snapshot.
+    pub fn remove_extra(mut self) -> Self {
+        self.remove_extra = true;
+        self
+    }
+
+    /// Leaves services as they are, instead of starting and stopping them to
+    /// match the snapshot.
+    pub fn skip_services(mut self) -> Self {
+        self.skip_services = true;
+        self
+    }
+}
+
+/// What `restore` changed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RestoreReport {
+    pub tapped: Vec<String>,
+    pub installed: Vec<String>,
+    pub uninstalled: Vec<String>,
+    pub pinned: Vec<String>,
+    pub started: Vec<String>,
+    pub stopped: Vec<String>,
+    /// Formulae and casks installed at a different version than in the
+    /// snapshot, with the snapshot's version and the installed one. brew
+    /// only installs the current version, so these can't be restored
+    /// exactly.
+    pub version_mismatches: Vec<(String, String, String)>,
+    /// How long restoring took, with the time spent starting brew for each
+    /// command added up.
+    pub timing: Timing,
+}
+
+/// Brings the machine back to `snapshot`, as far as `policy` allows.
+///
+/// Formulae installed as dependencies are not installed directly, but come
+/// back with the formulae that need them.
+pub fn restore(snapshot: &SystemSnapshot, policy: &RestorePolicy) -> Result<RestoreReport> {
+    let (report, timing) = timed(|| restore_untimed(snapshot, policy));
+    Ok(RestoreReport { timing, ..report? })
+}
+
+fn restore_untimed(snapshot: &SystemSnapshot, policy: &RestorePolicy) -> Result<RestoreReport> {
+    let mut report = RestoreReport::default();
+    let current = self::snapshot()?;
+
+    let taps: BTreeSet<&str> = current.taps.iter().map(|t| t.name.as_str()).collect();
+    for tap in snapshot
+        .taps
+        .iter()
+        .filter(|t| !taps.contains(t.name.as_str()))
+    {
+        let mut args = vec!["tap", &tap.name];
+        args.extend(tap.remote.as_deref());
+        run(&args, Error::UnknownError)?;
+        report.tapped.push(tap.name.clone());
+    }
+
+    let formulae: BTreeSet<&str> = current.formulae.iter().map(|f| f.name.as_str()).collect();
+    for formula in &snapshot.formulae {
+        if formula.installed_on_request && !formulae.contains(formula.name.as_str()) {
+            let mut args = vec!["install", "--formula", &formula.name];
+            args.extend(formula.options.iter().map(String::as_str));
+            run(&args, Error::InstallFailed)?;
+            report.installed.push(formula.name.clone());
+        }
+    }
+    let casks: BTreeSet<&str> = current.casks.iter().map(|c| c.name.as_str()).collect();
+    for cask in snapshot
+        .casks
+        .iter()
+        .filter(|c| !casks.contains(c.name.as_str()))
+    {
+        run(&["install", "--cask", &cask.name], Error::InstallFailed)?;
+        report.installed.push(cask.name.clone());
+    }
+
+    if policy.remove_extra {
+        let wanted: BTreeSet<&str> = snapshot.formulae.iter().map(|f| f.name.as_str()).collect();
+        for formula in &current.formulae {
+            if formula.installed_on_request && !wanted.contains(formula.name.as_str()) {
+                run(
+                    &["uninstall", "--formula", &formula.name],
+                    Error::UnknownError,
+                )?;
+                report.uninstalled.push(formula.name.clone());
+            }
+        }
+        let wanted: BTreeSet<&str> = snapshot.casks.iter().map(|c| c.name.as_str()).collect();
+        for cask in current
+            .casks
+            .iter()
+            .filter(|c| !wanted.contains(c.name.as_str()))
+        {
+            run(&["uninstall", "--cask", &cask.name], Error::UnknownError)?;
+            report.uninstalled.push(cask.name.clone());
+        }
+    }
+
+    let restored = self::snapshot()?;
+    for formula in snapshot.formulae.iter().filter(|f| f.pinned) {
+        if restored
+            .formulae
+            .iter()
+            .any(|f| f.name == formula.name && !f.pinned)
+        {
+            run(&["pin", &formula.name], Error::UnknownError)?;
+            report.pinned.push(formula.name.clone());
+        }
+    }
+    let versions = restored
+        .formulae
+        .iter()
+        .map(|f| (&f.name, &f.version))
+        .chain(restored.casks.iter().map(|c| (&c.name, &c.version)));
+    let wanted: BTreeMap<&String, &String> = snapshot
+        .formulae
+        .iter()
+        .map(|f| (&f.name, &f.version))
+        .chain(snapshot.casks.iter().map(|c| (&c.name, &c.version)))
+        .collect();
+    for (name, version) in versions {
+        match wanted.get(name) {
+            Some(&wanted) if wanted != version => {
+                report
+                    .version_mismatches
+                    .push((name.clone(), wanted.clone(), version.clone()))
+            }
+            _ => {}
+        }
+    }
+
+    if !policy.skip_services {
+        for (name, status) in &snapshot.services {
+            let now = restored.services.get(name);
+            if *status == ServiceStatus::Started && now != Some(&ServiceStatus::Started) {
+                run(&["services", "start", name], Error::UnknownError)?;
+                report.started.push(name.clone());
+            } else if *status != ServiceStatus::Started && now == Some(&ServiceStatus::Started) {
+                run(&["services", "stop", name], Error::UnknownError)?;
+                report.stopped.push(name.clone());
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Runs brew with `args`, failing with `error` if it does not succeed.
+fn run(args: &[&str], error: fn(crate::Failure) -> Error) -> Result<()> {
+    let output = brew(args)?;
+    if output.success() {
+        Ok(())
+    } else {
+        Err(brew_error(&output, error))
+    }
+}
+
+/// The installed casks, from `brew list --cask --versions`.
+fn installed_casks() -> Result<Vec<CaskState>> {
+    let output = brew(&["list", "--cask", "--versions"])?;
+    if output.success() {
+        Ok(parse_cask_versions(output.stdout()))
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+/// Parses lines like `firefox 121.0`. Casks with several versions installed
+/// list each, and the last is kept.
+fn parse_cask_versions(stdout: &str) -> Vec<CaskState> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            Some(CaskState {
+                name: words.next()?.to_owned(),
+                version: words.last().unwrap_or_default().to_owned(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_snapshots() {
+        let snapshot = SystemSnapshot {
+            casks: parse_cask_versions("firefox 121.0\nvisual-studio-code 1.85.1 1.85.2\n"),
+            services: vec![("redis".to_owned(), ServiceStatus::Started)]
+                .into_iter()
+                .collect(),
+            ..SystemSnapshot::default()
+        };
+        assert_eq!(snapshot.casks[1].version, "1.85.2");
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert_eq!(
+            serde_json::from_str::<SystemSnapshot>(&json).unwrap(),
+            snapshot
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! What brew knows about the system it runs on, via `brew config`.
+use crate::command::brew;
+use crate::platform::{current_platform, Os, Platform};
+use crate::{brew_error, Error, Result};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// The system brew runs on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SystemInfo {
+    pub platform: Platform,
+    pub homebrew_version: Option<String>,
+    /// The full macOS version, such as `14.2.1`.
+    pub macos_version: Option<String>,
+    /// Whether brew is running under Rosetta 2 on Apple silicon.
+    pub rosetta: bool,
+    /// The Xcode version, if installed.
+    pub xcode: Option<String>,
+    /// The Command Line Tools version, if installed.
+    pub clt: Option<String>,
+    /// The Linux distribution, such as `Ubuntu 22.04.3 LTS (jammy)`.
+    pub linux_distro: Option<String>,
+    /// The glibc version on Linux.
+    pub glibc: Option<String>,
+    /// The system GCC version on Linux.
+    pub gcc: Option<String>,
+    /// Every `key: value` line of `brew config`.
+    pub config: BTreeMap<String, String>,
+}
+
+impl SystemInfo {
+    /// Checks if formulae can be built from source: Xcode or the Command
+    /// Line Tools on macOS, or GCC on Linux.
+    pub fn can_build_from_source(&self) -> bool {
+        match self.platform.os {
+            Os::MacOs => self.xcode.is_some() || self.clt.is_some(),
+            Os::Linux => self.gcc.is_some(),
+        }
+    }
+}
+
+/// Describes the system, from `brew config` along with direct probes for
+/// what it does not report.
+pub fn system_info() -> Result<SystemInfo> {
+    let output = brew(&["config"])?;
+    if !output.success() {
+        return Err(brew_error(&output, Error::UnknownError));
+    }
+    let mut info = parse_config(output.stdout(), current_platform());
+    if info.platform.os == Os::MacOs && !info.config.contains_key("Rosetta 2") {
+        info.rosetta = probe("sysctl", &["-n", "sysctl.proc_translated"]).as_deref() == Some("1");
+    }
+    if info.platform.os == Os::Linux && info.glibc.is_none() {
+        // The first line is like `ldd (Ubuntu GLIBC 2.35-0ubuntu3.6) 2.35`.
+        info.glibc = probe("ldd", &["--version"]).and_then(|v| {
+            v.lines()
+                .next()?
+                .split_whitespace()
+                .last()
+                .map(str::to_owned)
+        });
+    }
+    Ok(info)
+}
+
+/// What brew itself is running as.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BrewRuntimeInfo {
+    /// The version, such as `4.2.0-45-g8f3e6c1`.
+    pub version: Option<String>,
+    /// The commit brew's repository is at.
+    pub git_revision: Option<String>,
+    /// When that commit was made, as brew says it, such as `3 days ago`.
+    pub last_commit: Option<String>,
+    /// The version of the Ruby brew runs with, such as `3.1.4`.
+    pub ruby_version: Option<String>,
+    pub ruby_path: Option<PathBuf>,
+    /// Whether that Ruby is the portable Ruby brew vendors, rather than the
+    /// system's.
+    pub portable_ruby: bool,
+}
+
+/// Describes brew's own version and the Ruby it runs with, from
+/// `brew config`.
+///
+/// Problems with an outdated brew or Ruby look like problems with formulae,
+/// so this is worth including when reporting them.
+pub fn brew_runtime_info() -> Result<BrewRuntimeInfo> {
+    let output = brew(&["config"])?;
+    if output.success() {
+        Ok(parse_runtime_info(output.stdout()))
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+fn parse_runtime_info(stdout: &str) -> BrewRuntimeInfo {
+    let config = parse_config(stdout, current_platform()).config;
+    let get = |key: &str| {
+        config
+            .get(key)
+            .filter(|v| !v.is_empty() && v.as_str() != "N/A" && v.as_str() != "(none)")
+            .cloned()
+    };
+    // Like `3.1.4 => /opt/homebrew/Library/Homebrew/vendor/portable-ruby/3.1.4/bin/ruby`.
+    let ruby = get("Homebrew Ruby");
+    let (ruby_version, ruby_path) = match ruby.as_deref().map(|r| r.split_once(" => ")) {
+        Some(Some((version, path))) => (Some(version.to_owned()), Some(PathBuf::from(path))),
+        Some(None) => (ruby.clone(), None),
+        None => (None, None),
+    };
+    BrewRuntimeInfo {
+        version: get("HOMEBREW_VERSION"),
+        git_revision: get("HEAD"),
+        last_commit: get("Last commit"),
+        portable_ruby: ruby_path
+            .as_ref()
+            .is_some_and(|p| p.to_string_lossy().contains("portable-ruby")),
+        ruby_version,
+        ruby_path,
+    }
+}
+
+/// Runs `program`, returning its trimmed stdout if it succeeds.
+fn probe(program: &str, args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    } else {
+        None
+    }
+}
+
+fn parse_config(stdout: &str, platform: Platform) -> SystemInfo {
+    let config: BTreeMap<String, String> = stdout
+        .lines()
+        .filter_map(|l| l.split_once(": "))
+        .map(|(k, v)| (k.trim().to_owned(), v.trim().to_owned()))
+        .collect();
+    // brew reports missing tools as `N/A`.
+    let get = |key: &str| config.get(key).filter(|v| v.as_str() != "N/A").cloned();
+    // Versions may be followed by the path, as in `11.4.0 => /usr/bin/gcc`.
+    let version = |v: String| v.split(" => ").next().unwrap_or(&v).to_owned();
+    SystemInfo {
+        platform,
+        homebrew_version: get("HOMEBREW_VERSION"),
+        macos_version: get("macOS").map(|v| v.split('-').next().unwrap_or(&v).to_owned()),
+        rosetta: get("Rosetta 2").as_deref() == Some("true"),
+        xcode: get("Xcode").map(version),
+        clt: get("CLT").map(version),
+        linux_distro: get("OS"),
+        glibc: get("Host glibc").map(version),
+        gcc: config
+            .iter()
+            .find(|(k, v)| k.ends_with("gcc") && v.as_str() != "N/A")
+            .map(|(_, v)| version(v.clone())),
+        config,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Arch;
+
+    #[test]
+    fn parses_macos_config() {
+        let info = parse_config(
+            "HOMEBREW_VERSION: 4.2.0\nCPU: octa-core 64-bit arm_firestorm_icestorm\n\
+             macOS: 14.2.1-arm64\nCLT: 15.1.0.0.1.1700200546\nXcode: N/A\nRosetta 2: false\n",
+            Platform::from_bottle_tag("arm64_sonoma").unwrap(),
+        );
+        assert_eq!(info.homebrew_version.as_deref(), Some("4.2.0"));
+        assert_eq!(info.macos_version.as_deref(), Some("14.2.1"));
+        assert_eq!(info.xcode, None);
+        assert!(info.can_build_from_source());
+        assert!(!info.rosetta);
+    }
+
+    #[test]
+    fn parses_linux_config() {
+        let info = parse_config(
+            "HOMEBREW_VERSION: 4.2.0\nOS: Ubuntu 22.04.3 LTS (jammy)\nHost glibc: 2.35\n\
+             /usr/bin/gcc: 11.4.0\n",
+            Platform {
+                os: Os::Linux,
+                arch: Arch::X86_64,
+                macos: None,
+            },
+        );
+        assert_eq!(
+            info.linux_distro.as_deref(),
+            Some("Ubuntu 22.04.3 LTS (jammy)")
+        );
+        assert_eq!(info.glibc.as_deref(), Some("2.35"));
+        assert_eq!(info.gcc.as_deref(), Some("11.4.0"));
+        assert!(info.can_build_from_source());
+    }
+
+    #[test]
+    fn parses_runtime_info() {
+        let info = parse_runtime_info(
+            "HOMEBREW_VERSION: 4.2.0-45-g8f3e6c1\nORIGIN: https://github.com/Homebrew/brew\n\
+             HEAD: 8f3e6c1d2a7b4e5f60718293a4b5c6d7e8f90a1b\nLast commit: 3 days ago\n\
+             Homebrew Ruby: 3.1.4 => \
+             /opt/homebrew/Library/Homebrew/vendor/portable-ruby/3.1.4/bin/ruby\n",
+        );
+        assert_eq!(info.version.as_deref(), Some("4.2.0-45-g8f3e6c1"));
+        assert_eq!(info.last_commit.as_deref(), Some("3 days ago"));
+        assert_eq!(info.ruby_version.as_deref(), Some("3.1.4"));
+        assert!(info.portable_ruby);
+
+        let info = parse_runtime_info(
+            "Homebrew Ruby: 2.6.10 => \
+             /System/Library/Frameworks/Ruby.framework/Versions/2.6/usr/bin/ruby\n",
+        );
+        assert_eq!(info.ruby_version.as_deref(), Some("2.6.10"));
+        assert!(!info.portable_ruby);
+    }
+}
@@ -0,0 +1,434 @@
+//! Taps, the git repositories formulae and casks come from.
+use crate::command::{brew, Output};
+use crate::{brew_error, brew_version, Error, Failure, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A tap, as reported by `brew tap-info --json`.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Tap {
+    /// The name, such as `homebrew/core`.
+    pub name: String,
+    pub user: String,
+    pub repo: String,
+    /// The tap's git checkout.
+    pub path: PathBuf,
+    pub installed: bool,
+    pub official: bool,
+    pub remote: Option<String>,
+    #[serde(default)]
+    pub custom_remote: bool,
+    #[serde(default)]
+    pub private: bool,
+    #[serde(default)]
+    pub formula_names: Vec<String>,
+    #[serde(default)]
+    pub cask_tokens: Vec<String>,
+}
+
+/// The state of a tap's git checkout.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TapGitInfo {
+    /// The commit checked out.
+    pub head: String,
+    /// The checked out branch, or `None` if HEAD is detached.
+    pub branch: Option<String>,
+    /// The URL of the `origin` remote.
+    pub remote: Option<String>,
+    /// Whether there are uncommitted changes.
+    pub dirty: bool,
+    /// Whether the checkout is a shallow clone.
+    pub shallow: bool,
+}
+
+impl Tap {
+    /// Looks up the tap `name`, such as `homebrew/cask-fonts`.
+    pub fn new(name: &str) -> Result<Tap> {
+        tap_info(&[name])?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::PackageNotFound(Failure::default()))
+    }
+
+    /// Reads the state of the tap's git checkout.
+    ///
+    /// Fails with `Error::TapCorrupted` if git cannot read it.
+    pub fn git_info(&self) -> Result<TapGitInfo> {
+        let head = self.head()?;
+        let branch = git(&self.path, &["symbolic-ref", "--short", "-q", "HEAD"]).ok();
+        let remote = git(&self.path, &["remote", "get-url", "origin"]).ok();
+        let status = git(&self.path, &["status", "--porcelain"])?;
+        let shallow = git(&self.path, &["rev-parse", "--is-shallow-repository"])?;
+        Ok(TapGitInfo {
+            head,
+            branch: branch.filter(|b| !b.is_empty()),
+            remote: remote.filter(|r| !r.is_empty()),
+            dirty: !status.is_empty(),
+            shallow: shallow == "true",
+        })
+    }
+
+    /// The commit the tap's git checkout has checked out.
+    pub fn head(&self) -> Result<String> {
+        git(&self.path, &["rev-parse", "HEAD"])
+    }
+
+    /// Checks that the tap's git checkout is usable, failing with
+    /// `Error::TapCorrupted` if it is missing or git cannot read it.
+    ///
+    /// Shallow clones are usable, though brew refuses to update them. Use
+    /// `git_info` to find those.
+    pub fn verify(&self) -> Result<()> {
+        if !self.path.join(".git").exists() {
+            return Err(Error::TapCorrupted(self.path.clone(), Failure::default()));
+        }
+        git(
+            &self.path,
+            &["rev-parse", "--verify", "--quiet", "HEAD^{tree}"],
+        )
+        .and_then(|_| git(&self.path, &["status", "--porcelain"]))
+        .map(|_| ())
+        .map_err(|e| match e {
+            Error::UnknownError(failure) => Error::TapCorrupted(self.path.clone(), failure),
+            e => e,
+        })
+    }
+
+    /// Fetches the tap's full history if it is a shallow clone, which
+    /// `brew update` requires.
+    pub fn unshallow(&self) -> Result<()> {
+        if self.git_info()?.shallow {
+            git(&self.path, &["fetch", "--unshallow", "--quiet"])?;
+        }
+        Ok(())
+    }
+
+    /// Replaces a broken tap with a fresh clone from the same remote, with
+    /// `brew untap --force` and `brew tap`.
+    ///
+    /// Local changes to the tap are lost, and formulae from the tap stay
+    /// installed.
+    pub fn reclone(&self) -> Result<Tap> {
+        let output = brew(&["untap", "--force", &self.name])?;
+        if !output.success() {
+            return Err(brew_error(&output, Error::UnknownError));
+        }
+        let mut args = vec!["tap", self.name.as_str()];
+        if let (true, Some(remote)) = (self.custom_remote, &self.remote) {
+            args.push(remote);
+        }
+        let output = brew(&args)?;
+        if output.success() {
+            Tap::new(&self.name)
+        } else {
+            Err(brew_error(&output, Error::UnknownError))
+        }
+    }
+
+    /// Fast-forwards the tap to its remote, like `brew update` does for
+    /// every tap.
+    pub fn pull(&self) -> Result<()> {
+        git(&self.path, &["pull", "--ff-only", "--quiet"]).map(|_| ())
+    }
+
+    /// Repairs the tap's links and remote, with `brew tap --repair`.
+    ///
+    /// brew repairs every tap at once, so this affects other taps too.
+    pub fn repair(&self) -> Result<()> {
+        let output = brew(&["tap", "--repair"])?;
+        if output.success() {
+            Ok(())
+        } else {
+            Err(brew_error(&output, Error::UnknownError))
+        }
+    }
+}
+
+/// The first brew version that deprecated `brew tap --shallow`, always
+/// making full clones.
+const FULL_CLONES_ONLY: (u32, u32, u32) = (2, 7, 0);
+
+/// Represents how to tap a tap.
+#[derive(Clone, Debug, Default)]
+pub struct TapOptions {
+    remote: Option<String>,
+    branch: Option<String>,
+    custom_remote: bool,
+    force_auto_update: bool,
+    shallow: bool,
+}
+
+impl TapOptions {
+    /// Represents no options added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Clones the tap from `url` instead of GitHub, such as a GitLab
+    /// repository for an internal tap.
+    pub fn remote(mut self, url: &str) -> Self {
+        self.remote = Some(url.to_owned());
+        self
+    }
+
+    /// Checks out `branch` after tapping, instead of the remote's default
+    /// branch. `brew update` resets taps to the remote's `HEAD`, so that is
+    /// pointed at `branch` too, and the tap stays on it.
+    pub fn branch(mut self, branch: &str) -> Self {
+        self.branch = Some(branch.to_owned());
+        self
+    }
+
+    /// Adds the `--custom-remote` flag, changing the remote of a tap that is
+    /// already tapped to the one given with `remote`.
+    pub fn custom_remote(mut self) -> Self {
+        self.custom_remote = true;
+        self
+    }
+
+    /// Adds the `--force-auto-update` flag, so `brew update` updates the tap
+    /// even if it is official.
+    pub fn force_auto_update(mut self) -> Self {
+        self.force_auto_update = true;
+        self
+    }
+
+    /// Adds the `--shallow` flag, making a shallow clone. Only brew before
+    /// 2.7.0 accepts it, so it is left out for newer ones, which always make
+    /// full clones.
+    pub fn shallow(mut self) -> Self {
+        self.shallow = true;
+        self
+    }
+
+    /// The flags to pass to brew `version`.
+    fn brew_options(&self, version: (u32, u32, u32)) -> Vec<&str> {
+        let mut out = Vec::new();
+        if self.custom_remote {
+            out.push("--custom-remote");
+        }
+        if self.force_auto_update {
+            out.push("--force-auto-update");
+        }
+        if self.shallow && version < FULL_CLONES_ONLY {
+            out.push("--shallow");
+        }
+        out
+    }
+}
+
+/// Taps `name`, such as `user/tap`, with `brew tap`, returning the tap.
+///
+/// Tapping a tap that is already tapped does nothing, unless `options`
+/// change its remote with `TapOptions::custom_remote`. A branch given with
+/// `TapOptions::branch` is checked out either way.
+pub fn tap(name: &str, options: &TapOptions) -> Result<Tap> {
+    let mut args = vec!["tap"];
+    let version = if options.shallow {
+        brew_version()?
+    } else {
+        FULL_CLONES_ONLY
+    };
+    args.extend(options.brew_options(version));
+    args.push(name);
+    if let Some(remote) = &options.remote {
+        args.push(remote);
+    }
+    let output = brew(&args)?;
+    if !output.success() {
+        return Err(brew_error(&output, Error::UnknownError));
+    }
+    let tap = Tap::new(name)?;
+    if let Some(branch) = &options.branch {
+        check_out(&tap.path, branch)?;
+    }
+    Ok(tap)
+}
+
+/// Checks out `branch` of `origin` in `repository`, tracking it, and points
+/// `origin/HEAD`, which `brew update` resets to, at it.
+fn check_out(repository: &Path, branch: &str) -> Result<()> {
+    let remote_branch = format!("origin/{}", branch);
+    let refspec = format!("{}:refs/remotes/{}", branch, remote_branch);
+    git(repository, &["fetch", "--quiet", "origin", &refspec])?;
+    git(
+        repository,
+        &[
+            "checkout",
+            "--quiet",
+            "-B",
+            branch,
+            "--track",
+            &remote_branch,
+        ],
+    )?;
+    git(repository, &["remote", "set-head", "origin", branch])?;
+    Ok(())
+}
+
+/// Lists every installed tap.
+pub fn taps() -> Result<Vec<Tap>> {
+    tap_info(&["--installed"])
+}
+
+fn tap_info(args: &[&str]) -> Result<Vec<Tap>> {
+    let mut command = vec!["tap-info", "--json"];
+    command.extend(args);
+    let output = brew(&command)?;
+    if output.success() {
+        Ok(serde_json::from_str(output.stdout())?)
+    } else {
+        Err(brew_error(&output, Error::UnknownError))
+    }
+}
+
+/// Runs git in `repository`, returning its trimmed stdout.
+fn git(repository: &Path, args: &[&str]) -> Result<String> {
+    let mut command = std::process::Command::new("git");
+    command
+        .arg("-C")
+        .arg(repository)
+        .args(args)
+        .stdin(std::process::Stdio::null());
+    let output = command.output()?;
+    let output = Output::new(output.stdout, output.stderr, output.status.into()).ran(&command);
+    if output.success() {
+        Ok(output.stdout().trim().to_owned())
+    } else if corrupted_repository(output.stderr()) {
+        Err(Error::TapCorrupted(repository.to_owned(), output.failure()))
+    } else {
+        Err(Error::UnknownError(output.failure()))
+    }
+}
+
+/// Checks if git failed because the repository is broken.
+fn corrupted_repository(stderr: &str) -> bool {
+    let markers = [
+        "not a git repository",
+        "bad object",
+        "is corrupt",
+        "loose object",
+        "unable to read",
+    ];
+    stderr.lines().any(|line| {
+        markers.iter().any(|m| line.contains(m))
+            // Such as `error: object file .git/objects/ab/cdef is empty`.
+            || (line.contains("object file") && line.contains("is empty"))
+    })
+}
+
+/// The tap brew failed to update because it is a shallow clone, from brew's
+/// advice to run `git -C <path> fetch --unshallow`.
+pub(crate) fn corrupted_tap(stderr: &str) -> Option<PathBuf> {
+    let line = stderr.lines().find(|l| l.contains("fetch --unshallow"))?;
+    let path = line.split("git -C ").nth(1)?.split(" fetch").next()?;
+    Some(PathBuf::from(path.trim().trim_matches('"')))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tap_info() {
+        let taps: Vec<Tap> = serde_json::from_str(
+            r#"[{"name":"homebrew/cask-fonts","user":"Homebrew","repo":"cask-fonts","repository":"homebrew-cask-fonts",
+                 "path":"/opt/homebrew/Library/Taps/homebrew/homebrew-cask-fonts","installed":true,"official":true,
+                 "formula_names":[],"cask_tokens":["font-fira-code"],"remote":"https://github.com/Homebrew/homebrew-cask-fonts",
+                 "custom_remote":false,"private":false,"HEAD":"0123abc","last_commit":"2 days ago","branch":"master"}]"#,
+        )
+        .unwrap();
+        assert_eq!(taps[0].cask_tokens, vec!["font-fira-code"]);
+        assert!(taps[0].official);
+    }
+
+    #[test]
+    fn adds_tap_flags() {
+        let options = TapOptions::new()
+            .remote("https://gitlab.example.com/acme/homebrew-tools.git")
+            .custom_remote()
+            .force_auto_update();
+        assert_eq!(
+            options.brew_options((4, 2, 0)),
+            ["--custom-remote", "--force-auto-update"]
+        );
+        let shallow = TapOptions::new().shallow();
+        assert_eq!(shallow.brew_options((2, 6, 2)), ["--shallow"]);
+        assert!(shallow.brew_options((4, 2, 0)).is_empty());
+    }
+
+    #[test]
+    fn checks_out_branches() {
+        let dir = std::env::temp_dir().join(format!("brew-rs-branch-{}", std::process::id()));
+        let (origin, tap) = (dir.join("origin"), dir.join("tap"));
+        std::fs::create_dir_all(&origin).unwrap();
+        let run = |repository: &Path, args: &[&str]| git(repository, args).unwrap();
+        run(&origin, &["init", "--quiet", "--initial-branch=main"]);
+        let commit = [
+            "-c",
+            "user.name=t",
+            "-c",
+            "user.email=t@t",
+            "commit",
+            "--quiet",
+        ];
+        run(
+            &origin,
+            &[&commit[..], &["--allow-empty", "-m", "main"]].concat(),
+        );
+        run(&origin, &["checkout", "--quiet", "-b", "stable"]);
+        run(
+            &origin,
+            &[&commit[..], &["--allow-empty", "-m", "stable"]].concat(),
+        );
+        run(&origin, &["checkout", "--quiet", "main"]);
+        run(&dir, &["clone", "--quiet", "origin", "tap"]);
+
+        check_out(&tap, "stable").unwrap();
+        assert_eq!(
+            run(&tap, &["rev-parse", "--abbrev-ref", "@{upstream}"]),
+            "origin/stable"
+        );
+        assert_eq!(
+            run(&tap, &["symbolic-ref", "refs/remotes/origin/HEAD"]),
+            "refs/remotes/origin/stable"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn finds_shallow_taps() {
+        assert_eq!(
+            corrupted_tap(
+                "Error: homebrew-core is a shallow clone.\n\
+                 To `brew update`, first run:\n  \
+                 git -C /opt/homebrew/Library/Taps/homebrew/homebrew-core fetch --unshallow\n"
+            ),
+            Some(PathBuf::from(
+                "/opt/homebrew/Library/Taps/homebrew/homebrew-core"
+            ))
+        );
+        assert_eq!(corrupted_tap("Error: No available formula"), None);
+        assert!(corrupted_repository("fatal: bad object HEAD"));
+        assert!(corrupted_repository(
+            "error: object file .git/objects/5b/2c1e9 is empty"
+        ));
+        assert!(!corrupted_repository(
+            "fatal: ambiguous argument 'HEAD': the value is empty"
+        ));
+    }
+
+    #[test]
+    fn reports_broken_checkouts() {
+        let dir = std::env::temp_dir().join(format!("brew-rs-tap-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        match git(&dir, &["rev-parse", "HEAD"]) {
+            Err(Error::TapCorrupted(path, failure)) => {
+                assert_eq!(path, dir);
+                assert_eq!(failure.command.unwrap().argv[..2], ["git", "-C"]);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,244 @@
+//! Inspecting and extracting downloaded bottle tarballs.
+//!
+//! A bottle is a gzipped tarball holding a single keg, laid out as
+//! `<name>/<version>/...`. Files that refer to where Homebrew is installed
+//! contain placeholders such as `@@HOMEBREW_PREFIX@@`, which brew replaces
+//! when pouring the bottle.
+use crate::{Error, Result};
+use flate2::read::GzDecoder;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// A placeholder brew replaces when relocating a keg.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Placeholder {
+    Prefix,
+    Cellar,
+    Repository,
+    Library,
+    Perl,
+    Java,
+}
+
+impl Placeholder {
+    /// Every placeholder.
+    pub const ALL: [Placeholder; 6] = [
+        Placeholder::Prefix,
+        Placeholder::Cellar,
+        Placeholder::Repository,
+        Placeholder::Library,
+        Placeholder::Perl,
+        Placeholder::Java,
+    ];
+
+    /// The placeholder as it appears in files, such as `@@HOMEBREW_PREFIX@@`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Placeholder::Prefix => "@@HOMEBREW_PREFIX@@",
+            Placeholder::Cellar => "@@HOMEBREW_CELLAR@@",
+            Placeholder::Repository => "@@HOMEBREW_REPOSITORY@@",
+            Placeholder::Library => "@@HOMEBREW_LIBRARY@@",
+            Placeholder::Perl => "@@HOMEBREW_PERL@@",
+            Placeholder::Java => "@@HOMEBREW_JAVA@@",
+        }
+    }
+}
+
+/// The kind of an entry in a bottle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    /// A symlink, and its target.
+    Symlink(PathBuf),
+    /// A hard link, and the path in the bottle it links to.
+    HardLink(PathBuf),
+}
+
+/// A file, directory or link in a bottle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BottleEntry {
+    /// The path relative to the keg, such as `bin/jq`.
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub mode: u32,
+    /// The placeholders the file contains, which must be replaced before
+    /// it can be used.
+    pub placeholders: Vec<Placeholder>,
+}
+
+/// The contents of a bottle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BottleContents {
+    pub name: String,
+    pub version: String,
+    pub entries: Vec<BottleEntry>,
+}
+
+impl BottleContents {
+    /// Checks if any file needs placeholders replaced when poured.
+    pub fn needs_relocation(&self) -> bool {
+        self.entries.iter().any(|e| !e.placeholders.is_empty())
+    }
+
+    /// The entries that contain placeholders.
+    pub fn relocatable(&self) -> impl Iterator<Item = &BottleEntry> {
+        self.entries.iter().filter(|e| !e.placeholders.is_empty())
+    }
+}
+
+/// Lists the contents of the bottle tarball at `path`.
+///
+/// This reads every file to find placeholders, so takes about as long as
+/// extracting the bottle.
+pub fn list<P: AsRef<Path>>(path: P) -> Result<BottleContents> {
+    let path = path.as_ref();
+    let mut archive = tar::Archive::new(GzDecoder::new(std::fs::File::open(path)?));
+    let mut keg: Option<(String, String)> = None;
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let full = entry.path()?.into_owned();
+        let (name, version, relative) = match split_keg_path(&full) {
+            Some(parts) => parts,
+            // The directory named after the formula, above the keg.
+            None if full.components().count() <= 2 && entry.header().entry_type().is_dir() => {
+                continue
+            }
+            None => return Err(Error::InvalidBottle(path.to_owned())),
+        };
+        match &keg {
+            Some((n, v)) if *n != name || *v != version => {
+                return Err(Error::InvalidBottle(path.to_owned()))
+            }
+            Some(_) => {}
+            None => keg = Some((name, version)),
+        }
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        let header = entry.header();
+        let entry_type = header.entry_type();
+        let size = header.size()?;
+        let mode = header.mode()?;
+        let kind = if entry_type.is_dir() {
+            EntryKind::Directory
+        } else if entry_type.is_symlink() {
+            EntryKind::Symlink(
+                entry
+                    .link_name()?
+                    .map(|l| l.into_owned())
+                    .unwrap_or_default(),
+            )
+        } else if entry_type.is_hard_link() {
+            let target = entry
+                .link_name()?
+                .map(|l| l.into_owned())
+                .unwrap_or_default();
+            EntryKind::HardLink(match split_keg_path(&target) {
+                Some((_, _, relative)) => relative,
+                None => target,
+            })
+        } else {
+            EntryKind::File
+        };
+        let placeholders = if kind == EntryKind::File {
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents)?;
+            placeholders_in(&contents)
+        } else {
+            Vec::new()
+        };
+        entries.push(BottleEntry {
+            path: relative,
+            kind,
+            size,
+            mode,
+            placeholders,
+        });
+    }
+    match keg {
+        Some((name, version)) => Ok(BottleContents {
+            name,
+            version,
+            entries,
+        }),
+        None => Err(Error::InvalidBottle(path.to_owned())),
+    }
+}
+
+/// Extracts the bottle tarball at `path` into `dir`, such as a Cellar,
+/// returning the path of the extracted keg.
+///
+/// Placeholders are left as they are.
+pub fn extract<P: AsRef<Path>, D: AsRef<Path>>(path: P, dir: D) -> Result<PathBuf> {
+    let path = path.as_ref();
+    let dir = dir.as_ref();
+    let contents = list(path)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(std::fs::File::open(path)?));
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+    archive.unpack(dir)?;
+    Ok(dir.join(contents.name).join(contents.version))
+}
+
+/// Splits a path in a bottle into the formula name, version and the path
+/// relative to the keg.
+fn split_keg_path(path: &Path) -> Option<(String, String, PathBuf)> {
+    let mut components = path
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir));
+    let name = match components.next()? {
+        Component::Normal(name) => name.to_str()?.to_owned(),
+        _ => return None,
+    };
+    let version = match components.next()? {
+        Component::Normal(version) => version.to_str()?.to_owned(),
+        _ => return None,
+    };
+    let relative: PathBuf = components.collect();
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some((name, version, relative))
+}
+
+/// The placeholders that appear in `contents`.
+fn placeholders_in(contents: &[u8]) -> Vec<Placeholder> {
+    Placeholder::ALL
+        .iter()
+        .copied()
+        .filter(|p| {
+            let needle = p.as_str().as_bytes();
+            contents.windows(needle.len()).any(|w| w == needle)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_keg_paths() {
+        assert_eq!(
+            split_keg_path(Path::new("./jq/1.7.1/bin/jq")),
+            Some(("jq".to_owned(), "1.7.1".to_owned(), PathBuf::from("bin/jq")))
+        );
+        assert_eq!(split_keg_path(Path::new("jq/1.7.1/../../etc")), None);
+        assert_eq!(split_keg_path(Path::new("jq")), None);
+    }
+
+    #[test]
+    fn finds_placeholders() {
+        assert_eq!(
+            placeholders_in(b"prefix=@@HOMEBREW_PREFIX@@\nlibdir=@@HOMEBREW_CELLAR@@/jq"),
+            vec![Placeholder::Prefix, Placeholder::Cellar]
+        );
+        assert!(placeholders_in(b"\x7fELF").is_empty());
+    }
+}
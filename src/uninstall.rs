@@ -0,0 +1,255 @@
+//! Uninstalling many formulae at once.
+//!
+//! ``` no_run
+//! # fn main() -> brew::Result<()> {
+//! use brew::uninstall::{uninstall_many, UninstallOptions, UninstallOutcome};
+//!
+//! let names = ["php@8.1", "php@8.2"];
+//! for (name, outcome) in uninstall_many(&names, &UninstallOptions::new())? {
+//!     if let UninstallOutcome::Blocked { dependents } = outcome {
+//!         eprintln!("{} is needed by {}", name, dependents.join(", "));
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::cask::casks;
+use crate::command::brew;
+use crate::receipt::{receipts, Receipt};
+use crate::search::split_tap;
+use crate::{brew_error, cellar, prefix, Error, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+/// Represents how to uninstall formulae.
+#[derive(Clone, Debug, Default)]
+pub struct UninstallOptions {
+    force: bool,
+    ignore_dependencies: bool,
+}
+
+impl UninstallOptions {
+    /// Represents no options added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the `--force` flag, removing every installed version.
+    pub fn force(mut self) -> Self {
+        self.force = true;
+        self
+    }
+
+    /// Adds the `--ignore-dependencies` flag, uninstalling formulae even if
+    /// installed formulae depend on them.
+    pub fn ignore_dependencies(mut self) -> Self {
+        self.ignore_dependencies = true;
+        self
+    }
+
+    fn brew_options(&self) -> Vec<&'static str> {
+        let mut out = Vec::new();
+        if self.force {
+            out.push("--force");
+        }
+        if self.ignore_dependencies {
+            out.push("--ignore-dependencies");
+        }
+        out
+    }
+}
+
+/// What happened to a formula passed to `uninstall_many`.
+#[derive(Debug)]
+pub enum UninstallOutcome {
+    Uninstalled,
+    NotInstalled,
+    /// The formula was not uninstalled because installed formulae that are
+    /// not being uninstalled depend on it.
+    Blocked {
+        dependents: Vec<String>,
+    },
+    Failed(Error),
+}
+
+/// Uninstalls `names` with a single `brew uninstall`, returning what
+/// happened to each, in the order given.
+///
+/// brew refuses to uninstall anything if one of the formulae is needed by
+/// another installed formula or cask, so those are found first, from
+/// install receipts and the installed casks, reported as
+/// `UninstallOutcome::Blocked`, and left out.
+pub fn uninstall_many(
+    names: &[&str],
+    options: &UninstallOptions,
+) -> Result<Vec<(String, UninstallOutcome)>> {
+    let cellar = cellar()?;
+    let kegs = |name: &str| -> Vec<PathBuf> {
+        std::fs::read_dir(cellar.join(split_tap(name).1))
+            .map(|entries| entries.filter_map(|e| Some(e.ok()?.path())).collect())
+            .unwrap_or_default()
+    };
+    let installed_kegs: HashMap<&str, Vec<PathBuf>> = names
+        .iter()
+        .map(|&name| (name, kegs(name)))
+        .filter(|(_, kegs)| !kegs.is_empty())
+        .collect();
+    let installed: Vec<&str> = names
+        .iter()
+        .copied()
+        .filter(|name| installed_kegs.contains_key(name))
+        .collect();
+    let blocked = if options.ignore_dependencies || installed.is_empty() {
+        HashMap::new()
+    } else {
+        blocked(&receipts()?, &cask_dependencies()?, &installed)
+    };
+    let mut args = vec!["uninstall", "--formula"];
+    args.extend(options.brew_options());
+    let before = args.len();
+    args.extend(
+        installed
+            .iter()
+            .filter(|name| !blocked.contains_key(split_tap(name).1)),
+    );
+    let output = if args.len() > before {
+        Some(brew(&args)?)
+    } else {
+        None
+    };
+    Ok(names
+        .iter()
+        .map(|&name| {
+            let outcome = if !installed.contains(&name) {
+                UninstallOutcome::NotInstalled
+            } else if let Some(dependents) = blocked.get(split_tap(name).1) {
+                UninstallOutcome::Blocked {
+                    dependents: dependents.clone(),
+                }
+            } else {
+                match &output {
+                    // Even if brew failed on another formula, this one was
+                    // uninstalled if its kegs were removed.
+                    Some(output)
+                        if output.success()
+                            || installed_kegs[name].iter().any(|keg| !keg.exists()) =>
+                    {
+                        UninstallOutcome::Uninstalled
+                    }
+                    Some(output) => {
+                        UninstallOutcome::Failed(brew_error(output, Error::UnknownError))
+                    }
+                    None => UninstallOutcome::NotInstalled,
+                }
+            };
+            (name.to_owned(), outcome)
+        })
+        .collect())
+}
+
+/// The formulae each installed cask depends on, by token, without spawning
+/// brew if no casks are installed.
+fn cask_dependencies() -> Result<HashMap<String, Vec<String>>> {
+    let caskroom = prefix()?.join("Caskroom");
+    let any = std::fs::read_dir(caskroom).is_ok_and(|mut entries| entries.next().is_some());
+    if !any {
+        return Ok(HashMap::new());
+    }
+    Ok(casks(&["--installed"])?
+        .into_iter()
+        .map(|cask| (cask.token, cask.depends_on.formula))
+        .collect())
+}
+
+/// The formulae of `names` that installed formulae, or the casks in `casks`,
+/// depend on, with their dependents.
+///
+/// A formula is only needed by another being uninstalled if that one is
+/// itself blocked.
+fn blocked(
+    receipts: &HashMap<String, Receipt>,
+    casks: &HashMap<String, Vec<String>>,
+    names: &[&str],
+) -> HashMap<String, Vec<String>> {
+    let removing: HashSet<&str> = names.iter().map(|n| split_tap(n).1).collect();
+    let mut blocked: HashMap<String, Vec<String>> = HashMap::new();
+    loop {
+        let remaining = |name: &str| !removing.contains(name) || blocked.contains_key(name);
+        let mut found = HashMap::new();
+        for &name in &removing {
+            let needs = |d: &str| split_tap(d).1 == name;
+            let formulae = receipts
+                .iter()
+                .filter(|(dependent, _)| remaining(dependent))
+                .filter(|(_, receipt)| {
+                    receipt
+                        .runtime_dependencies
+                        .iter()
+                        .flatten()
+                        .any(|d| needs(&d.full_name))
+                })
+                .map(|(dependent, _)| dependent.clone());
+            let casks = casks
+                .iter()
+                .filter(|(_, formulae)| formulae.iter().any(|f| needs(f)))
+                .map(|(token, _)| token.clone());
+            let mut dependents: Vec<String> = formulae.chain(casks).collect();
+            if !dependents.is_empty() {
+                dependents.sort();
+                found.insert(name.to_owned(), dependents);
+            }
+        }
+        if found.len() == blocked.len() {
+            return found;
+        }
+        blocked = found;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt(deps: &[&str]) -> Receipt {
+        serde_json::from_value(serde_json::json!({
+            "installed_on_request": true,
+            "installed_as_dependency": false,
+            "runtime_dependencies": deps
+                .iter()
+                .map(|d| serde_json::json!({ "full_name": d, "version": "1.0" }))
+                .collect::<Vec<_>>(),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn finds_blocked_formulae() {
+        let mut receipts = HashMap::new();
+        receipts.insert("php@8.1".to_owned(), receipt(&["icu4c"]));
+        receipts.insert("composer".to_owned(), receipt(&["php@8.2"]));
+        receipts.insert("php@8.2".to_owned(), receipt(&["icu4c"]));
+        receipts.insert("icu4c".to_owned(), receipt(&[]));
+
+        // composer keeps php@8.2, which keeps icu4c.
+        let no_casks = HashMap::new();
+        let kept = blocked(&receipts, &no_casks, &["php@8.1", "php@8.2", "icu4c"]);
+        assert_eq!(kept["php@8.2"], vec!["composer"]);
+        assert_eq!(kept["icu4c"], vec!["php@8.2"]);
+        assert!(!kept.contains_key("php@8.1"));
+
+        assert!(blocked(&receipts, &no_casks, &["php@8.1", "composer", "php@8.2"]).is_empty());
+    }
+
+    #[test]
+    fn finds_formulae_casks_need() {
+        let mut receipts = HashMap::new();
+        receipts.insert("php@8.1".to_owned(), receipt(&[]));
+        let mut casks = HashMap::new();
+        casks.insert(
+            "php-monitor".to_owned(),
+            vec!["homebrew/core/php@8.1".to_owned()],
+        );
+        let kept = blocked(&receipts, &casks, &["php@8.1"]);
+        assert_eq!(kept["php@8.1"], vec!["php-monitor"]);
+    }
+}
@@ -0,0 +1,235 @@
+//! Whether packages can be upgraded, and what upgrading an outdated package
+//! would change.
+use crate::deps::DepsOptions;
+use crate::platform::current_platform;
+use crate::Package;
+
+/// How the availability of a bottle for this platform changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BottleChange {
+    Unchanged,
+    /// The installed version was built from source, but the new version has
+    /// a bottle.
+    Added,
+    /// The installed version was poured from a bottle, but the new version
+    /// has none, so will be built from source.
+    Removed,
+}
+
+/// What kind of change an upgrade brings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpgradeKind {
+    /// A new upstream version.
+    Version,
+    /// The same version with a new revision, such as rebuilding against a
+    /// new openssl.
+    Revision,
+    /// The same version and revision, with a rebuilt bottle.
+    Rebuild,
+}
+
+impl UpgradeKind {
+    /// The kind of upgrade from `old` to `new`, both with their revisions,
+    /// such as `3.1.4_1`.
+    pub fn between(old: &str, new: &str) -> UpgradeKind {
+        let (old_version, old_revision) = split_revision(old);
+        let (new_version, new_revision) = split_revision(new);
+        if old_version != new_version {
+            UpgradeKind::Version
+        } else if old_revision != new_revision {
+            UpgradeKind::Revision
+        } else {
+            UpgradeKind::Rebuild
+        }
+    }
+}
+
+/// What upgrading a package would change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UpgradeSummary {
+    /// The installed version, with its revision, such as `3.1.4_1`.
+    pub old_version: String,
+    /// The version the upgrade installs, with its revision.
+    pub new_version: String,
+    pub kind: UpgradeKind,
+    pub bottle: BottleChange,
+    /// Runtime dependencies of the new version that the installed one does
+    /// not have.
+    pub new_dependencies: Vec<String>,
+}
+
+impl Package {
+    /// Summarizes what upgrading the package would change, or `None` if it
+    /// is not installed or not outdated.
+    pub fn pending_upgrade_summary(&self) -> Option<UpgradeSummary> {
+        if !self.outdated {
+            return None;
+        }
+        let installed = self.installed.last()?;
+        let old_version = installed.version.original().to_owned();
+        let new_version = self.pkg_version()?;
+        let platform = current_platform();
+        let bottle = match (
+            installed.poured_from_bottle,
+            self.bottle_for(&platform).is_some(),
+        ) {
+            (false, true) => BottleChange::Added,
+            (true, false) => BottleChange::Removed,
+            _ => BottleChange::Unchanged,
+        };
+        let new_dependencies = self
+            .dependencies_for(&platform, &DepsOptions::new())
+            .ok()?
+            .into_iter()
+            .filter(|dep| {
+                !installed
+                    .runtime_dependencies
+                    .iter()
+                    .any(|d| d.full_name.rsplit('/').next() == Some(dep))
+            })
+            .map(str::to_owned)
+            .collect();
+        Some(UpgradeSummary {
+            kind: UpgradeKind::between(&old_version, &new_version),
+            old_version,
+            new_version,
+            bottle,
+            new_dependencies,
+        })
+    }
+}
+
+/// Whether an installed package can be upgraded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UpgradeStatus {
+    UpToDate,
+    /// A newer version is available. Both versions have their revisions,
+    /// such as `3.1.4_1`.
+    Outdated {
+        from: String,
+        to: String,
+    },
+    /// A newer version is available, but the package is pinned to the
+    /// installed one.
+    Pinned {
+        latest: String,
+    },
+    /// The package was installed from HEAD, or only has a HEAD version, so
+    /// whether it is outdated can't be known without fetching its source.
+    HeadOnly,
+    NotInstalled,
+}
+
+impl Package {
+    /// Whether the package can be upgraded, worked out from its info without
+    /// running brew.
+    ///
+    /// The info is only as recent as the last `brew update`.
+    pub fn upgrade_status(&self) -> UpgradeStatus {
+        let installed: Vec<&str> = self
+            .installed
+            .iter()
+            .map(|i| i.version.original())
+            .collect();
+        let current = self.pkg_version().filter(|v| !v.is_empty());
+        upgrade_status(&installed, current.as_deref(), self.outdated, self.pinned)
+    }
+
+    /// The current stable version, with its revision, such as `3.1.4_1`, or
+    /// `None` if the formula is HEAD-only.
+    pub(crate) fn pkg_version(&self) -> Option<String> {
+        let stable = self.versions.stable.as_ref()?.original();
+        Some(match self.revision {
+            0 => stable.to_owned(),
+            revision => format!("{}_{}", stable, revision),
+        })
+    }
+}
+
+/// The upgrade status of a package with the `installed` versions, whose
+/// current stable version is `current`, if it has one.
+fn upgrade_status(
+    installed: &[&str],
+    current: Option<&str>,
+    outdated: bool,
+    pinned: bool,
+) -> UpgradeStatus {
+    let latest_installed = match installed.last() {
+        Some(version) => *version,
+        None => return UpgradeStatus::NotInstalled,
+    };
+    let current = match current {
+        Some(current) if !latest_installed.starts_with("HEAD") => current,
+        _ => return UpgradeStatus::HeadOnly,
+    };
+    // brew also reports packages whose bottle was rebuilt as outdated.
+    if !outdated && installed.contains(&current) {
+        UpgradeStatus::UpToDate
+    } else if pinned {
+        UpgradeStatus::Pinned {
+            latest: current.to_owned(),
+        }
+    } else {
+        UpgradeStatus::Outdated {
+            from: latest_installed.to_owned(),
+            to: current.to_owned(),
+        }
+    }
+}
+
+/// Splits a version such as `3.1.4_1` into the version and its revision.
+pub(crate) fn split_revision(version: &str) -> (&str, usize) {
+    version
+        .rsplit_once('_')
+        .and_then(|(v, revision)| Some((v, revision.parse().ok()?)))
+        .unwrap_or((version, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn works_out_upgrade_status() {
+        assert_eq!(
+            upgrade_status(&[], Some("1.7.1"), false, false),
+            UpgradeStatus::NotInstalled
+        );
+        assert_eq!(
+            upgrade_status(&["1.7.1"], Some("1.7.1"), false, false),
+            UpgradeStatus::UpToDate
+        );
+        assert_eq!(
+            upgrade_status(&["1.6", "1.7.1"], Some("1.7.1_1"), false, false),
+            UpgradeStatus::Outdated {
+                from: "1.7.1".to_owned(),
+                to: "1.7.1_1".to_owned()
+            }
+        );
+        assert_eq!(
+            upgrade_status(&["1.7.1"], Some("1.7.1"), true, true),
+            UpgradeStatus::Pinned {
+                latest: "1.7.1".to_owned()
+            }
+        );
+        assert_eq!(
+            upgrade_status(&["HEAD-8f3e6c1"], Some("1.7.1"), false, false),
+            UpgradeStatus::HeadOnly
+        );
+    }
+
+    #[test]
+    fn splits_revisions() {
+        assert_eq!(split_revision("3.1.4_1"), ("3.1.4", 1));
+        assert_eq!(split_revision("1.7.1"), ("1.7.1", 0));
+        assert_eq!(split_revision("2023_beta"), ("2023_beta", 0));
+        assert_eq!(
+            UpgradeKind::between("3.1.4_1", "3.1.4_2"),
+            UpgradeKind::Revision
+        );
+        assert_eq!(
+            UpgradeKind::between("3.1.4_1", "3.2.0"),
+            UpgradeKind::Version
+        );
+    }
+}
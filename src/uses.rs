@@ -0,0 +1,124 @@
+//! Finding the formulae that depend on a formula, via `brew uses`.
+use crate::command::brew;
+use crate::{brew_error, Error, Package, Result};
+
+/// Represents command line options with which to find dependents.
+///
+/// Only installed formulae are searched unless `all` is set.
+#[derive(Clone, Debug, Default)]
+pub struct UsesOptions {
+    recursive: bool,
+    include_build: bool,
+    include_test: bool,
+    include_optional: bool,
+    skip_recommended: bool,
+    all: bool,
+}
+
+impl UsesOptions {
+    /// Represents no options added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the `--recursive` flag, finding formulae that depend on the
+    /// formula through other dependencies too.
+    pub fn recursive(mut self) -> Self {
+        self.recursive = true;
+        self
+    }
+
+    /// Adds the `--include-build` flag.
+    pub fn include_build(mut self) -> Self {
+        self.include_build = true;
+        self
+    }
+
+    /// Adds the `--include-test` flag.
+    pub fn include_test(mut self) -> Self {
+        self.include_test = true;
+        self
+    }
+
+    /// Adds the `--include-optional` flag.
+    pub fn include_optional(mut self) -> Self {
+        self.include_optional = true;
+        self
+    }
+
+    /// Adds the `--skip-recommended` flag.
+    pub fn skip_recommended(mut self) -> Self {
+        self.skip_recommended = true;
+        self
+    }
+
+    /// Adds the `--eval-all` flag, searching every formula instead of only
+    /// installed ones, which is slow.
+    pub fn all(mut self) -> Self {
+        self.all = true;
+        self
+    }
+
+    fn brew_options(&self) -> Vec<&str> {
+        let mut out = vec![if self.all {
+            "--eval-all"
+        } else {
+            "--installed"
+        }];
+        if self.recursive {
+            out.push("--recursive")
+        }
+        if self.include_build {
+            out.push("--include-build")
+        }
+        if self.include_test {
+            out.push("--include-test")
+        }
+        if self.include_optional {
+            out.push("--include-optional")
+        }
+        if self.skip_recommended {
+            out.push("--skip-recommended")
+        }
+        out
+    }
+}
+
+impl Package {
+    /// The names of the formulae that depend on this one, sorted.
+    pub fn uses(&self, options: &UsesOptions) -> Result<Vec<String>> {
+        let mut args = vec!["uses"];
+        args.extend(options.brew_options());
+        args.push(&self.full_name);
+        let output = brew(&args)?;
+        if output.success() {
+            let mut names: Vec<String> = output
+                .stdout()
+                .split_whitespace()
+                .map(str::to_owned)
+                .collect();
+            names.sort();
+            Ok(names)
+        } else {
+            Err(brew_error(&output, Error::UnknownError))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_flags() {
+        assert_eq!(UsesOptions::new().brew_options(), vec!["--installed"]);
+        assert_eq!(
+            UsesOptions::new()
+                .all()
+                .recursive()
+                .include_build()
+                .brew_options(),
+            vec!["--eval-all", "--recursive", "--include-build"]
+        );
+    }
+}